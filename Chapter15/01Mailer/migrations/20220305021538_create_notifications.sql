@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS notifications
+(
+    uuid              UUID PRIMARY KEY,
+    user_uuid         UUID NOT NULL,
+    actor_uuid        UUID,
+    notification_type INTEGER NOT NULL,
+    message           VARCHAR NOT NULL,
+    link              VARCHAR,
+    read_at           TIMESTAMPTZ,
+    created_at        TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_uuid) REFERENCES "users" (uuid) ON DELETE CASCADE,
+    FOREIGN KEY (actor_uuid) REFERENCES "users" (uuid) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user_uuid_created_at_idx ON notifications (user_uuid, created_at DESC);
+CREATE INDEX IF NOT EXISTS notifications_unread_idx ON notifications (user_uuid) WHERE read_at IS NULL;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS jobs
+(
+    uuid       UUID PRIMARY KEY,
+    job_type   INTEGER NOT NULL,
+    payload    JSONB NOT NULL DEFAULT '{}',
+    status     INTEGER NOT NULL DEFAULT 0,
+    attempts   INTEGER NOT NULL DEFAULT 0,
+    run_at     TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    locked_at  TIMESTAMPTZ,
+    locked_by  VARCHAR,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS jobs_queued_run_at_idx ON jobs (run_at) WHERE status = 0;
+CREATE INDEX IF NOT EXISTS jobs_running_locked_at_idx ON jobs (locked_at) WHERE status = 1;
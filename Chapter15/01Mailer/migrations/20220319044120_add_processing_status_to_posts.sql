@@ -0,0 +1,9 @@
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS processing_status INTEGER NOT NULL DEFAULT 2;
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS processing_error TEXT;
+
+-- Videos still being converted by the old in-process worker have no job to
+-- resume, so they are marked failed and can be retried from the post page.
+UPDATE posts
+SET processing_status = 3, content = substring(content from 8),
+    processing_error = 'Processing was interrupted by an upgrade. Retry to process the video again.'
+WHERE content LIKE 'loading/%';
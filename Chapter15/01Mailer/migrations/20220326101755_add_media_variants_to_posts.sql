@@ -0,0 +1,3 @@
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS variants JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS poster VARCHAR;
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS duration DOUBLE PRECISION;
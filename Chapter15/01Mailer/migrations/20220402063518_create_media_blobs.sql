@@ -0,0 +1,21 @@
+ALTER TABLE posts DROP CONSTRAINT IF EXISTS posts_content_key;
+
+CREATE TABLE IF NOT EXISTS media_blobs
+(
+    hash       VARCHAR(64) PRIMARY KEY,
+    key        VARCHAR NOT NULL,
+    size       BIGINT NOT NULL,
+    ref_count  INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS post_blobs
+(
+    post_uuid UUID NOT NULL,
+    blob_hash VARCHAR(64) NOT NULL,
+    PRIMARY KEY (post_uuid, blob_hash),
+    FOREIGN KEY (post_uuid) REFERENCES posts (uuid) ON DELETE CASCADE,
+    FOREIGN KEY (blob_hash) REFERENCES media_blobs (hash)
+);
+
+CREATE INDEX IF NOT EXISTS post_blobs_blob_hash_idx ON post_blobs (blob_hash);
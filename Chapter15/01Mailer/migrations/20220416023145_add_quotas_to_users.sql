@@ -0,0 +1,15 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS storage_quota BIGINT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS daily_post_limit INTEGER;
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS media_size BIGINT NOT NULL DEFAULT 0;
+
+UPDATE posts
+SET media_size = sizes.media_size
+FROM (
+    SELECT post_blobs.post_uuid, SUM(media_blobs.size) AS media_size
+    FROM post_blobs JOIN media_blobs ON media_blobs.hash = post_blobs.blob_hash
+    GROUP BY post_blobs.post_uuid
+) AS sizes
+WHERE posts.uuid = sizes.post_uuid;
+
+CREATE INDEX IF NOT EXISTS posts_user_uuid_created_at_idx ON posts (user_uuid, created_at);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS resumable_uploads
+(
+    uuid          UUID PRIMARY KEY,
+    user_uuid     UUID NOT NULL,
+    filename      VARCHAR,
+    content_type  VARCHAR NOT NULL,
+    upload_length BIGINT NOT NULL,
+    upload_offset BIGINT NOT NULL DEFAULT 0,
+    post_uuid     UUID,
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at    TIMESTAMPTZ NOT NULL,
+    FOREIGN KEY (user_uuid) REFERENCES "users" (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS resumable_uploads_expires_at_idx ON resumable_uploads (expires_at);
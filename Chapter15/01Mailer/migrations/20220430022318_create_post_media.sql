@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS post_media
+(
+    uuid              UUID PRIMARY KEY,
+    post_uuid         UUID NOT NULL,
+    position          INTEGER NOT NULL,
+    media_type        INTEGER NOT NULL,
+    content           VARCHAR NOT NULL,
+    variants          JSONB NOT NULL DEFAULT '[]',
+    poster            VARCHAR,
+    duration          DOUBLE PRECISION,
+    processing_status INTEGER NOT NULL DEFAULT 2,
+    processing_error  TEXT,
+    media_size        BIGINT NOT NULL DEFAULT 0,
+    blob_hashes       VARCHAR(64)[] NOT NULL DEFAULT '{}',
+    created_at        TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (post_uuid) REFERENCES posts (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS post_media_post_uuid_position_idx ON post_media (post_uuid, position);
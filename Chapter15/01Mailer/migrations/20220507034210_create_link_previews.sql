@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS link_previews
+(
+    url         VARCHAR PRIMARY KEY,
+    status      INTEGER NOT NULL DEFAULT 0,
+    title       VARCHAR,
+    description TEXT,
+    image_url   VARCHAR,
+    site_name   VARCHAR,
+    source      VARCHAR,
+    error       TEXT,
+    fetched_at  TIMESTAMPTZ,
+    expires_at  TIMESTAMPTZ NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS link_url VARCHAR;
+
+CREATE INDEX IF NOT EXISTS posts_link_url_idx ON posts (link_url) WHERE link_url IS NOT NULL;
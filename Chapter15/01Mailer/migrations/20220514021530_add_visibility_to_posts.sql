@@ -0,0 +1,5 @@
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS visibility INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE resumable_uploads ADD COLUMN IF NOT EXISTS visibility INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS posts_user_uuid_visibility_created_at_idx ON posts (user_uuid, visibility, created_at DESC);
@@ -0,0 +1,7 @@
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS publish_status INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS publish_at TIMESTAMPTZ;
+
+ALTER TABLE resumable_uploads ADD COLUMN IF NOT EXISTS publish_status INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE resumable_uploads ADD COLUMN IF NOT EXISTS publish_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS posts_unpublished_idx ON posts (user_uuid, publish_status) WHERE publish_status <> 0;
@@ -0,0 +1,3 @@
+ALTER TABLE posts DROP CONSTRAINT IF EXISTS posts_user_uuid_fkey;
+ALTER TABLE posts
+    ADD CONSTRAINT posts_user_uuid_fkey FOREIGN KEY (user_uuid) REFERENCES "users" (uuid) ON DELETE CASCADE;
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS exports
+(
+    uuid         UUID PRIMARY KEY,
+    user_uuid    UUID NOT NULL,
+    status       INTEGER NOT NULL DEFAULT 0,
+    storage_key  VARCHAR,
+    size         BIGINT NOT NULL DEFAULT 0,
+    error        TEXT,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    completed_at TIMESTAMPTZ,
+    expires_at   TIMESTAMPTZ,
+    FOREIGN KEY (user_uuid) REFERENCES "users" (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS exports_user_uuid_idx ON exports (user_uuid, created_at);
+CREATE INDEX IF NOT EXISTS exports_expires_at_idx ON exports (expires_at) WHERE expires_at IS NOT NULL;
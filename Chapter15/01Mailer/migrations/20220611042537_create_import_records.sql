@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS import_records
+(
+    source      VARCHAR NOT NULL,
+    record_key  VARCHAR NOT NULL,
+    status      INTEGER NOT NULL,
+    target_uuid UUID,
+    error       TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (source, record_key)
+);
@@ -0,0 +1,53 @@
+use our_application::{import_archive, Config};
+use std::path::PathBuf;
+use std::process::exit;
+
+const USAGE: &str = "Usage: import_archive [--dry-run] [--resume] <archive.zip>";
+
+#[rocket::main]
+async fn main() {
+    let mut dry_run = false;
+    let mut resume = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--resume" => resume = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if !arg.starts_with('-') && path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    let config: Config = rocket::Config::figment()
+        .extract()
+        .expect("Incorrect Rocket.toml configuration");
+    match import_archive(&config, &path, resume, dry_run).await {
+        Ok(report) => {
+            for result in report.results.iter() {
+                println!("{}", result);
+            }
+            println!("{}", report);
+            if report.failed() > 0 {
+                exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use our_application::{collect_media_garbage, Config};
+use std::process::exit;
+
+const USAGE: &str = "Usage: media_gc [--dry-run] [--grace-period <seconds>]";
+
+#[rocket::main]
+async fn main() {
+    let mut dry_run = false;
+    let mut grace_period = None;
+    let mut args = std::env::args()
+        .skip(1)
+        .collect::<Vec<String>>()
+        .into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--grace-period" => match args.next().and_then(|value| value.parse().ok()) {
+                Some(seconds) => grace_period = Some(seconds),
+                None => {
+                    eprintln!("{}", USAGE);
+                    exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                exit(2);
+            }
+        }
+    }
+
+    let config: Config = rocket::Config::figment()
+        .extract()
+        .expect("Incorrect Rocket.toml configuration");
+    match collect_media_garbage(&config, dry_run, grace_period).await {
+        Ok(Some(report)) => {
+            let verb = if dry_run { "would remove" } else { "removed" };
+            for key in report.blobs.iter() {
+                println!("{} blob {}", verb, key);
+            }
+            for key in report.objects.iter() {
+                println!("{} file {}", verb, key);
+            }
+            for path in report.staged.iter() {
+                println!("{} upload {}", verb, path);
+            }
+            println!("{}", report);
+        }
+        Ok(None) => {
+            eprintln!("Another media garbage collection is already running");
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("Media garbage collection failed: {}", e);
+            exit(1);
+        }
+    }
+}
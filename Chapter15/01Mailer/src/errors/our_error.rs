@@ -68,6 +68,18 @@ impl OurError {
         Self::new_error_with_status(Status::InternalServerError, message, debug)
     }
 
+    pub fn new_payload_too_large_error(message: String, debug: Option<Box<dyn Error>>) -> Self {
+        Self::new_error_with_status(Status::PayloadTooLarge, message, debug)
+    }
+
+    pub fn new_too_many_requests_error(message: String, debug: Option<Box<dyn Error>>) -> Self {
+        Self::new_error_with_status(Status::TooManyRequests, message, debug)
+    }
+
+    pub fn new_service_unavailable_error(message: String, debug: Option<Box<dyn Error>>) -> Self {
+        Self::new_error_with_status(Status::ServiceUnavailable, message, debug)
+    }
+
     pub fn new_unauthorized_error(debug: Option<Box<dyn Error>>) -> Self {
         Self::new_error_with_status(Status::Unauthorized, String::from("unauthorized"), debug)
     }
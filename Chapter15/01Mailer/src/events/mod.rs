@@ -0,0 +1,152 @@
+use crate::models::notification::{find_mentions, Notification, NotificationEvent};
+use crate::models::post::Post;
+use crate::models::post_type::PostType;
+use crate::models::user::User;
+use crate::models::visibility::Visibility;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::PgConnection;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use uuid::Uuid;
+
+const HUB_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    VideoProcessed {
+        user_uuid: Uuid,
+        post_uuid: Uuid,
+        post_html: String,
+    },
+    VideoFailed {
+        user_uuid: Uuid,
+        post_uuid: Uuid,
+        post_html: String,
+    },
+    Notification {
+        user_uuid: Uuid,
+        notification_uuid: Uuid,
+        message: String,
+        link: Option<String>,
+        unread_count: i64,
+    },
+    NewPost {
+        user_uuid: Uuid,
+        post_uuid: Uuid,
+        post_html: String,
+        visibility: Visibility,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Event::VideoProcessed { .. } => "video_processed",
+            Event::VideoFailed { .. } => "video_failed",
+            Event::Notification { .. } => "notification",
+            Event::NewPost { .. } => "new_post",
+        }
+    }
+
+    pub fn is_for(&self, user_uuid: &Uuid, watched: &[Uuid]) -> bool {
+        match self {
+            Event::NewPost {
+                user_uuid: author,
+                visibility,
+                ..
+            } => author == user_uuid || (visibility.is_listed() && watched.contains(author)),
+            Event::VideoProcessed {
+                user_uuid: owner, ..
+            }
+            | Event::VideoFailed {
+                user_uuid: owner, ..
+            }
+            | Event::Notification {
+                user_uuid: owner, ..
+            } => owner == user_uuid,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventHub {
+    sender: Sender<Event>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(HUB_CAPACITY);
+        EventHub { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+pub async fn notify<'a>(
+    connection: &mut PgConnection,
+    hub: &EventHub,
+    event: NotificationEvent<'a>,
+) {
+    let notification = match Notification::record(connection, event).await {
+        Ok(notification) => notification,
+        Err(_) => return,
+    };
+    let unread_count = Notification::count_unread(connection, &notification.user_uuid)
+        .await
+        .unwrap_or(0);
+    hub.publish(Event::Notification {
+        user_uuid: notification.user_uuid,
+        notification_uuid: notification.uuid,
+        message: notification.message,
+        link: notification.link,
+        unread_count,
+    });
+}
+
+pub async fn notify_mentions(connection: &mut PgConnection, hub: &EventHub, post: &Post) {
+    if post.post_type != PostType::Text || !post.is_published() || post.visibility.is_restricted()
+    {
+        return;
+    }
+    let usernames = find_mentions(&post.content);
+    if usernames.is_empty() {
+        return;
+    }
+    let author = match User::find(connection, &post.user_uuid.to_string()).await {
+        Ok(author) => author,
+        Err(_) => return,
+    };
+    let mentioned = User::find_by_usernames(connection, &usernames)
+        .await
+        .unwrap_or_default();
+    for user in mentioned.iter().filter(|user| user.uuid != author.uuid) {
+        notify(
+            connection,
+            hub,
+            NotificationEvent::Mention {
+                user_uuid: &user.uuid,
+                author: &author,
+                post,
+            },
+        )
+        .await;
+    }
+}
+
+pub fn publish_new_post(hub: &EventHub, post: &Post) {
+    if !post.is_published() {
+        return;
+    }
+    hub.publish(Event::NewPost {
+        user_uuid: post.user_uuid,
+        post_uuid: post.uuid,
+        post_html: post.to_show_post().post_html,
+        visibility: post.visibility,
+    });
+}
@@ -1,4 +1,5 @@
 use crate::fairings::db::DBConnection;
+use crate::models::notification::Notification;
 use crate::models::user::User;
 use crate::states::JWToken;
 use hmac::{Hmac, Mac};
@@ -15,6 +16,7 @@ pub const LOGIN_COOKIE_NAME: &str = "user_uuid";
 #[derive(Serialize)]
 pub struct CurrentUser {
     pub user: User,
+    pub unread_notifications: i64,
 }
 
 #[rocket::async_trait]
@@ -39,12 +41,17 @@ impl<'r> FromRequest<'r> for CurrentUser {
             return error;
         }
         let connection = parsed_connection.unwrap();
-        let found_user = User::find(connection, uuid).await;
-        if found_user.is_err() {
-            return error;
-        }
-        let user = found_user.unwrap();
-        Outcome::Success(CurrentUser { user })
+        let user = match User::find(connection, uuid).await {
+            Ok(user) => user,
+            Err(_) => return error,
+        };
+        let unread_notifications = Notification::count_unread(connection, &user.uuid)
+            .await
+            .unwrap_or(0);
+        Outcome::Success(CurrentUser {
+            user,
+            unread_notifications,
+        })
     }
 }
 
@@ -113,3 +120,47 @@ impl<'r> FromRequest<'r> for APIUser {
         Outcome::Success(APIUser { user })
     }
 }
+
+pub struct AuthenticatedUser {
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Outcome::Success(current_user) = req.guard::<CurrentUser>().await {
+            return Outcome::Success(AuthenticatedUser {
+                user: current_user.user,
+            });
+        }
+        req.guard::<APIUser>()
+            .await
+            .map(|api_user| AuthenticatedUser {
+                user: api_user.user,
+            })
+    }
+}
+
+pub struct AdminUser {
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.guard::<CurrentUser>().await {
+            Outcome::Success(current_user) if current_user.user.is_admin => {
+                Outcome::Success(AdminUser {
+                    user: current_user.user,
+                })
+            }
+            Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+            Outcome::Failure(e) => Outcome::Failure(e),
+            Outcome::Forward(f) => Outcome::Forward(f),
+        }
+    }
+}
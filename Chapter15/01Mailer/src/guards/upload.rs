@@ -0,0 +1,42 @@
+use crate::events::EventHub;
+use crate::media::photo::PhotoConfig;
+use crate::media::scan::ScanConfig;
+use crate::models::quota::QuotaConfig;
+use crate::storage::Storage;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+pub struct UploadPipeline<'r> {
+    pub hub: &'r EventHub,
+    pub photo: &'r PhotoConfig,
+    pub storage: &'r Storage,
+    pub quota: &'r QuotaConfig,
+    pub scan: &'r ScanConfig,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UploadPipeline<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let rocket = req.rocket();
+        match (
+            rocket.state::<EventHub>(),
+            rocket.state::<PhotoConfig>(),
+            rocket.state::<Storage>(),
+            rocket.state::<QuotaConfig>(),
+            rocket.state::<ScanConfig>(),
+        ) {
+            (Some(hub), Some(photo), Some(storage), Some(quota), Some(scan)) => {
+                Outcome::Success(UploadPipeline {
+                    hub,
+                    photo,
+                    storage,
+                    quota,
+                    scan,
+                })
+            }
+            _ => Outcome::Failure((Status::InternalServerError, ())),
+        }
+    }
+}
@@ -0,0 +1,579 @@
+//! Bulk import of users and posts from an archive.
+//!
+//! An import archive is a ZIP file with a `manifest.json` at its root:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "source": "legacy-site",
+//!   "users": [
+//!     {
+//!       "id": "42",
+//!       "username": "alice",
+//!       "email": "alice@example.com",
+//!       "password_hash": "$argon2id$v=19$...",
+//!       "description": "Hello",
+//!       "created_at": "2019-01-02T03:04:05Z",
+//!       "posts": [
+//!         { "id": "7", "content": "My first post", "created_at": "2019-01-03T00:00:00Z" },
+//!         { "id": "8", "files": ["media/8.jpg"], "visibility": "unlisted" },
+//!         { "id": "9", "files": ["media/9a.png", "media/9b.mp4"] }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! `source` names the system the archive comes from; together with the user and post `id`s it
+//! identifies every record, so an archive can be re-run with `--resume` without duplicating what
+//! was already imported. Post `id`s only need to be unique per user. Each record is created and
+//! marked as imported in one transaction. Users are validated with the `NewUser` rules and need either a
+//! `password` or an Argon2 `password_hash`. A post has either text `content` or one or more
+//! `files` stored in the archive; several files make an album. Files go through the regular
+//! upload pipeline, so media is scanned, checked against quotas and queued for processing.
+//! `created_at` is optional and kept as the original creation time.
+
+use crate::errors::our_error::OurError;
+use crate::guards::upload::UploadPipeline;
+use crate::models::import_record::ImportRecord;
+use crate::models::import_status::ImportStatus;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::post::{Post, PostSettings};
+use crate::models::publish_status::PublishStatus;
+use crate::models::user::{hash_password, NewUser, User};
+use crate::models::visibility::Visibility;
+use crate::routes::post::{post_type_for, save_staged_album, save_staged_upload};
+use crate::storage::content_type_for;
+use argon2::password_hash::PasswordHash;
+use chrono::offset::Utc;
+use rocket::form::{Form, ValueField};
+use rocket::http::ContentType;
+use rocket::serde::json::serde_json;
+use rocket::serde::Deserialize;
+use sqlx::{Acquire, PgConnection};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zip::ZipArchive;
+
+pub const MANIFEST: &str = "manifest.json";
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    pub source: String,
+    #[serde(default)]
+    pub users: Vec<ImportedUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImportedUser {
+    pub id: String,
+    pub username: String,
+    pub email: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub posts: Vec<ImportedPost>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImportedPost {
+    pub id: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub visibility: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+pub enum PostBody {
+    Text(String),
+    Files(Vec<(String, ContentType)>),
+}
+
+pub struct PostPlan {
+    pub body: PostBody,
+    pub visibility: Visibility,
+    pub created_at: Option<OurDateTime>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportOutcome {
+    Imported(Option<Uuid>),
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct ImportResult {
+    pub record: String,
+    pub outcome: ImportOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub results: Vec<ImportResult>,
+}
+
+impl ImportReport {
+    fn push(&mut self, record: &str, outcome: ImportOutcome) {
+        self.results.push(ImportResult {
+            record: String::from(record),
+            outcome,
+        });
+    }
+
+    fn count(&self, matches: fn(&ImportOutcome) -> bool) -> usize {
+        self.results
+            .iter()
+            .filter(|result| matches(&result.outcome))
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, ImportOutcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} records, skipped {}, failed {}",
+            if self.dry_run {
+                "Validated"
+            } else {
+                "Imported"
+            },
+            self.count(|outcome| matches!(outcome, ImportOutcome::Imported(_))),
+            self.count(|outcome| matches!(outcome, ImportOutcome::Skipped(_))),
+            self.failed()
+        )
+    }
+}
+
+impl fmt::Display for ImportResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            ImportOutcome::Imported(Some(uuid)) => write!(f, "ok      {} -> {}", self.record, uuid),
+            ImportOutcome::Imported(None) => write!(f, "ok      {}", self.record),
+            ImportOutcome::Skipped(reason) => write!(f, "skipped {}: {}", self.record, reason),
+            ImportOutcome::Failed(error) => write!(f, "failed  {}: {}", self.record, error),
+        }
+    }
+}
+
+pub fn user_key(user: &ImportedUser) -> String {
+    format!("users/{}", user.id)
+}
+
+pub fn post_key(user: &ImportedUser, post: &ImportedPost) -> String {
+    format!("{}/posts/{}", user_key(user), post.id)
+}
+
+pub fn read_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Manifest, String> {
+    let mut manifest = String::new();
+    archive
+        .by_name(MANIFEST)
+        .map_err(|_| format!("Archive has no {}", MANIFEST))?
+        .read_to_string(&mut manifest)
+        .map_err(|e| format!("Cannot read {}: {}", MANIFEST, e))?;
+    let manifest: Manifest =
+        serde_json::from_str(&manifest).map_err(|e| format!("Invalid {}: {}", MANIFEST, e))?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(format!(
+            "Unsupported manifest version {}, expected {}",
+            manifest.version, MANIFEST_VERSION
+        ));
+    }
+    if manifest.source.trim().is_empty() {
+        return Err(String::from("Manifest source cannot be empty"));
+    }
+    Ok(manifest)
+}
+
+fn parse_created_at(created_at: Option<&str>) -> Result<Option<OurDateTime>, String> {
+    let created_at = match created_at {
+        Some(created_at) => created_at,
+        None => return Ok(None),
+    };
+    match OurDateTime::parse(created_at) {
+        Some(parsed) if parsed.0 <= Utc::now() => Ok(Some(parsed)),
+        Some(_) => Err(String::from("created_at cannot be in the future")),
+        None => Err(format!("Invalid created_at {}", created_at)),
+    }
+}
+
+pub fn validate_user(user: &ImportedUser) -> Result<Option<OurDateTime>, String> {
+    let password = match (&user.password, &user.password_hash) {
+        (Some(password), None) => Some(password.as_str()),
+        (None, Some(password_hash)) => {
+            PasswordHash::new(password_hash)
+                .map_err(|e| format!("Invalid password_hash: {}", e))?;
+            None
+        }
+        _ => return Err(String::from("Set either password or password_hash")),
+    };
+    let fields = vec![
+        ValueField::from(("username", user.username.as_str())),
+        ValueField::from(("email", user.email.as_str())),
+        ValueField::from(("password", password.unwrap_or_default())),
+        ValueField::from(("password_confirmation", password.unwrap_or_default())),
+        ValueField::from((
+            "description",
+            user.description.as_deref().unwrap_or_default(),
+        )),
+        ValueField::from(("authenticity_token", "")),
+    ];
+    if let Err(errors) = Form::<NewUser>::parse_iter(fields) {
+        let messages: Vec<String> = errors
+            .iter()
+            .filter(|error| {
+                password.is_some()
+                    || !(error.is_for("password") || error.is_for("password_confirmation"))
+            })
+            .map(|error| match &error.name {
+                Some(name) => format!("{}: {}", name, error),
+                None => error.to_string(),
+            })
+            .collect();
+        if !messages.is_empty() {
+            return Err(messages.join(", "));
+        }
+    }
+    parse_created_at(user.created_at.as_deref())
+}
+
+pub fn validate_post(
+    post: &ImportedPost,
+    entries: &HashSet<String>,
+    album_items: usize,
+) -> Result<PostPlan, String> {
+    let visibility = match post.visibility.as_deref() {
+        Some(visibility) => {
+            Visibility::parse(visibility).ok_or(format!("Unknown visibility {}", visibility))?
+        }
+        None => Visibility::Public,
+    };
+    let created_at = parse_created_at(post.created_at.as_deref())?;
+    let body = match (&post.content, post.files.is_empty()) {
+        (Some(content), true) if !content.trim().is_empty() => PostBody::Text(content.clone()),
+        (None, false) => {
+            if post.files.len() > album_items {
+                return Err(format!(
+                    "An album can contain at most {} files",
+                    album_items
+                ));
+            }
+            let mut files = vec![];
+            for name in post.files.iter() {
+                if !entries.contains(name) {
+                    return Err(format!("{} is missing from the archive", name));
+                }
+                let content_type = ContentType::parse_flexible(&content_type_for(name))
+                    .filter(|content_type| post_type_for(content_type).is_some())
+                    .ok_or(format!("Unsupported file type {}", name))?;
+                files.push((name.clone(), content_type));
+            }
+            PostBody::Files(files)
+        }
+        _ => return Err(String::from("Set either text content or files")),
+    };
+    Ok(PostPlan {
+        body,
+        visibility,
+        created_at,
+    })
+}
+
+pub async fn import_archive(
+    connection: &mut PgConnection,
+    pipeline: &UploadPipeline<'_>,
+    path: &Path,
+    resume: bool,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Invalid archive: {}", e))?;
+    let manifest = read_manifest(&mut archive)?;
+    let entries: HashSet<String> = archive.file_names().map(String::from).collect();
+    let source = manifest.source.as_str();
+    if !dry_run && !resume {
+        let imported = ImportRecord::source_exists(connection, source)
+            .await
+            .map_err(|e| e.message)?;
+        if imported {
+            return Err(format!(
+                "Source {} was already imported, use --resume to re-run it",
+                source
+            ));
+        }
+    }
+
+    let mut report = ImportReport {
+        dry_run,
+        ..ImportReport::default()
+    };
+    for imported_user in manifest.users.iter() {
+        let key = user_key(imported_user);
+        let user = if dry_run {
+            match validate_user(imported_user) {
+                Ok(_) => report.push(&key, ImportOutcome::Imported(None)),
+                Err(e) => report.push(&key, ImportOutcome::Failed(e)),
+            }
+            None
+        } else {
+            match import_user(connection, source, &key, imported_user).await {
+                Ok((user, outcome)) => {
+                    report.push(&key, outcome);
+                    Some(user)
+                }
+                Err(e) => {
+                    record_failure(connection, source, &key, &e).await;
+                    report.push(&key, ImportOutcome::Failed(e));
+                    None
+                }
+            }
+        };
+
+        for imported_post in imported_user.posts.iter() {
+            let key = post_key(imported_user, imported_post);
+            let plan = match validate_post(imported_post, &entries, pipeline.quota.album_items) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    if !dry_run {
+                        record_failure(connection, source, &key, &e).await;
+                    }
+                    report.push(&key, ImportOutcome::Failed(e));
+                    continue;
+                }
+            };
+            if dry_run {
+                report.push(&key, ImportOutcome::Imported(None));
+                continue;
+            }
+            let user = match user.as_ref() {
+                Some(user) => user,
+                None => {
+                    report.push(
+                        &key,
+                        ImportOutcome::Skipped(String::from("user was not imported")),
+                    );
+                    continue;
+                }
+            };
+            match import_post(connection, pipeline, &mut archive, source, &key, user, plan).await {
+                Ok(outcome) => report.push(&key, outcome),
+                Err(e) => {
+                    record_failure(connection, source, &key, &e).await;
+                    report.push(&key, ImportOutcome::Failed(e));
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+async fn record_failure(connection: &mut PgConnection, source: &str, key: &str, error: &str) {
+    let saved = ImportRecord::save(
+        connection,
+        source,
+        key,
+        ImportStatus::Failed,
+        None,
+        Some(error),
+    )
+    .await;
+    if let Err(e) = saved {
+        log::error!("Cannot record import of {}: {}", key, e.message);
+    }
+}
+
+async fn record_import(
+    connection: &mut PgConnection,
+    source: &str,
+    key: &str,
+    uuid: &Uuid,
+) -> Result<(), String> {
+    ImportRecord::save(
+        connection,
+        source,
+        key,
+        ImportStatus::Imported,
+        Some(uuid),
+        None,
+    )
+    .await
+    .map(|_| ())
+    .map_err(|e| e.message)
+}
+
+async fn find_imported(
+    connection: &mut PgConnection,
+    source: &str,
+    key: &str,
+) -> Result<Option<Uuid>, String> {
+    let found = ImportRecord::find(connection, source, key)
+        .await
+        .map_err(|e| e.message)?;
+    Ok(found
+        .filter(|record| record.status == ImportStatus::Imported)
+        .and_then(|record| record.target_uuid))
+}
+
+async fn import_user(
+    connection: &mut PgConnection,
+    source: &str,
+    key: &str,
+    imported: &ImportedUser,
+) -> Result<(User, ImportOutcome), String> {
+    if let Some(uuid) = find_imported(connection, source, key).await? {
+        let user = User::find(connection, &uuid.to_string())
+            .await
+            .map_err(|_| format!("Previously imported user {} no longer exists", uuid))?;
+        return Ok((
+            user,
+            ImportOutcome::Skipped(format!("already imported as {}", uuid)),
+        ));
+    }
+    let created_at = validate_user(imported)?.unwrap_or(OurDateTime(Utc::now()));
+    let password_hash = match (&imported.password, &imported.password_hash) {
+        (Some(password), _) => hash_password(password).map_err(|e| e.message)?,
+        (None, Some(password_hash)) => password_hash.clone(),
+        (None, None) => return Err(String::from("Set either password or password_hash")),
+    };
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let user = User::import(
+        &mut transaction,
+        &imported.username,
+        &imported.email,
+        &password_hash,
+        imported.description.as_deref(),
+        &created_at,
+    )
+    .await
+    .map_err(|e| match e.status.code {
+        400 => String::from("Username or email is already taken"),
+        _ => e.message,
+    })?;
+    record_import(&mut transaction, source, key, &user.uuid).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    let uuid = user.uuid;
+    Ok((user, ImportOutcome::Imported(Some(uuid))))
+}
+
+async fn import_post<R: Read + Seek>(
+    connection: &mut PgConnection,
+    pipeline: &UploadPipeline<'_>,
+    archive: &mut ZipArchive<R>,
+    source: &str,
+    key: &str,
+    user: &User,
+    plan: PostPlan,
+) -> Result<ImportOutcome, String> {
+    if let Some(uuid) = find_imported(connection, source, key).await? {
+        return Ok(ImportOutcome::Skipped(format!(
+            "already imported as {}",
+            uuid
+        )));
+    }
+    let mut staged: Vec<(PathBuf, ContentType)> = vec![];
+    let extracted = match &plan.body {
+        PostBody::Text(content) => {
+            let staged_path = pipeline
+                .storage
+                .staging_path(&format!("{}.txt", Uuid::new_v4()));
+            let written = std::fs::write(&staged_path, content)
+                .map_err(|e| format!("Cannot stage post: {}", e));
+            staged.push((staged_path, ContentType::Plain));
+            written
+        }
+        PostBody::Files(files) => files.iter().try_for_each(|(name, content_type)| {
+            let staged_path = stage_file(archive, pipeline, name, content_type)?;
+            staged.push((staged_path, content_type.clone()));
+            Ok(())
+        }),
+    };
+    if let Err(e) = extracted {
+        for (staged_path, _) in staged.iter() {
+            let _ = std::fs::remove_file(staged_path);
+        }
+        return Err(e);
+    }
+
+    let settings = PostSettings {
+        visibility: plan.visibility,
+        publish_status: PublishStatus::Published,
+        publish_at: None,
+    };
+    // Media uploaded for a post that is rolled back stays unreferenced and is
+    // removed by the media garbage collector.
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let saved = if staged.len() == 1 {
+        let (staged_path, content_type) = &staged[0];
+        save_staged_upload(
+            &mut transaction,
+            user,
+            staged_path,
+            content_type,
+            &settings,
+            pipeline,
+        )
+        .await
+    } else {
+        save_staged_album(&mut transaction, user, &staged, &settings, pipeline).await
+    };
+    let post = saved.map_err(|e: OurError| e.message)?;
+    if let Some(created_at) = plan.created_at.as_ref() {
+        Post::backdate(&mut transaction, &post.uuid, created_at)
+            .await
+            .map_err(|e| e.message)?;
+    }
+    record_import(&mut transaction, source, key, &post.uuid).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(ImportOutcome::Imported(Some(post.uuid)))
+}
+
+fn stage_file<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    pipeline: &UploadPipeline<'_>,
+    name: &str,
+    content_type: &ContentType,
+) -> Result<PathBuf, String> {
+    let ext = content_type
+        .extension()
+        .map(|ext| ext.to_string())
+        .or_else(|| {
+            name.rsplit_once('.')
+                .map(|(_, ext)| ext.to_ascii_lowercase())
+        })
+        .ok_or(format!("Unsupported file type {}", name))?;
+    let staged_path = pipeline
+        .storage
+        .staging_path(&format!("{}.{}", Uuid::new_v4(), ext));
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| format!("Cannot read {}: {}", name, e))?;
+    let mut file =
+        File::create(&staged_path).map_err(|e| format!("Cannot stage {}: {}", name, e))?;
+    if let Err(e) = std::io::copy(&mut entry, &mut file) {
+        let _ = std::fs::remove_file(&staged_path);
+        return Err(format!("Cannot stage {}: {}", name, e));
+    }
+    Ok(staged_path)
+}
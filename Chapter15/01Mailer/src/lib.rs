@@ -1,30 +1,48 @@
 #[macro_use]
 extern crate rocket;
 
+use crate::events::EventHub;
 use crate::fairings::{csrf::Csrf, db::DBConnection};
-use crate::models::worker::Message;
-use crate::routes::{api, post, session, user};
+use crate::guards::upload::UploadPipeline;
+use crate::import::ImportReport;
+use crate::media::link_preview::LinkPreviewConfig;
+use crate::media::photo::PhotoConfig;
+use crate::media::scan::ScanConfig;
+use crate::models::quota::QuotaConfig;
+use crate::models::resumable_upload::TusConfig;
+use crate::routes::{
+    api, asset, draft, event, export, notification, post, session, trash, tus, user,
+};
 use crate::states::JWToken;
-use crate::workers::video::process_video;
+use crate::storage::{Storage, StorageConfig};
+use crate::workers::audio::AudioConfig;
+use crate::workers::export::ExportConfig;
+use crate::workers::gc::{run_exclusive, spawn_media_gc, GcConfig, GcReport};
+use crate::workers::trash::TrashConfig;
+use crate::workers::video::VideoConfig;
+use crate::workers::{spawn_workers, WorkerConfig, WorkerContext};
 use lettre::{SmtpClient, Transport};
 use lettre_email::EmailBuilder;
 use log::LevelFilter;
-use rocket::fs::relative;
-use rocket::fs::FileServer;
 use rocket::serde::Deserialize;
 use rocket::{Build, Rocket};
 use rocket_db_pools::Database;
 use rocket_dyn_templates::Template;
 use sqlx::postgres::PgPoolOptions;
-use tokio::runtime::Handle;
+use std::path::Path;
 
 pub mod catchers;
 pub mod errors;
+pub mod events;
 pub mod fairings;
 pub mod guards;
+pub mod import;
+pub mod media;
 pub mod models;
+pub mod responders;
 pub mod routes;
 pub mod states;
+pub mod storage;
 pub mod traits;
 pub mod workers;
 
@@ -32,6 +50,30 @@ pub mod workers;
 pub struct Config {
     databases: Databases,
     jwt_secret: String,
+    #[serde(default)]
+    workers: WorkerConfig,
+    #[serde(default)]
+    video: VideoConfig,
+    #[serde(default)]
+    audio: AudioConfig,
+    #[serde(default)]
+    link_preview: LinkPreviewConfig,
+    #[serde(default)]
+    photo: PhotoConfig,
+    #[serde(default)]
+    storage: StorageConfig,
+    #[serde(default)]
+    gc: GcConfig,
+    #[serde(default)]
+    quota: QuotaConfig,
+    #[serde(default)]
+    scan: ScanConfig,
+    #[serde(default)]
+    tus: TusConfig,
+    #[serde(default)]
+    trash: TrashConfig,
+    #[serde(default)]
+    export: ExportConfig,
 }
 
 #[derive(Deserialize)]
@@ -73,13 +115,13 @@ fn setup_logger() {
 
 pub async fn setup_rocket() -> Rocket<Build> {
     setup_logger();
-    let (tx, rx) = flume::bounded::<Message>(5);
+    let hub = EventHub::new();
 
     let our_rocket = rocket::build()
         .attach(DBConnection::init())
         .attach(Template::fairing())
         .attach(Csrf::new())
-        .manage(tx)
+        .manage(hub.clone())
         .mount(
             "/",
             routes![
@@ -93,21 +135,58 @@ pub async fn setup_rocket() -> Rocket<Build> {
                 user::patch_user,
                 user::delete_user,
                 user::delete_user_entry_point,
+                user::confirm_delete_user,
+                user::restore_user_form,
+                user::restore_user,
+                user::update_quota,
                 post::get_post,
                 post::get_posts,
                 post::create_post,
                 post::delete_post,
+                post::retry_post,
+                post::edit_album,
+                post::update_album,
+                draft::get_drafts,
+                draft::edit_draft,
+                draft::update_draft,
+                trash::get_trash,
+                trash::restore_post,
+                export::get_exports,
+                export::create_export,
+                export::download_export,
+                notification::get_notifications,
+                notification::read_notification,
+                notification::read_all_notifications,
+                event::events,
                 routes::home,
                 routes::shutdown,
                 session::new,
                 session::create,
                 session::delete,
+                tus::options,
+                tus::create,
+                tus::head,
+                tus::patch,
+                tus::terminate,
             ],
         )
-        .mount("/assets", FileServer::from(relative!("static")))
+        .mount("/assets", routes![asset::hls, asset::file])
         .mount(
             "/api",
-            routes![api::users, api::login, api::authenticated_users,],
+            routes![
+                api::users,
+                api::login,
+                api::authenticated_users,
+                api::notifications,
+                api::unread_notifications_count,
+                api::read_notification,
+                api::read_all_notifications,
+                api::posts,
+                api::create_post,
+                api::post,
+                api::post_status,
+                api::retry_post,
+            ],
         )
         .register(
             "/",
@@ -127,34 +206,98 @@ pub async fn setup_rocket() -> Rocket<Build> {
         secret: String::from(config.jwt_secret.clone()),
     };
 
-    let final_rocket = our_rocket.manage(jwt_secret);
+    let storage = Storage::new(&config.storage).expect("Incorrect storage configuration");
+
+    let final_rocket = our_rocket
+        .manage(jwt_secret)
+        .manage(config.photo.clone())
+        .manage(config.quota.clone())
+        .manage(config.scan.clone())
+        .manage(config.tus.clone())
+        .manage(config.trash.clone())
+        .manage(config.export.clone())
+        .manage(storage.clone());
 
     let pool = PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.workers.pool_size())
         .connect(&config.databases.main_connection.url)
         .await
         .expect("Failed to connect to database");
 
-    tokio::task::spawn_blocking(move || loop {
-        let wm = rx.recv().unwrap();
-        let handle = Handle::current();
-        let get_connection = async { (&pool).acquire().await.unwrap() };
-        let mut connection = handle.block_on(get_connection);
-        let _ = process_video(&mut connection, wm);
-    });
+    spawn_media_gc(pool.clone(), storage.clone(), config.gc);
+    let context = WorkerContext {
+        hub,
+        video: config.video,
+        audio: config.audio,
+        link_preview: config.link_preview,
+        trash: config.trash,
+        export: config.export,
+        storage,
+    };
+    spawn_workers(pool, context, config.workers).await;
     final_rocket
 }
 
+pub async fn collect_media_garbage(
+    config: &Config,
+    dry_run: bool,
+    grace_period: Option<u64>,
+) -> Result<Option<GcReport>, String> {
+    let storage = Storage::new(&config.storage)?;
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&config.databases.main_connection.url)
+        .await
+        .map_err(|e| format!("Cannot connect to database: {}", e))?;
+    let grace_period = grace_period.unwrap_or(config.gc.grace_period);
+    run_exclusive(&pool, &storage, grace_period, dry_run).await
+}
+
+pub async fn import_archive(
+    config: &Config,
+    path: &Path,
+    resume: bool,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    let storage = Storage::new(&config.storage)?;
+    let hub = EventHub::new();
+    let pipeline = UploadPipeline {
+        hub: &hub,
+        photo: &config.photo,
+        storage: &storage,
+        quota: &config.quota,
+        scan: &config.scan,
+    };
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&config.databases.main_connection.url)
+        .await
+        .map_err(|e| format!("Cannot connect to database: {}", e))?;
+    let mut connection = pool.acquire().await.map_err(|e| e.to_string())?;
+    import::import_archive(&mut connection, &pipeline, path, resume, dry_run).await
+}
+
 pub fn send_email(email: &str, name: &str) -> Result<String, String> {
+    send_mail(
+        email,
+        name,
+        "Hi, welcome to our_application",
+        "Hello, thank you for joining our_application.",
+    )
+}
+
+pub fn send_mail(email: &str, name: &str, subject: &str, text: &str) -> Result<String, String> {
     let email = EmailBuilder::new()
         .to((email, name))
         .from("admin@our_application.com")
-        .subject("Hi, welcome to our_application")
-        .text("Hello, thank you for joining our_application.")
+        .subject(subject)
+        .text(text)
         .build()
-        .unwrap();
+        .map_err(|e| format!("Cannot build email: {}", e))?;
 
-    let mut mailer = SmtpClient::new_unencrypted_localhost().unwrap().transport();
+    let mut mailer = SmtpClient::new_unencrypted_localhost()
+        .map_err(|e| format!("Cannot connect to the mail server: {}", e))?
+        .transport();
     mailer
         .send(email.into())
         .map(|_| String::from("Successfuly sent email"))
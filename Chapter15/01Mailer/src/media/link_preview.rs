@@ -0,0 +1,505 @@
+use crate::models::escape_html;
+use image::ImageFormat;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Response, Url};
+use rocket::serde::json::{serde_json, Value};
+use rocket::serde::Deserialize;
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s<>"'`]+"#).unwrap());
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"', ']', '}'];
+const MAX_URL_LENGTH: usize = 2048;
+const MAX_TITLE_LENGTH: usize = 300;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const HTML_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+const OEMBED_TYPES: &[&str] = &["application/json", "text/json", "application/json+oembed"];
+const IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LinkPreviewConfig {
+    pub enabled: bool,
+    pub timeout: u64,
+    pub max_size: usize,
+    pub max_image_size: usize,
+    pub max_redirects: usize,
+    pub ttl: u64,
+    pub failure_ttl: u64,
+    pub user_agent: String,
+    pub allow_private: bool,
+}
+
+impl Default for LinkPreviewConfig {
+    fn default() -> Self {
+        LinkPreviewConfig {
+            enabled: true,
+            timeout: 5,
+            max_size: 512 * 1024,
+            max_image_size: 2 * 1024 * 1024,
+            max_redirects: 3,
+            ttl: 7 * 86400,
+            failure_ttl: 3600,
+            user_agent: String::from("OurApplication-LinkPreview/1.0"),
+            allow_private: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PreviewMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+    pub source: String,
+    pub oembed_url: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FetchError {
+    pub message: String,
+    pub permanent: bool,
+}
+
+impl FetchError {
+    fn permanent(message: String) -> Self {
+        FetchError {
+            message,
+            permanent: true,
+        }
+    }
+
+    fn transient(message: String) -> Self {
+        FetchError {
+            message,
+            permanent: false,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+fn url_spans(text: &str) -> Vec<(usize, usize)> {
+    URL_REGEX
+        .find_iter(text)
+        .filter_map(|found| {
+            let mut url = found.as_str();
+            loop {
+                let trimmed = url.trim_end_matches(TRAILING_PUNCTUATION);
+                let trimmed = if trimmed.ends_with(')')
+                    && trimmed.matches(')').count() > trimmed.matches('(').count()
+                {
+                    &trimmed[..trimmed.len() - 1]
+                } else {
+                    trimmed
+                };
+                if trimmed.len() == url.len() {
+                    break;
+                }
+                url = trimmed;
+            }
+            let host = url.splitn(2, "://").nth(1).unwrap_or("");
+            if host.is_empty() || url.len() > MAX_URL_LENGTH {
+                return None;
+            }
+            Some((found.start(), found.start() + url.len()))
+        })
+        .collect()
+}
+
+pub fn find_urls(text: &str) -> Vec<String> {
+    url_spans(text)
+        .into_iter()
+        .map(|(start, end)| &text[start..end])
+        .filter(|url| Url::parse(url).map_or(false, |parsed| parsed.host_str().is_some()))
+        .map(String::from)
+        .collect()
+}
+
+pub fn linkify(text: &str) -> String {
+    let mut html = String::new();
+    let mut last = 0;
+    for (start, end) in url_spans(text) {
+        html.push_str(&escape_html(&text[last..start]));
+        let url = escape_html(&text[start..end]);
+        html.push_str(&format!(
+            r#"<a href="{}" rel="nofollow noopener ugc" target="_blank">{}</a>"#,
+            url, url
+        ));
+        last = end;
+    }
+    html.push_str(&escape_html(&text[last..]));
+    html
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || octets[0] == 0
+        || octets[0] >= 240
+        || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+        || (octets[0] == 198 && (octets[1] & 0xfe) == 18))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let segments = ip.segments();
+    if segments[..5].iter().all(|s| *s == 0) && (segments[5] == 0xffff || segments[5] == 0) {
+        if segments[5] == 0 && segments[6] == 0 {
+            return false;
+        }
+        let [a, b] = segments[6].to_be_bytes();
+        let [c, d] = segments[7].to_be_bytes();
+        return is_public_ipv4(Ipv4Addr::new(a, b, c, d));
+    }
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (segments[0] & 0xfe00) == 0xfc00
+        || (segments[0] & 0xffc0) == 0xfe80
+        || (segments[0] & 0xffc0) == 0xfec0
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        || (segments[0] == 0x0064 && segments[1] == 0xff9b)
+        || segments[0] == 0x2002)
+}
+
+pub async fn fetch_preview(
+    config: &LinkPreviewConfig,
+    url: &str,
+) -> Result<PreviewMetadata, FetchError> {
+    let timeout = Duration::from_secs(config.timeout.max(1));
+    tokio::time::timeout(timeout, fetch_metadata(config, url))
+        .await
+        .map_err(|_| FetchError::transient(format!("Timed out fetching {}", url)))?
+}
+
+async fn fetch_metadata(
+    config: &LinkPreviewConfig,
+    url: &str,
+) -> Result<PreviewMetadata, FetchError> {
+    let page_url =
+        Url::parse(url).map_err(|e| FetchError::permanent(format!("Invalid URL: {}", e)))?;
+    let (final_url, body) = fetch(config, page_url, HTML_TYPES).await?;
+    let mut metadata = parse_metadata(&String::from_utf8_lossy(&body), &final_url);
+    if metadata.title.is_none() {
+        if let Some(oembed_url) = metadata
+            .oembed_url
+            .as_deref()
+            .and_then(|u| Url::parse(u).ok())
+        {
+            match fetch(config, oembed_url, OEMBED_TYPES).await {
+                Ok((_, body)) => match parse_oembed(&body) {
+                    Ok(oembed) => metadata.merge(oembed),
+                    Err(e) => log::warn!("Cannot read oEmbed data for {}: {}", url, e),
+                },
+                Err(e) => log::warn!("Cannot fetch oEmbed data for {}: {}", url, e),
+            }
+        }
+    }
+    if metadata.title.is_none() {
+        return Err(FetchError::permanent(String::from(
+            "Page does not have any preview metadata",
+        )));
+    }
+    Ok(metadata)
+}
+
+// Downloads a preview image with the same address checks as the page
+// itself. Returns the image and its file extension.
+pub async fn fetch_image(
+    config: &LinkPreviewConfig,
+    url: &str,
+) -> Result<(Vec<u8>, &'static str), FetchError> {
+    let timeout = Duration::from_secs(config.timeout.max(1));
+    tokio::time::timeout(timeout, download_image(config, url))
+        .await
+        .map_err(|_| FetchError::transient(format!("Timed out fetching {}", url)))?
+}
+
+async fn download_image(
+    config: &LinkPreviewConfig,
+    url: &str,
+) -> Result<(Vec<u8>, &'static str), FetchError> {
+    let image_url =
+        Url::parse(url).map_err(|e| FetchError::permanent(format!("Invalid URL: {}", e)))?;
+    let (final_url, response) = send(config, image_url, IMAGE_TYPES).await?;
+    let body = read_limited(response, config.max_image_size + 1).await?;
+    if body.len() > config.max_image_size {
+        return Err(FetchError::permanent(format!(
+            "{} is larger than {} bytes",
+            final_url, config.max_image_size
+        )));
+    }
+    let extension = match image::guess_format(&body) {
+        Ok(ImageFormat::Jpeg) => "jpg",
+        Ok(ImageFormat::Png) => "png",
+        Ok(ImageFormat::Gif) => "gif",
+        Ok(ImageFormat::WebP) => "webp",
+        _ => {
+            return Err(FetchError::permanent(format!(
+                "{} is not a supported image",
+                final_url
+            )))
+        }
+    };
+    Ok((body, extension))
+}
+
+async fn fetch(
+    config: &LinkPreviewConfig,
+    url: Url,
+    accepted_types: &[&str],
+) -> Result<(Url, Vec<u8>), FetchError> {
+    let (final_url, response) = send(config, url, accepted_types).await?;
+    let body = read_limited(response, config.max_size).await?;
+    Ok((final_url, body))
+}
+
+async fn send(
+    config: &LinkPreviewConfig,
+    url: Url,
+    accepted_types: &[&str],
+) -> Result<(Url, Response), FetchError> {
+    let mut current = url;
+    for _ in 0..=config.max_redirects {
+        let address = resolve(config, &current).await?;
+        let mut builder = Client::builder()
+            .redirect(Policy::none())
+            .user_agent(&config.user_agent);
+        if let Some(domain) = current.domain() {
+            builder = builder.resolve(domain, address);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| FetchError::transient(format!("Cannot build HTTP client: {}", e)))?;
+        let response = client
+            .get(current.clone())
+            .header(ACCEPT, accepted_types.join(", "))
+            .send()
+            .await
+            .map_err(|e| FetchError::transient(format!("Cannot fetch {}: {}", current, e)))?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or_else(|| FetchError::permanent(format!("{} redirects nowhere", current)))?;
+            current = current
+                .join(location)
+                .map_err(|e| FetchError::permanent(format!("Invalid redirect: {}", e)))?;
+            continue;
+        }
+        if status.is_client_error() {
+            return Err(FetchError::permanent(format!(
+                "{} responded with {}",
+                current, status
+            )));
+        }
+        if !status.is_success() {
+            return Err(FetchError::transient(format!(
+                "{} responded with {}",
+                current, status
+            )));
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        if !accepted_types.contains(&content_type.as_str()) {
+            return Err(FetchError::permanent(format!(
+                "{} is not a supported page ({})",
+                current, content_type
+            )));
+        }
+        return Ok((current, response));
+    }
+    Err(FetchError::permanent(format!(
+        "Too many redirects fetching {}",
+        current
+    )))
+}
+
+async fn resolve(config: &LinkPreviewConfig, url: &Url) -> Result<SocketAddr, FetchError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(FetchError::permanent(format!(
+            "Unsupported URL scheme {}",
+            url.scheme()
+        )));
+    }
+    if !url.username().is_empty() || url.password().is_some() {
+        return Err(FetchError::permanent(String::from(
+            "URLs with credentials are not fetched",
+        )));
+    }
+    let host = url
+        .host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| FetchError::permanent(String::from("URL does not have a host")))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| FetchError::transient(format!("Cannot resolve {}: {}", host, e)))?
+        .collect();
+    if addresses.is_empty() {
+        return Err(FetchError::permanent(format!("Cannot resolve {}", host)));
+    }
+    if !config.allow_private && addresses.iter().any(|a| !is_public_ip(a.ip())) {
+        return Err(FetchError::permanent(format!(
+            "{} resolves to a private address",
+            host
+        )));
+    }
+    Ok(addresses[0])
+}
+
+async fn read_limited(mut response: Response, max_size: usize) -> Result<Vec<u8>, FetchError> {
+    let mut body = vec![];
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| FetchError::transient(format!("Cannot read response: {}", e)))?
+    {
+        let remaining = max_size.saturating_sub(body.len());
+        body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        if body.len() >= max_size {
+            break;
+        }
+    }
+    Ok(body)
+}
+
+pub fn parse_metadata(html: &str, base: &Url) -> PreviewMetadata {
+    let document = Html::parse_document(html);
+    let meta_selector = Selector::parse("meta").unwrap();
+    let mut tags: HashMap<String, String> = HashMap::new();
+    for element in document.select(&meta_selector) {
+        let value = element.value();
+        let key = value
+            .attr("property")
+            .or_else(|| value.attr("name"))
+            .map(|key| key.trim().to_ascii_lowercase());
+        let content = value
+            .attr("content")
+            .map(str::trim)
+            .filter(|content| !content.is_empty());
+        if let (Some(key), Some(content)) = (key, content) {
+            tags.entry(key).or_insert_with(|| String::from(content));
+        }
+    }
+    let pick = |keys: &[&str]| keys.iter().find_map(|key| tags.get(*key).cloned());
+    let title_selector = Selector::parse("title").unwrap();
+    let title = pick(&["og:title", "twitter:title"]).or_else(|| {
+        document
+            .select(&title_selector)
+            .next()
+            .map(|title| title.text().collect::<String>().trim().to_string())
+            .filter(|title| !title.is_empty())
+    });
+    let oembed_selector = Selector::parse(r#"link[type="application/json+oembed"]"#).unwrap();
+    let source = if tags.keys().any(|key| key.starts_with("og:")) {
+        "opengraph"
+    } else if tags.keys().any(|key| key.starts_with("twitter:")) {
+        "twitter"
+    } else {
+        "html"
+    };
+    PreviewMetadata {
+        title: title.map(|title| truncate(&title, MAX_TITLE_LENGTH)),
+        description: pick(&["og:description", "twitter:description", "description"])
+            .map(|description| truncate(&description, MAX_DESCRIPTION_LENGTH)),
+        image_url: pick(&[
+            "og:image:secure_url",
+            "og:image:url",
+            "og:image",
+            "twitter:image",
+            "twitter:image:src",
+        ])
+        .and_then(|image| absolute_url(base, &image)),
+        site_name: pick(&["og:site_name", "application-name"])
+            .map(|site_name| truncate(&site_name, MAX_TITLE_LENGTH)),
+        source: String::from(source),
+        oembed_url: document
+            .select(&oembed_selector)
+            .find_map(|link| link.value().attr("href"))
+            .and_then(|href| absolute_url(base, href)),
+    }
+}
+
+pub fn parse_oembed(body: &[u8]) -> Result<PreviewMetadata, String> {
+    let json: Value =
+        serde_json::from_slice(body).map_err(|e| format!("Invalid oEmbed response: {}", e))?;
+    let field = |key: &str| {
+        json[key]
+            .as_str()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(String::from)
+    };
+    Ok(PreviewMetadata {
+        title: field("title").map(|title| truncate(&title, MAX_TITLE_LENGTH)),
+        description: field("author_name"),
+        image_url: field("thumbnail_url").filter(|url| {
+            Url::parse(url).map_or(false, |url| {
+                url.scheme() == "http" || url.scheme() == "https"
+            })
+        }),
+        site_name: field("provider_name"),
+        source: String::from("oembed"),
+        oembed_url: None,
+    })
+}
+
+impl PreviewMetadata {
+    fn merge(&mut self, other: PreviewMetadata) {
+        if self.title.is_none() {
+            self.source = other.source;
+        }
+        self.title = self.title.take().or(other.title);
+        self.description = self.description.take().or(other.description);
+        self.image_url = self.image_url.take().or(other.image_url);
+        self.site_name = self.site_name.take().or(other.site_name);
+    }
+}
+
+fn absolute_url(base: &Url, url: &str) -> Option<String> {
+    let url = base.join(url.trim()).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    Some(url.to_string())
+}
+
+fn truncate(value: &str, max_length: usize) -> String {
+    match value.char_indices().nth(max_length) {
+        Some((index, _)) => format!("{}…", &value[..index]),
+        None => String::from(value),
+    }
+}
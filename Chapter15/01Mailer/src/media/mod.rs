@@ -0,0 +1,4 @@
+pub mod link_preview;
+pub mod photo;
+pub mod scan;
+pub mod svg;
@@ -0,0 +1,314 @@
+use crate::models::media_variant::MediaVariant;
+use exif::experimental::Writer as ExifWriter;
+use exif::{Exif, In, Reader as ExifReader, Tag};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::imageops::FilterType;
+use image::io::Reader as ImageReader;
+use image::{ColorType, DynamicImage, ImageEncoder, ImageError, Rgb, RgbImage, Rgba, RgbaImage};
+use rocket::serde::Deserialize;
+use std::fs::{remove_file, write};
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PhotoSize {
+    pub label: String,
+    pub width: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PhotoConfig {
+    pub sizes: Vec<PhotoSize>,
+    pub jpeg_quality: u8,
+    pub webp: bool,
+    pub webp_quality: u8,
+    pub avif: bool,
+    pub avif_quality: u8,
+    pub avif_speed: u8,
+    pub keep_camera_info: bool,
+    pub rasterize_svg: bool,
+}
+
+impl Default for PhotoConfig {
+    fn default() -> Self {
+        PhotoConfig {
+            sizes: vec![
+                PhotoSize {
+                    label: String::from("thumbnail"),
+                    width: 320,
+                },
+                PhotoSize {
+                    label: String::from("medium"),
+                    width: 800,
+                },
+                PhotoSize {
+                    label: String::from("large"),
+                    width: 1600,
+                },
+            ],
+            jpeg_quality: 75,
+            webp: true,
+            webp_quality: 75,
+            avif: true,
+            avif_quality: 60,
+            avif_speed: 6,
+            keep_camera_info: false,
+            rasterize_svg: false,
+        }
+    }
+}
+
+const CAMERA_TAGS: [Tag; 8] = [
+    Tag::Make,
+    Tag::Model,
+    Tag::LensMake,
+    Tag::LensModel,
+    Tag::ExposureTime,
+    Tag::FNumber,
+    Tag::PhotographicSensitivity,
+    Tag::FocalLength,
+];
+
+#[derive(Debug)]
+pub struct ProcessedPhoto {
+    pub content: String,
+    pub variants: Vec<MediaVariant>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PhotoFormat {
+    Jpeg,
+    WebP,
+    Avif,
+}
+
+impl PhotoFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            PhotoFormat::Jpeg => "jpg",
+            PhotoFormat::WebP => "webp",
+            PhotoFormat::Avif => "avif",
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            PhotoFormat::Jpeg => "image/jpeg",
+            PhotoFormat::WebP => "image/webp",
+            PhotoFormat::Avif => "image/avif",
+        }
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<DynamicImage, String> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())
+}
+
+pub fn read_exif(bytes: &[u8]) -> Option<Exif> {
+    ExifReader::new()
+        .read_from_container(&mut BufReader::new(Cursor::new(bytes)))
+        .ok()
+}
+
+pub fn orientation(exif: &Exif) -> u32 {
+    exif.get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .unwrap_or(1)
+}
+
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+fn camera_info(exif: &Exif) -> Option<Vec<u8>> {
+    let fields: Vec<_> = exif
+        .fields()
+        .filter(|field| field.ifd_num == In::PRIMARY && CAMERA_TAGS.contains(&field.tag))
+        .collect();
+    if fields.is_empty() {
+        return None;
+    }
+    let mut writer = ExifWriter::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(vec![]);
+    writer.write(&mut tiff, exif.little_endian()).ok()?;
+    Some(tiff.into_inner())
+}
+
+fn insert_jpeg_exif(jpeg: Vec<u8>, tiff: &[u8]) -> Vec<u8> {
+    let length = 2 + 6 + tiff.len();
+    if jpeg.len() < 2 || length > u16::MAX as usize {
+        return jpeg;
+    }
+    let mut output = Vec::with_capacity(jpeg.len() + length + 2);
+    output.extend_from_slice(&jpeg[..2]);
+    output.extend_from_slice(&[0xFF, 0xE1]);
+    output.extend_from_slice(&(length as u16).to_be_bytes());
+    output.extend_from_slice(b"Exif\0\0");
+    output.extend_from_slice(tiff);
+    output.extend_from_slice(&jpeg[2..]);
+    output
+}
+
+pub fn derivative_sizes(config: &PhotoConfig, source_width: u32) -> Vec<PhotoSize> {
+    let mut sizes: Vec<PhotoSize> = config.sizes.clone();
+    sizes.sort_by_key(|size| size.width);
+    let mut derivatives: Vec<PhotoSize> = vec![];
+    for size in sizes {
+        let width = size.width.min(source_width);
+        if derivatives.iter().any(|d| d.width == width) {
+            continue;
+        }
+        derivatives.push(PhotoSize {
+            label: size.label,
+            width,
+        });
+    }
+    derivatives
+}
+
+pub fn process_photo(
+    config: &PhotoConfig,
+    bytes: &[u8],
+    output_dir: &Path,
+    base_name: &str,
+) -> Result<ProcessedPhoto, String> {
+    let exif = read_exif(bytes);
+    let mut image = decode(bytes)?;
+    let mut kept_exif = None;
+    if let Some(exif) = &exif {
+        image = apply_orientation(image, orientation(exif));
+        if config.keep_camera_info {
+            kept_exif = camera_info(exif);
+        }
+    }
+    let mut created: Vec<PathBuf> = vec![];
+    let result = write_derivatives(
+        config,
+        &image,
+        kept_exif.as_deref(),
+        output_dir,
+        base_name,
+        &mut created,
+    );
+    if result.is_err() {
+        for path in created {
+            let _ = remove_file(path);
+        }
+    }
+    result
+}
+
+fn write_derivatives(
+    config: &PhotoConfig,
+    image: &DynamicImage,
+    exif: Option<&[u8]>,
+    output_dir: &Path,
+    base_name: &str,
+    created: &mut Vec<PathBuf>,
+) -> Result<ProcessedPhoto, String> {
+    let mut formats = vec![PhotoFormat::Jpeg];
+    if config.webp {
+        formats.push(PhotoFormat::WebP);
+    }
+    if config.avif {
+        formats.push(PhotoFormat::Avif);
+    }
+
+    let mut variants = vec![];
+    for size in derivative_sizes(config, image.width()) {
+        let resized = if size.width < image.width() {
+            image.resize(size.width, u32::MAX, FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+        for format in formats.iter() {
+            let mut encoded = encode(config, *format, &resized)
+                .map_err(|e| format!("Cannot encode {} image: {}", format.extension(), e))?;
+            if let (PhotoFormat::Jpeg, Some(exif)) = (format, exif) {
+                encoded = insert_jpeg_exif(encoded, exif);
+            }
+            let filename = format!("{}_{}.{}", base_name, size.label, format.extension());
+            let dest = output_dir.join(&filename);
+            created.push(dest.clone());
+            write(&dest, &encoded).map_err(|e| format!("Cannot write image: {}", e))?;
+            variants.push(MediaVariant {
+                path: filename,
+                mime: String::from(format.mime()),
+                label: size.label.clone(),
+                width: Some(resized.width()),
+                height: Some(resized.height()),
+            });
+        }
+    }
+
+    let content = variants
+        .iter()
+        .rev()
+        .find(|variant| variant.mime == PhotoFormat::Jpeg.mime())
+        .map(|variant| variant.path.clone())
+        .ok_or_else(|| String::from("No image derivatives configured"))?;
+    Ok(ProcessedPhoto { content, variants })
+}
+
+fn encode(
+    config: &PhotoConfig,
+    format: PhotoFormat,
+    image: &DynamicImage,
+) -> Result<Vec<u8>, ImageError> {
+    let (width, height) = (image.width(), image.height());
+    let (data, color_type) = if !image.color().has_alpha() {
+        (image.to_rgb8().into_raw(), ColorType::Rgb8)
+    } else if format == PhotoFormat::Jpeg {
+        (flatten(&image.to_rgba8()).into_raw(), ColorType::Rgb8)
+    } else {
+        (image.to_rgba8().into_raw(), ColorType::Rgba8)
+    };
+    let mut buffer: Vec<u8> = vec![];
+    match format {
+        PhotoFormat::Jpeg => JpegEncoder::new_with_quality(&mut buffer, config.jpeg_quality)
+            .write_image(&data, width, height, color_type)?,
+        PhotoFormat::WebP => webp_encoder(&mut buffer, config.webp_quality)
+            .write_image(&data, width, height, color_type)?,
+        PhotoFormat::Avif => {
+            AvifEncoder::new_with_speed_quality(&mut buffer, config.avif_speed, config.avif_quality)
+                .write_image(&data, width, height, color_type)?
+        }
+    }
+    Ok(buffer)
+}
+
+// JPEG has no alpha channel, so transparent pixels are composited onto white
+// instead of whatever colour the decoder left behind them.
+fn flatten(image: &RgbaImage) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32) + 127) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+// Lossy WebP is deprecated upstream in favour of lossless, which is far too
+// large for photos.
+#[allow(deprecated)]
+fn webp_encoder(buffer: &mut Vec<u8>, quality: u8) -> WebPEncoder<&mut Vec<u8>> {
+    WebPEncoder::new_with_quality(buffer, WebPQuality::lossy(quality))
+}
@@ -0,0 +1,158 @@
+use rocket::serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const MAX_REPLY_LENGTH: usize = 4096;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ScanConfig {
+    pub enabled: bool,
+    pub address: String,
+    pub fail_open: bool,
+    pub timeout: u64,
+    pub quarantine_dir: String,
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        ScanConfig {
+            enabled: false,
+            address: String::from("127.0.0.1:3310"),
+            fail_open: false,
+            timeout: 30,
+            quarantine_dir: std::env::temp_dir()
+                .join("quarantine")
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ScanResult {
+    Clean,
+    Infected(String),
+}
+
+pub async fn check_upload(config: &ScanConfig, path: &Path) -> Result<ScanResult, String> {
+    if !config.enabled {
+        return Ok(ScanResult::Clean);
+    }
+    match scan_file(config, path).await {
+        Err(e) if config.fail_open => {
+            log::warn!("Skipping malware scan of {}: {}", path.display(), e);
+            Ok(ScanResult::Clean)
+        }
+        result => result,
+    }
+}
+
+pub async fn scan_file(config: &ScanConfig, path: &Path) -> Result<ScanResult, String> {
+    let timeout = Duration::from_secs(config.timeout.max(1));
+    let reply = tokio::time::timeout(timeout, request_scan(&config.address, path))
+        .await
+        .map_err(|_| {
+            format!(
+                "Scanner did not answer within {} seconds",
+                timeout.as_secs()
+            )
+        })??;
+    parse_reply(&reply)
+}
+
+pub fn parse_reply(reply: &str) -> Result<ScanResult, String> {
+    let reply = reply.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+    let result = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if result == "OK" {
+        return Ok(ScanResult::Clean);
+    }
+    if let Some(signature) = result.strip_suffix(" FOUND") {
+        return Ok(ScanResult::Infected(String::from(signature.trim())));
+    }
+    Err(format!("Scanner error: {}", result))
+}
+
+pub async fn quarantine(config: &ScanConfig, path: &Path, name: &str) -> Result<PathBuf, String> {
+    let dir = PathBuf::from(&config.quarantine_dir);
+    fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let dest = dir.join(name);
+    if fs::rename(path, &dest).await.is_err() {
+        fs::copy(path, &dest)
+            .await
+            .map_err(|e| format!("Cannot quarantine {}: {}", path.display(), e))?;
+        let _ = fs::remove_file(path).await;
+    }
+    Ok(dest)
+}
+
+async fn request_scan(address: &str, path: &Path) -> Result<String, String> {
+    #[cfg(unix)]
+    {
+        if let Some(socket) = address.strip_prefix("unix:") {
+            let stream = UnixStream::connect(socket)
+                .await
+                .map_err(|e| format!("Cannot connect to scanner at {}: {}", address, e))?;
+            return instream(stream, path).await;
+        }
+    }
+    let stream = TcpStream::connect(address.strip_prefix("tcp://").unwrap_or(address))
+        .await
+        .map_err(|e| format!("Cannot connect to scanner at {}: {}", address, e))?;
+    instream(stream, path).await
+}
+
+async fn instream<S>(mut stream: S, path: &Path) -> Result<String, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let write_err = |e: std::io::Error| format!("Cannot send file to scanner: {}", e);
+    let mut file = File::open(path)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    stream.write_all(b"zINSTREAM\0").await.map_err(write_err)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        stream
+            .write_all(&(read as u32).to_be_bytes())
+            .await
+            .map_err(write_err)?;
+        stream.write_all(&buffer[..read]).await.map_err(write_err)?;
+    }
+    stream.write_all(&[0; 4]).await.map_err(write_err)?;
+    stream.flush().await.map_err(write_err)?;
+
+    let mut reply = vec![];
+    let mut byte = [0; 1];
+    while reply.len() < MAX_REPLY_LENGTH {
+        let read = stream
+            .read(&mut byte)
+            .await
+            .map_err(|e| format!("Cannot read scanner reply: {}", e))?;
+        if read == 0 || byte[0] == 0 {
+            break;
+        }
+        reply.push(byte[0]);
+    }
+    if reply.is_empty() {
+        return Err(String::from(
+            "Scanner closed the connection without a reply",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&reply).to_string())
+}
@@ -0,0 +1,299 @@
+use roxmltree::{Document, Node, NodeType};
+use std::fmt::Write;
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "svg",
+    "g",
+    "defs",
+    "title",
+    "desc",
+    "symbol",
+    "use",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "textPath",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "pattern",
+    "clipPath",
+    "mask",
+    "marker",
+    "image",
+    "filter",
+    "feBlend",
+    "feColorMatrix",
+    "feComposite",
+    "feFlood",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feOffset",
+];
+
+const TEXT_ELEMENTS: &[&str] = &["title", "desc", "text", "tspan", "textPath"];
+
+const ALLOWED_ATTRIBUTES: &[&str] = &[
+    "id",
+    "class",
+    "style",
+    "transform",
+    "viewBox",
+    "preserveAspectRatio",
+    "version",
+    "width",
+    "height",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "fx",
+    "fy",
+    "d",
+    "points",
+    "pathLength",
+    "dx",
+    "dy",
+    "rotate",
+    "textLength",
+    "lengthAdjust",
+    "startOffset",
+    "text-anchor",
+    "dominant-baseline",
+    "font-family",
+    "font-size",
+    "font-style",
+    "font-weight",
+    "letter-spacing",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "stroke",
+    "stroke-dasharray",
+    "stroke-dashoffset",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "stroke-miterlimit",
+    "stroke-opacity",
+    "stroke-width",
+    "opacity",
+    "color",
+    "display",
+    "visibility",
+    "clip-path",
+    "clip-rule",
+    "mask",
+    "filter",
+    "marker-start",
+    "marker-mid",
+    "marker-end",
+    "offset",
+    "stop-color",
+    "stop-opacity",
+    "gradientUnits",
+    "gradientTransform",
+    "spreadMethod",
+    "patternUnits",
+    "patternContentUnits",
+    "patternTransform",
+    "clipPathUnits",
+    "maskUnits",
+    "maskContentUnits",
+    "markerUnits",
+    "markerWidth",
+    "markerHeight",
+    "refX",
+    "refY",
+    "orient",
+    "filterUnits",
+    "primitiveUnits",
+    "in",
+    "in2",
+    "result",
+    "mode",
+    "type",
+    "values",
+    "operator",
+    "k1",
+    "k2",
+    "k3",
+    "k4",
+    "stdDeviation",
+    "flood-color",
+    "flood-opacity",
+    "href",
+];
+
+const RASTER_DATA_PREFIXES: &[&str] = &[
+    "data:image/png;",
+    "data:image/jpeg;",
+    "data:image/gif;",
+    "data:image/webp;",
+];
+
+pub fn sanitize(svg: &str) -> Result<String, String> {
+    let document = Document::parse(svg).map_err(|e| format!("Invalid SVG: {}", e))?;
+    let root = document.root_element();
+    if root.tag_name().namespace() != Some(SVG_NAMESPACE) || root.tag_name().name() != "svg" {
+        return Err(String::from("Document is not an SVG image"));
+    }
+    let mut output = String::new();
+    write_element(&mut output, root, true);
+    Ok(output)
+}
+
+fn write_element(output: &mut String, node: Node, is_root: bool) {
+    let name = node.tag_name().name();
+    if node.tag_name().namespace() != Some(SVG_NAMESPACE) {
+        return;
+    }
+    if name == "a" {
+        write_children(output, node, false);
+        return;
+    }
+    if !ALLOWED_ELEMENTS.contains(&name) {
+        return;
+    }
+
+    output.push('<');
+    output.push_str(name);
+    if is_root {
+        let _ = write!(output, " xmlns=\"{}\"", SVG_NAMESPACE);
+        let _ = write!(output, " xmlns:xlink=\"{}\"", XLINK_NAMESPACE);
+    }
+    for attribute in node.attributes() {
+        let attribute_name = attribute.name();
+        let is_xlink = attribute.namespace() == Some(XLINK_NAMESPACE);
+        if attribute.namespace().is_some() && !is_xlink {
+            continue;
+        }
+        if !ALLOWED_ATTRIBUTES.contains(&attribute_name) {
+            continue;
+        }
+        if is_xlink && attribute_name != "href" {
+            continue;
+        }
+        let value = attribute.value();
+        let allowed = match attribute_name {
+            "href" => is_safe_reference(name, value),
+            "style" => is_safe_style(value),
+            _ => is_safe_value(value),
+        };
+        if !allowed {
+            continue;
+        }
+        let prefix = if is_xlink { "xlink:" } else { "" };
+        let _ = write!(
+            output,
+            " {}{}=\"{}\"",
+            prefix,
+            attribute_name,
+            escape(value)
+        );
+    }
+    output.push('>');
+    write_children(output, node, TEXT_ELEMENTS.contains(&name));
+    let _ = write!(output, "</{}>", name);
+}
+
+fn write_children(output: &mut String, node: Node, allow_text: bool) {
+    for child in node.children() {
+        match child.node_type() {
+            NodeType::Element => write_element(output, child, false),
+            NodeType::Text if allow_text => output.push_str(&escape(child.text().unwrap_or(""))),
+            _ => {}
+        }
+    }
+}
+
+fn is_safe_reference(element: &str, value: &str) -> bool {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return true;
+    }
+    element == "image"
+        && RASTER_DATA_PREFIXES
+            .iter()
+            .any(|prefix| value.to_ascii_lowercase().starts_with(prefix))
+}
+
+fn is_safe_value(value: &str) -> bool {
+    let lowered: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if lowered.contains("javascript:") || lowered.contains("data:") {
+        return false;
+    }
+    let mut rest = lowered.as_str();
+    while let Some(index) = rest.find("url(") {
+        rest = &rest[index + 4..];
+        let target = rest.trim_start_matches(|c| c == '\'' || c == '"');
+        if !target.starts_with('#') {
+            return false;
+        }
+    }
+    true
+}
+
+fn is_safe_style(value: &str) -> bool {
+    let lowered = value.to_ascii_lowercase();
+    !lowered.contains('\\')
+        && !lowered.contains("@import")
+        && !lowered.contains("expression")
+        && is_safe_value(value)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn rasterize(svg: &str, max_width: u32) -> Result<Vec<u8>, String> {
+    let mut options = usvg::Options::default();
+    options.fontdb.load_system_fonts();
+    let tree = usvg::Tree::from_str(svg, &options.to_ref())
+        .map_err(|e| format!("Cannot render SVG: {}", e))?;
+    let size = tree.svg_node().size.to_screen_size();
+    let fit_to = if size.width() > max_width {
+        usvg::FitTo::Width(max_width)
+    } else {
+        usvg::FitTo::Original
+    };
+    let target = fit_to
+        .fit_to(size)
+        .ok_or_else(|| String::from("Cannot render an empty SVG"))?;
+    let mut pixmap = tiny_skia::Pixmap::new(target.width(), target.height())
+        .ok_or_else(|| String::from("Cannot allocate SVG canvas"))?;
+    resvg::render(
+        &tree,
+        fit_to,
+        tiny_skia::Transform::default(),
+        pixmap.as_mut(),
+    )
+    .ok_or_else(|| String::from("Cannot render SVG"))?;
+    pixmap
+        .encode_png()
+        .map_err(|e| format!("Cannot encode PNG: {}", e))
+}
@@ -0,0 +1,35 @@
+use super::post::Post;
+use crate::traits::DisplayPostContent;
+
+pub struct AlbumPost<'a>(&'a Post);
+
+impl<'a> AlbumPost<'a> {
+    pub fn new(post: &'a Post) -> Self {
+        AlbumPost(post)
+    }
+
+    pub fn items_html(&self) -> Vec<String> {
+        self.0
+            .media
+            .0
+            .iter()
+            .map(|item| item.to_post(self.0).to_media().raw_html())
+            .collect()
+    }
+}
+
+impl<'a> DisplayPostContent for AlbumPost<'a> {
+    fn raw_html(&self) -> String {
+        let items = self
+            .items_html()
+            .into_iter()
+            .map(|html| {
+                format!(
+                    r#"<div class="col-sm-12 col-md-6 album-item">{}</div>"#,
+                    html
+                )
+            })
+            .collect::<String>();
+        format!(r#"<div class="row album">{}</div>"#, items)
+    }
+}
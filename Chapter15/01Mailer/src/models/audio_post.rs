@@ -0,0 +1,86 @@
+use super::post::{Post, REJECTED_HTML};
+use super::processing_status::ProcessingStatus;
+use crate::traits::DisplayPostContent;
+
+pub struct AudioPost<'a>(&'a Post);
+
+impl<'a> AudioPost<'a> {
+    pub fn new(post: &'a Post) -> Self {
+        AudioPost(post)
+    }
+
+    pub fn format_duration(duration: f64) -> String {
+        let seconds = duration.round().max(0.0) as u64;
+        if seconds >= 3600 {
+            format!(
+                "{}:{:02}:{:02}",
+                seconds / 3600,
+                seconds % 3600 / 60,
+                seconds % 60
+            )
+        } else {
+            format!("{}:{:02}", seconds / 60, seconds % 60)
+        }
+    }
+}
+
+impl<'a> DisplayPostContent for AudioPost<'a> {
+    fn raw_html(&self) -> String {
+        match self.0.processing_status {
+            ProcessingStatus::Queued | ProcessingStatus::Processing => {
+                return String::from(
+                    "<figure><img src=\"/assets/loading.gif\" class=\"section media\"/></figure>",
+                );
+            }
+            ProcessingStatus::Failed => {
+                return String::from(
+                    "<figure class=\"section media\"><figcaption>This audio could not be processed.</figcaption></figure>",
+                );
+            }
+            ProcessingStatus::Rejected => {
+                return String::from(REJECTED_HTML);
+            }
+            ProcessingStatus::Ready => {}
+        }
+        let waveform = match &self.0.poster {
+            Some(poster) => format!(
+                "<img src=\"{}\" alt=\"Waveform\" loading=\"lazy\" class=\"section media\"/>\n",
+                poster
+            ),
+            None => String::new(),
+        };
+        let sources = if self.0.variants.0.is_empty() {
+            format!(
+                "    <source src=\"{}\" type=\"audio/mp4\">\n",
+                self.0.content
+            )
+        } else {
+            self.0
+                .variants
+                .0
+                .iter()
+                .map(|variant| {
+                    format!(
+                        "    <source src=\"{}\" type=\"{}\">\n",
+                        variant.path, variant.mime
+                    )
+                })
+                .collect::<String>()
+        };
+        let duration = match self.0.duration {
+            Some(duration) => format!(
+                "<figcaption>{}</figcaption>\n",
+                Self::format_duration(duration)
+            ),
+            None => String::new(),
+        };
+        format!(
+            r#"<figure class="section media">
+{}<audio controls preload="metadata">
+{}    Your browser does not support the audio element.
+</audio>
+{}</figure>"#,
+            waveform, sources, duration
+        )
+    }
+}
@@ -0,0 +1,179 @@
+use super::export_status::ExportStatus;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::offset::Utc;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Export {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub status: ExportStatus,
+    #[serde(skip_serializing)]
+    pub storage_key: Option<String>,
+    pub size: i64,
+    pub error: Option<String>,
+    pub created_at: OurDateTime,
+    pub completed_at: Option<OurDateTime>,
+    pub expires_at: Option<OurDateTime>,
+}
+
+impl Export {
+    pub fn is_downloadable(&self) -> bool {
+        self.status == ExportStatus::Ready
+            && self.storage_key.is_some()
+            && self
+                .expires_at
+                .as_ref()
+                .map_or(false, |expires_at| expires_at.0 > Utc::now())
+    }
+
+    pub fn filename(&self) -> String {
+        format!(
+            "our_application-export-{}.zip",
+            self.created_at.0.format("%Y%m%d%H%M%S")
+        )
+    }
+
+    pub async fn create(connection: &mut PgConnection, user_uuid: &Uuid) -> Result<Self, OurError> {
+        let query_str = "INSERT INTO exports (uuid, user_uuid) VALUES ($1, $2) RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find(
+        connection: &mut PgConnection,
+        uuid: &str,
+        user_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM exports WHERE uuid = $1 AND user_uuid = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_pending(
+        connection: &mut PgConnection,
+        uuid: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM exports WHERE uuid = $1 AND status = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(ExportStatus::Pending)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM exports WHERE user_uuid = $1 ORDER BY created_at DESC";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn has_pending(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<bool, OurError> {
+        let query_str = "SELECT EXISTS(SELECT 1 FROM exports WHERE user_uuid = $1 AND status = $2)";
+        Ok(sqlx::query_scalar(query_str)
+            .bind(user_uuid)
+            .bind(ExportStatus::Pending)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn complete(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        storage_key: &str,
+        size: i64,
+        expires_at: &OurDateTime,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE exports
+SET status = $1, storage_key = $2, size = $3, error = NULL, completed_at = NOW(), expires_at = $4
+WHERE uuid = $5
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(ExportStatus::Ready)
+            .bind(storage_key)
+            .bind(size)
+            .bind(expires_at)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn fail(
+        connection: &mut PgConnection,
+        uuid: &str,
+        error: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE exports
+SET status = $1, error = $2, completed_at = NOW()
+WHERE uuid = $3 AND status = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(ExportStatus::Failed)
+            .bind(error)
+            .bind(parsed_uuid)
+            .bind(ExportStatus::Pending)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_expired(connection: &mut PgConnection) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM exports WHERE expires_at <= CURRENT_TIMESTAMP";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy_expired(connection: &mut PgConnection) -> Result<Vec<Self>, OurError> {
+        let query_str = "DELETE FROM exports WHERE expires_at <= CURRENT_TIMESTAMP RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy_for_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<String>, OurError> {
+        let query_str = "DELETE FROM exports WHERE user_uuid = $1 RETURNING storage_key";
+        let keys: Vec<Option<String>> = sqlx::query_scalar(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(keys.into_iter().flatten().collect())
+    }
+}
+
+#[derive(FromForm)]
+pub struct RequestExport<'r> {
+    pub authenticity_token: &'r str,
+}
@@ -0,0 +1,21 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum ExportStatus {
+    Pending = 0,
+    Ready = 1,
+    Failed = 2,
+}
+
+impl fmt::Display for ExportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExportStatus::Pending => write!(f, "Pending"),
+            ExportStatus::Ready => write!(f, "Ready"),
+            ExportStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
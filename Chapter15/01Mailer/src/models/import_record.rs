@@ -0,0 +1,70 @@
+use super::import_status::ImportStatus;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+#[derive(Debug, FromRow)]
+pub struct ImportRecord {
+    pub source: String,
+    pub record_key: String,
+    pub status: ImportStatus,
+    pub target_uuid: Option<Uuid>,
+    pub error: Option<String>,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+}
+
+impl ImportRecord {
+    pub async fn find(
+        connection: &mut PgConnection,
+        source: &str,
+        record_key: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = "SELECT * FROM import_records WHERE source = $1 AND record_key = $2";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(source)
+            .bind(record_key)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn source_exists(
+        connection: &mut PgConnection,
+        source: &str,
+    ) -> Result<bool, OurError> {
+        let query_str = "SELECT EXISTS(SELECT 1 FROM import_records WHERE source = $1)";
+        Ok(sqlx::query_scalar(query_str)
+            .bind(source)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn save(
+        connection: &mut PgConnection,
+        source: &str,
+        record_key: &str,
+        status: ImportStatus,
+        target_uuid: Option<&Uuid>,
+        error: Option<&str>,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO import_records
+(source, record_key, status, target_uuid, error)
+VALUES
+($1, $2, $3, $4, $5)
+ON CONFLICT (source, record_key) DO UPDATE
+SET status = $3, target_uuid = COALESCE($4, import_records.target_uuid), error = $5, updated_at = NOW()
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(source)
+            .bind(record_key)
+            .bind(status)
+            .bind(target_uuid)
+            .bind(error)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
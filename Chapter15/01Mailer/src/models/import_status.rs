@@ -0,0 +1,19 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum ImportStatus {
+    Imported = 0,
+    Failed = 1,
+}
+
+impl fmt::Display for ImportStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ImportStatus::Imported => write!(f, "Imported"),
+            ImportStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
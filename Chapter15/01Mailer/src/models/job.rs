@@ -0,0 +1,231 @@
+use super::job_status::JobStatus;
+use super::job_type::JobType;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use chrono::offset::Utc;
+use rocket::serde::json::{serde_json, Value};
+use rocket::serde::{DeserializeOwned, Serialize};
+use rocket_db_pools::sqlx::{types::Json, FromRow, PgConnection};
+use uuid::Uuid;
+
+pub const STALE_ERROR: &str = "Worker stopped responding while running the job";
+
+#[derive(Debug, FromRow)]
+pub struct Job {
+    pub uuid: Uuid,
+    pub job_type: JobType,
+    pub payload: Json<Value>,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub run_at: OurDateTime,
+    pub locked_at: Option<OurDateTime>,
+    pub locked_by: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: OurDateTime,
+    pub updated_at: OurDateTime,
+}
+
+impl Job {
+    pub async fn enqueue<T: Serialize>(
+        connection: &mut PgConnection,
+        job_type: JobType,
+        payload: &T,
+    ) -> Result<Self, OurError> {
+        Self::enqueue_at(connection, job_type, payload, &OurDateTime(Utc::now())).await
+    }
+
+    pub async fn enqueue_at<T: Serialize>(
+        connection: &mut PgConnection,
+        job_type: JobType,
+        payload: &T,
+        run_at: &OurDateTime,
+    ) -> Result<Self, OurError> {
+        let value = serde_json::to_value(payload).map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Cannot serialize job"),
+                Some(Box::new(e)),
+            )
+        })?;
+        let uuid = Uuid::new_v4();
+        let query_str = r#"INSERT INTO jobs
+(uuid, job_type, payload, run_at)
+VALUES
+($1, $2, $3, $4)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(job_type)
+            .bind(Json(value))
+            .bind(run_at)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn claim(
+        connection: &mut PgConnection,
+        worker_id: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = r#"UPDATE jobs
+SET status = $1, attempts = attempts + 1, locked_at = NOW(), locked_by = $2, updated_at = NOW()
+WHERE uuid = (
+    SELECT uuid FROM jobs
+    WHERE status = $3 AND run_at <= NOW()
+    ORDER BY run_at
+    LIMIT 1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(JobStatus::Running)
+            .bind(worker_id)
+            .bind(JobStatus::Queued)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // Completing and failing only touch a job while this worker still holds
+    // its lease. They return `false` / `None` when the lease was recovered as
+    // stale, and possibly claimed by another worker, in the meantime.
+    pub async fn complete(&self, connection: &mut PgConnection) -> Result<bool, OurError> {
+        let query_str = r#"UPDATE jobs
+SET status = $1, locked_at = NULL, locked_by = NULL, last_error = NULL, updated_at = NOW()
+WHERE uuid = $2 AND status = $3 AND locked_by = $4"#;
+        let result = sqlx::query(query_str)
+            .bind(JobStatus::Completed)
+            .bind(&self.uuid)
+            .bind(JobStatus::Running)
+            .bind(&self.locked_by)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn fail(
+        &self,
+        connection: &mut PgConnection,
+        error: &str,
+        max_attempts: i32,
+        backoff_seconds: u64,
+    ) -> Result<Option<JobStatus>, OurError> {
+        let status = if self.attempts >= max_attempts {
+            JobStatus::Dead
+        } else {
+            JobStatus::Queued
+        };
+        let delay = backoff_seconds.saturating_mul(1u64 << (self.attempts.max(1) - 1).min(16));
+        let query_str = r#"UPDATE jobs
+SET status = $1, run_at = NOW() + ($2 * INTERVAL '1 second'), locked_at = NULL, locked_by = NULL,
+    last_error = $3, updated_at = NOW()
+WHERE uuid = $4 AND status = $5 AND locked_by = $6"#;
+        let result = sqlx::query(query_str)
+            .bind(status)
+            .bind(delay as f64)
+            .bind(error)
+            .bind(&self.uuid)
+            .bind(JobStatus::Running)
+            .bind(&self.locked_by)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(Some(status).filter(|_| result.rows_affected() > 0))
+    }
+
+    pub async fn recover_stale(
+        connection: &mut PgConnection,
+        lease_seconds: u64,
+        max_attempts: i32,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"UPDATE jobs
+SET status = CASE WHEN attempts >= $1 THEN $2 ELSE $3 END, locked_at = NULL, locked_by = NULL,
+    last_error = $4, updated_at = NOW()
+WHERE status = $5 AND locked_at < NOW() - ($6 * INTERVAL '1 second')
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(max_attempts)
+            .bind(JobStatus::Dead)
+            .bind(JobStatus::Queued)
+            .bind(STALE_ERROR)
+            .bind(JobStatus::Running)
+            .bind(lease_seconds as f64)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn heartbeat(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        worker_id: &str,
+    ) -> Result<(), OurError> {
+        let query_str =
+            "UPDATE jobs SET locked_at = NOW() WHERE uuid = $1 AND status = $2 AND locked_by = $3";
+        sqlx::query(query_str)
+            .bind(uuid)
+            .bind(JobStatus::Running)
+            .bind(worker_id)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn requeue_dead(
+        connection: &mut PgConnection,
+        job_type: JobType,
+        payload_uuid: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = r#"UPDATE jobs
+SET status = $1, attempts = 0, run_at = NOW(), last_error = NULL, updated_at = NOW()
+WHERE uuid = (
+    SELECT uuid FROM jobs
+    WHERE job_type = $2 AND status = $3
+    AND (payload->>'uuid' = $4 OR payload->>'media_uuid' = $4)
+    ORDER BY created_at DESC
+    LIMIT 1
+)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(JobStatus::Queued)
+            .bind(job_type)
+            .bind(JobStatus::Dead)
+            .bind(payload_uuid)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn cancel_for_post(
+        connection: &mut PgConnection,
+        post_uuid: &str,
+    ) -> Result<Vec<String>, OurError> {
+        let query_str = r#"DELETE FROM jobs
+WHERE status <> $1 AND payload->>'uuid' = $2
+RETURNING payload->>'orig_filename'"#;
+        let keys: Vec<Option<String>> = sqlx::query_scalar(query_str)
+            .bind(JobStatus::Running)
+            .bind(post_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(keys.into_iter().flatten().collect())
+    }
+
+    pub async fn pending_upload_keys(
+        connection: &mut PgConnection,
+    ) -> Result<Vec<String>, OurError> {
+        let query_str = r#"SELECT payload->>'orig_filename' FROM jobs
+WHERE status <> $1 AND payload->>'orig_filename' IS NOT NULL"#;
+        Ok(sqlx::query_scalar(query_str)
+            .bind(JobStatus::Completed)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub fn parse_payload<T: DeserializeOwned>(&self) -> Result<T, String> {
+        serde_json::from_value(self.payload.0.clone()).map_err(|e| e.to_string())
+    }
+}
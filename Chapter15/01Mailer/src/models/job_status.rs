@@ -0,0 +1,23 @@
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum JobStatus {
+    Queued = 0,
+    Running = 1,
+    Completed = 2,
+    Dead = 3,
+}
+
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            JobStatus::Queued => write!(f, "Queued"),
+            JobStatus::Running => write!(f, "Running"),
+            JobStatus::Completed => write!(f, "Completed"),
+            JobStatus::Dead => write!(f, "Dead"),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+
+#[derive(sqlx::Type, Debug, Serialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum JobType {
+    ProcessVideo = 0,
+    ProcessAudio = 1,
+    FetchLinkPreview = 2,
+    PublishPost = 3,
+    PurgePost = 4,
+    PurgeUser = 5,
+    ExportData = 6,
+}
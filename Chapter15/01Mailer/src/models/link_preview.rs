@@ -0,0 +1,163 @@
+use super::escape_html;
+use super::link_preview_status::LinkPreviewStatus;
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use crate::media::link_preview::PreviewMetadata;
+use reqwest::Url;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+#[derive(FromRow, Serialize, Deserialize, Clone, Debug)]
+pub struct LinkPreview {
+    pub url: String,
+    pub status: LinkPreviewStatus,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+    pub source: Option<String>,
+    #[serde(skip_serializing, default)]
+    pub error: Option<String>,
+    pub fetched_at: Option<OurDateTime>,
+    pub expires_at: OurDateTime,
+    pub created_at: OurDateTime,
+}
+
+impl LinkPreview {
+    pub fn to_html(&self) -> Option<String> {
+        if self.status != LinkPreviewStatus::Ready {
+            return None;
+        }
+        let title = self.title.as_ref()?;
+        let site_name = self.site_name.clone().or_else(|| {
+            Url::parse(&self.url)
+                .ok()
+                .and_then(|url| url.host_str().map(String::from))
+        });
+        let image = self
+            .image_url
+            .as_ref()
+            .map(|image| {
+                format!(
+                    r#"<img src="{}" alt="" loading="lazy" referrerpolicy="no-referrer" class="section media"/>"#,
+                    escape_html(image)
+                )
+            })
+            .unwrap_or_default();
+        let site_name = site_name
+            .map(|site_name| format!("<small>{}</small>", escape_html(&site_name)))
+            .unwrap_or_default();
+        let description = self
+            .description
+            .as_ref()
+            .map(|description| format!("<p>{}</p>", escape_html(description)))
+            .unwrap_or_default();
+        Some(format!(
+            r#"<a href="{}" class="card fluid link-preview" rel="nofollow noopener ugc" target="_blank">{}<div class="section"><h4>{}{}</h4>{}</div></a>"#,
+            escape_html(&self.url),
+            image,
+            escape_html(title),
+            site_name,
+            description
+        ))
+    }
+
+    pub async fn request(
+        connection: &mut PgConnection,
+        url: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = r#"INSERT INTO link_previews (url, status, expires_at)
+VALUES ($1, $2, NOW())
+ON CONFLICT (url) DO UPDATE
+SET status = $2, error = NULL, expires_at = NOW()
+WHERE link_previews.expires_at < NOW() AND link_previews.status <> $2
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(url)
+            .bind(LinkPreviewStatus::Pending)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        connection: &mut PgConnection,
+        urls: &[String],
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM link_previews WHERE url = ANY($1)";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(urls)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn complete(
+        connection: &mut PgConnection,
+        url: &str,
+        metadata: &PreviewMetadata,
+        ttl: u64,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"UPDATE link_previews
+SET status = $1, title = $2, description = $3, image_url = $4, site_name = $5, source = $6,
+    error = NULL, fetched_at = NOW(), expires_at = NOW() + ($7 * INTERVAL '1 second')
+WHERE url = $8
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(LinkPreviewStatus::Ready)
+            .bind(&metadata.title)
+            .bind(&metadata.description)
+            .bind(&metadata.image_url)
+            .bind(&metadata.site_name)
+            .bind(&metadata.source)
+            .bind(ttl as f64)
+            .bind(url)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn fail(
+        connection: &mut PgConnection,
+        url: &str,
+        error: &str,
+        retry_after: u64,
+    ) -> Result<(), OurError> {
+        let query_str = r#"UPDATE link_previews
+SET status = $1, error = $2, fetched_at = NOW(), expires_at = NOW() + ($3 * INTERVAL '1 second')
+WHERE url = $4"#;
+        sqlx::query(query_str)
+            .bind(LinkPreviewStatus::Failed)
+            .bind(error)
+            .bind(retry_after as f64)
+            .bind(url)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn image_urls(connection: &mut PgConnection) -> Result<Vec<String>, OurError> {
+        let query_str = "SELECT image_url FROM link_previews WHERE image_url IS NOT NULL";
+        Ok(sqlx::query_scalar(query_str)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn attach(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        url: Option<&str>,
+    ) -> Result<(), OurError> {
+        let query_str = "UPDATE posts SET link_url = $1 WHERE uuid = $2";
+        sqlx::query(query_str)
+            .bind(url)
+            .bind(post_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+}
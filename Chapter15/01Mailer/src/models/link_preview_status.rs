@@ -0,0 +1,21 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum LinkPreviewStatus {
+    Pending = 0,
+    Ready = 1,
+    Failed = 2,
+}
+
+impl fmt::Display for LinkPreviewStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LinkPreviewStatus::Pending => write!(f, "Pending"),
+            LinkPreviewStatus::Ready => write!(f, "Ready"),
+            LinkPreviewStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
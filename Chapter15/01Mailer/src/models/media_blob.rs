@@ -0,0 +1,188 @@
+use super::our_date_time::OurDateTime;
+use crate::errors::our_error::OurError;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+// Advisory lock space for per-hash blob locks. It is separate from the
+// single-key space the media GC lock lives in.
+const BLOB_LOCK_SPACE: i32 = 0x626c_6f62;
+
+#[derive(Debug, FromRow)]
+pub struct MediaBlob {
+    pub hash: String,
+    pub key: String,
+    pub size: i64,
+    pub ref_count: i32,
+    pub created_at: OurDateTime,
+}
+
+impl MediaBlob {
+    pub fn key_for(hash: &str, extension: &str) -> String {
+        format!("blobs/{}/{}.{}", &hash[..2], hash, extension)
+    }
+
+    // Locks the blob for the rest of the transaction. Acquiring a blob holds
+    // the lock until the upload that may follow it commits, and deleting a
+    // released blob's file takes it too, so a file is never deleted while the
+    // blob is being taken again.
+    pub async fn lock(connection: &mut PgConnection, hash: &str) -> Result<(), OurError> {
+        sqlx::query("SELECT pg_advisory_xact_lock($1, hashtext($2))")
+            .bind(BLOB_LOCK_SPACE)
+            .bind(hash)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn acquire(
+        connection: &mut PgConnection,
+        hash: &str,
+        key: &str,
+        size: i64,
+    ) -> Result<(String, bool), OurError> {
+        Self::lock(connection, hash).await?;
+        let query_str = r#"INSERT INTO media_blobs
+(hash, key, size)
+VALUES
+($1, $2, $3)
+ON CONFLICT (hash) DO UPDATE SET ref_count = media_blobs.ref_count + 1
+RETURNING key, (xmax = 0) AS inserted"#;
+        Ok(sqlx::query_as::<_, (String, bool)>(query_str)
+            .bind(hash)
+            .bind(key)
+            .bind(size)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn link(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        hashes: &[String],
+    ) -> Result<(), OurError> {
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        let query_str = r#"WITH linked AS (
+    INSERT INTO post_blobs
+    (post_uuid, blob_hash)
+    SELECT $1, UNNEST($2::VARCHAR[])
+    ON CONFLICT DO NOTHING
+    RETURNING blob_hash
+)
+UPDATE media_blobs SET ref_count = ref_count - 1
+WHERE hash = ANY($2) AND hash NOT IN (SELECT blob_hash FROM linked)"#;
+        sqlx::query(query_str)
+            .bind(post_uuid)
+            .bind(hashes)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn release(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+    ) -> Result<Vec<String>, OurError> {
+        let query_str = "DELETE FROM post_blobs WHERE post_uuid = $1 RETURNING blob_hash";
+        let hashes: Vec<String> = sqlx::query_scalar(query_str)
+            .bind(post_uuid)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::dereference(connection, &hashes).await
+    }
+
+    pub async fn unlink(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        hashes: &[String],
+    ) -> Result<Vec<String>, OurError> {
+        if hashes.is_empty() {
+            return Ok(vec![]);
+        }
+        let query_str =
+            "DELETE FROM post_blobs WHERE post_uuid = $1 AND blob_hash = ANY($2) RETURNING blob_hash";
+        let hashes: Vec<String> = sqlx::query_scalar(query_str)
+            .bind(post_uuid)
+            .bind(hashes)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::dereference(connection, &hashes).await
+    }
+
+    async fn dereference(
+        connection: &mut PgConnection,
+        hashes: &[String],
+    ) -> Result<Vec<String>, OurError> {
+        if hashes.is_empty() {
+            return Ok(vec![]);
+        }
+        let query_str = "UPDATE media_blobs SET ref_count = ref_count - 1 WHERE hash = ANY($1)";
+        sqlx::query(query_str)
+            .bind(hashes)
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let query_str = r#"DELETE FROM media_blobs
+WHERE hash = ANY($1) AND ref_count <= 0
+AND NOT EXISTS (SELECT 1 FROM post_blobs WHERE blob_hash = media_blobs.hash)
+RETURNING key"#;
+        Ok(sqlx::query_scalar(query_str)
+            .bind(hashes)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn is_stored(connection: &mut PgConnection, key: &str) -> Result<bool, OurError> {
+        let query_str = "SELECT EXISTS(SELECT 1 FROM media_blobs WHERE key = $1)";
+        Ok(sqlx::query_scalar(query_str)
+            .bind(key)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn keys(connection: &mut PgConnection) -> Result<Vec<String>, OurError> {
+        Ok(sqlx::query_scalar("SELECT key FROM media_blobs")
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn recount(connection: &mut PgConnection) -> Result<u64, OurError> {
+        let query_str = r#"UPDATE media_blobs
+SET ref_count = counts.ref_count
+FROM (
+    SELECT media_blobs.hash, COUNT(post_blobs.blob_hash)::INTEGER AS ref_count
+    FROM media_blobs LEFT JOIN post_blobs ON post_blobs.blob_hash = media_blobs.hash
+    GROUP BY media_blobs.hash
+) AS counts
+WHERE media_blobs.hash = counts.hash AND media_blobs.ref_count <> counts.ref_count"#;
+        Ok(sqlx::query(query_str)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?
+            .rows_affected())
+    }
+
+    pub async fn destroy_orphaned(
+        connection: &mut PgConnection,
+        created_before: &OurDateTime,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"DELETE FROM media_blobs
+WHERE created_at < $1
+AND NOT EXISTS (SELECT 1 FROM post_blobs WHERE blob_hash = media_blobs.hash)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(created_before)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
@@ -0,0 +1,10 @@
+use rocket::serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MediaVariant {
+    pub path: String,
+    pub mime: String,
+    pub label: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
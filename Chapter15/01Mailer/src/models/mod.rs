@@ -1,16 +1,37 @@
 use ammonia::Builder;
 use std::collections::hash_set::HashSet;
 
+pub mod album_post;
+pub mod audio_post;
 pub mod bool_wrapper;
+pub mod export;
+pub mod export_status;
+pub mod import_record;
+pub mod import_status;
+pub mod job;
+pub mod job_status;
+pub mod job_type;
+pub mod link_preview;
+pub mod link_preview_status;
+pub mod media_blob;
+pub mod media_variant;
+pub mod notification;
+pub mod notification_type;
 pub mod our_date_time;
 pub mod pagination;
 pub mod photo_post;
 pub mod post;
+pub mod post_media;
 pub mod post_type;
+pub mod processing_status;
+pub mod publish_status;
+pub mod quota;
+pub mod resumable_upload;
 pub mod text_post;
 pub mod user;
 pub mod user_status;
 pub mod video_post;
+pub mod visibility;
 pub mod worker;
 
 pub fn clean_html(src: &str) -> String {
@@ -19,3 +40,18 @@ pub fn clean_html(src: &str) -> String {
         .clean(src)
         .to_string()
 }
+
+pub fn escape_html(src: &str) -> String {
+    let mut escaped = String::with_capacity(src.len());
+    for c in src.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,307 @@
+use super::bool_wrapper::BoolWrapper;
+use super::export::Export;
+use super::notification_type::NotificationType;
+use super::our_date_time::OurDateTime;
+use super::pagination::{Pagination, DEFAULT_LIMIT};
+use super::post::Post;
+use super::post_type::PostType;
+use super::user::User;
+use crate::errors::our_error::OurError;
+use crate::fairings::db::DBConnection;
+use chrono::offset::Utc;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx::{Acquire, FromRow, PgConnection};
+use rocket_db_pools::Connection;
+use uuid::Uuid;
+
+static MENTION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[^\w@])@(\w+)").unwrap());
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct Notification {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub actor_uuid: Option<Uuid>,
+    pub notification_type: NotificationType,
+    pub message: String,
+    pub link: Option<String>,
+    pub read_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+}
+
+pub enum NotificationEvent<'a> {
+    Mention {
+        user_uuid: &'a Uuid,
+        author: &'a User,
+        post: &'a Post,
+    },
+    VideoProcessed { post: &'a Post },
+    VideoFailed { post: &'a Post },
+    ExportReady { export: &'a Export },
+    ExportFailed { export: &'a Export },
+}
+
+impl<'a> NotificationEvent<'a> {
+    fn recipient(&self) -> &'a Uuid {
+        match *self {
+            NotificationEvent::Mention { user_uuid, .. } => user_uuid,
+            NotificationEvent::VideoProcessed { post } => &post.user_uuid,
+            NotificationEvent::VideoFailed { post } => &post.user_uuid,
+            NotificationEvent::ExportReady { export } => &export.user_uuid,
+            NotificationEvent::ExportFailed { export } => &export.user_uuid,
+        }
+    }
+
+    fn actor(&self) -> Option<&'a Uuid> {
+        match *self {
+            NotificationEvent::Mention { author, .. } => Some(&author.uuid),
+            NotificationEvent::VideoProcessed { .. }
+            | NotificationEvent::VideoFailed { .. }
+            | NotificationEvent::ExportReady { .. }
+            | NotificationEvent::ExportFailed { .. } => None,
+        }
+    }
+
+    fn notification_type(&self) -> NotificationType {
+        match *self {
+            NotificationEvent::Mention { .. } => NotificationType::Mention,
+            NotificationEvent::VideoProcessed { .. } => NotificationType::VideoProcessed,
+            NotificationEvent::VideoFailed { .. } => NotificationType::VideoFailed,
+            NotificationEvent::ExportReady { .. } => NotificationType::ExportReady,
+            NotificationEvent::ExportFailed { .. } => NotificationType::ExportFailed,
+        }
+    }
+
+    fn message(&self) -> String {
+        match *self {
+            NotificationEvent::Mention { author, .. } => {
+                format!("{} mentioned you in a post", author.username)
+            }
+            NotificationEvent::VideoProcessed { post } => {
+                format!("Your {} has finished processing", media_name(post))
+            }
+            NotificationEvent::VideoFailed { post } => {
+                format!("Your {} could not be processed", media_name(post))
+            }
+            NotificationEvent::ExportReady { .. } => {
+                String::from("Your data export is ready to download")
+            }
+            NotificationEvent::ExportFailed { .. } => {
+                String::from("Your data export could not be created")
+            }
+        }
+    }
+
+    fn link(&self) -> String {
+        match *self {
+            NotificationEvent::Mention { post, .. }
+            | NotificationEvent::VideoProcessed { post }
+            | NotificationEvent::VideoFailed { post } => {
+                format!("/users/{}/posts/{}", post.user_uuid, post.uuid)
+            }
+            NotificationEvent::ExportReady { export }
+            | NotificationEvent::ExportFailed { export } => {
+                format!("/users/{}/exports", export.user_uuid)
+            }
+        }
+    }
+}
+
+pub fn find_mentions(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = vec![];
+    for captures in MENTION_REGEX.captures_iter(text) {
+        let username = String::from(&captures[1]);
+        if !usernames.contains(&username) {
+            usernames.push(username);
+        }
+    }
+    usernames
+}
+
+fn media_name(post: &Post) -> &'static str {
+    match post.post_type {
+        PostType::Audio => "audio",
+        PostType::Album => "album",
+        _ => "video",
+    }
+}
+
+impl Notification {
+    pub async fn record<'a>(
+        connection: &mut PgConnection,
+        event: NotificationEvent<'a>,
+    ) -> Result<Self, OurError> {
+        let uuid = Uuid::new_v4();
+        let query_str = r#"INSERT INTO notifications
+(uuid, user_uuid, actor_uuid, notification_type, message, link)
+VALUES
+($1, $2, $3, $4, $5, $6)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(event.recipient())
+            .bind(event.actor())
+            .bind(event.notification_type())
+            .bind(event.message())
+            .bind(event.link())
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_all(
+        db: &mut Connection<DBConnection>,
+        user_uuid: &str,
+        pagination: Option<Pagination>,
+    ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
+        let parsed_uuid = Uuid::parse_str(user_uuid).map_err(OurError::from_uuid_error)?;
+        let limit = pagination
+            .as_ref()
+            .map(|p| p.limit)
+            .unwrap_or(DEFAULT_LIMIT);
+        let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
+        let notifications = match &pagination {
+            Some(pagination) => {
+                let query_str = r#"SELECT *
+FROM notifications
+WHERE user_uuid = $1 AND created_at < $2
+ORDER BY created_at DESC
+LIMIT $3"#;
+                sqlx::query_as::<_, Self>(query_str)
+                    .bind(&parsed_uuid)
+                    .bind(&pagination.next)
+                    .bind(limit as i32)
+                    .fetch_all(connection)
+                    .await
+            }
+            None => {
+                let query_str = r#"SELECT *
+FROM notifications
+WHERE user_uuid = $1
+ORDER BY created_at DESC
+LIMIT $2"#;
+                sqlx::query_as::<_, Self>(query_str)
+                    .bind(&parsed_uuid)
+                    .bind(limit as i32)
+                    .fetch_all(connection)
+                    .await
+            }
+        }
+        .map_err(OurError::from_sqlx_error)?;
+        let mut new_pagination: Option<Pagination> = None;
+        if notifications.len() == limit {
+            let query_str =
+                "SELECT EXISTS(SELECT 1 FROM notifications WHERE user_uuid = $1 AND created_at < $2)";
+            let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
+            let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
+                .bind(&parsed_uuid)
+                .bind(&notifications.last().unwrap().created_at)
+                .fetch_one(connection)
+                .await
+                .map_err(OurError::from_sqlx_error)?;
+            if exists.0 {
+                new_pagination = Some(Pagination {
+                    next: notifications.last().unwrap().created_at.to_owned(),
+                    limit,
+                });
+            }
+        }
+        Ok((notifications, new_pagination))
+    }
+
+    pub async fn count_unread(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<i64, OurError> {
+        let query_str =
+            "SELECT COUNT(*) FROM notifications WHERE user_uuid = $1 AND read_at IS NULL";
+        let (count,): (i64,) = sqlx::query_as(query_str)
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(count)
+    }
+
+    pub async fn mark_read(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        uuid: &str,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let now = OurDateTime(Utc::now());
+        let query_str = r#"UPDATE notifications
+SET read_at = COALESCE(read_at, $1)
+WHERE uuid = $2 AND user_uuid = $3
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(&now)
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn mark_all_read(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<u64, OurError> {
+        let now = OurDateTime(Utc::now());
+        let query_str =
+            "UPDATE notifications SET read_at = $1 WHERE user_uuid = $2 AND read_at IS NULL";
+        let result = sqlx::query(query_str)
+            .bind(&now)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn find_all_for_export(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM notifications WHERE user_uuid = $1 ORDER BY created_at";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy_for_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<u64, OurError> {
+        let query_str = "DELETE FROM notifications WHERE user_uuid = $1 OR actor_uuid = $1";
+        let result = sqlx::query(query_str)
+            .bind(user_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(FromForm)]
+pub struct ReadNotification<'r> {
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Serialize)]
+pub struct NotificationsWrapper {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Serialize)]
+pub struct UnreadCount {
+    pub unread_count: i64,
+}
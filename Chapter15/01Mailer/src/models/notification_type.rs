@@ -0,0 +1,25 @@
+use rocket::serde::Serialize;
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum NotificationType {
+    Mention = 1,
+    VideoProcessed = 4,
+    VideoFailed = 5,
+    ExportReady = 6,
+    ExportFailed = 7,
+}
+
+impl fmt::Display for NotificationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            NotificationType::Mention => write!(f, "Mention"),
+            NotificationType::VideoProcessed => write!(f, "Video Processed"),
+            NotificationType::VideoFailed => write!(f, "Video Failed"),
+            NotificationType::ExportReady => write!(f, "Export Ready"),
+            NotificationType::ExportFailed => write!(f, "Export Failed"),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{offset::Utc, DateTime, TimeZone};
+use chrono::{offset::Utc, DateTime, NaiveDateTime, TimeZone};
 use rocket::data::ToByteUnit;
 use rocket::form::{self, DataField, FromFormField, ValueField};
 use rocket::serde::{Deserialize, Serialize};
@@ -7,6 +7,20 @@ use rocket::serde::{Deserialize, Serialize};
 #[sqlx(transparent)]
 pub struct OurDateTime(pub DateTime<Utc>);
 
+impl OurDateTime {
+    // Accepts RFC 3339 timestamps and the UTC value of an HTML datetime-local input.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Some(OurDateTime(datetime.with_timezone(&Utc)));
+        }
+        ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .map(|datetime| OurDateTime(Utc.from_utc_datetime(&datetime)))
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for OurDateTime {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
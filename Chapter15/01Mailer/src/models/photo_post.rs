@@ -1,3 +1,4 @@
+use super::media_variant::MediaVariant;
 use super::post::Post;
 use crate::traits::DisplayPostContent;
 
@@ -7,13 +8,59 @@ impl<'a> PhotoPost<'a> {
     pub fn new(post: &'a Post) -> Self {
         PhotoPost(post)
     }
+
+    fn srcset(variants: &[&MediaVariant]) -> String {
+        variants
+            .iter()
+            .map(|variant| format!("{} {}w", variant.path, variant.width.unwrap_or(0)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
 }
 
 impl<'a> DisplayPostContent for PhotoPost<'a> {
     fn raw_html(&self) -> String {
+        let variants = &self.0.variants.0;
+        let jpegs: Vec<&MediaVariant> = variants
+            .iter()
+            .filter(|variant| variant.mime == "image/jpeg")
+            .collect();
+        let largest = match jpegs.last() {
+            Some(largest) => largest,
+            None => {
+                return format!(
+                    r#"<figure><img src="{}" class="section media"/></figure>"#,
+                    self.0.content
+                )
+            }
+        };
+        let sizes = format!(
+            "(max-width: {0}px) 100vw, {0}px",
+            largest.width.unwrap_or(0)
+        );
+        let mut sources = String::new();
+        for mime in ["image/avif", "image/webp"].iter() {
+            let matching: Vec<&MediaVariant> = variants
+                .iter()
+                .filter(|variant| &variant.mime == mime)
+                .collect();
+            if !matching.is_empty() {
+                sources.push_str(&format!(
+                    r#"<source type="{}" srcset="{}" sizes="{}"/>"#,
+                    mime,
+                    Self::srcset(&matching),
+                    sizes
+                ));
+            }
+        }
         format!(
-            r#"<figure><img src="{}" class="section media"/></figure>"#,
-            self.0.content
+            r#"<figure><picture>{}<img src="{}" srcset="{}" sizes="{}" width="{}" height="{}" loading="lazy" class="section media"/></picture></figure>"#,
+            sources,
+            largest.path,
+            Self::srcset(&jpegs),
+            sizes,
+            largest.width.unwrap_or(0),
+            largest.height.unwrap_or(0)
         )
     }
 }
@@ -1,32 +1,122 @@
+use super::album_post::AlbumPost;
+use super::audio_post::AudioPost;
 use super::bool_wrapper::BoolWrapper;
+use super::job::Job;
+use super::job_type::JobType;
+use super::link_preview::LinkPreview;
+use super::media_variant::MediaVariant;
 use super::our_date_time::OurDateTime;
 use super::pagination::{Pagination, DEFAULT_LIMIT};
 use super::photo_post::PhotoPost;
+use super::post_media::PostMedia;
 use super::post_type::PostType;
+use super::processing_status::ProcessingStatus;
+use super::publish_status::PublishStatus;
 use super::text_post::TextPost;
 use super::video_post::VideoPost;
+use super::visibility::Visibility;
+use super::worker::Message;
 use crate::errors::our_error::OurError;
 use crate::fairings::db::DBConnection;
+use crate::storage::Storage;
 use crate::traits::DisplayPostContent;
+use chrono::offset::Utc;
 use rocket::fs::TempFile;
 use rocket::serde::Serialize;
-use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use rocket_db_pools::sqlx::{types::Json, FromRow, PgConnection};
 use rocket_db_pools::{sqlx::Acquire, Connection};
 use uuid::Uuid;
 
+pub const REJECTED_HTML: &str = "<figure class=\"section media\"><figcaption>This upload was rejected by the malware scanner.</figcaption></figure>";
+
 #[derive(Serialize)]
 pub struct ShowPost {
     pub uuid: String,
     pub post_html: String,
+    pub processing_status: ProcessingStatus,
+    pub processing_error: Option<String>,
+    pub media: Vec<String>,
+    pub visibility: Visibility,
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<OurDateTime>,
 }
 
-#[derive(FromRow)]
+#[derive(FromRow, Serialize)]
 pub struct Post {
     pub uuid: Uuid,
     pub user_uuid: Uuid,
     pub post_type: PostType,
     pub content: String,
     pub created_at: OurDateTime,
+    pub processing_status: ProcessingStatus,
+    #[serde(skip_serializing)]
+    pub processing_error: Option<String>,
+    pub variants: Json<Vec<MediaVariant>>,
+    pub poster: Option<String>,
+    pub duration: Option<f64>,
+    pub media_size: i64,
+    #[sqlx(default)]
+    pub media: Json<Vec<PostMedia>>,
+    pub link_url: Option<String>,
+    #[sqlx(default)]
+    pub link_preview: Json<Option<LinkPreview>>,
+    pub visibility: Visibility,
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<OurDateTime>,
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<OurDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostSettings {
+    pub visibility: Visibility,
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<OurDateTime>,
+}
+
+impl PostSettings {
+    pub fn new(
+        visibility: Visibility,
+        publish_status: PublishStatus,
+        publish_at: Option<&str>,
+    ) -> Result<Self, OurError> {
+        let publish_at = if publish_status == PublishStatus::Scheduled {
+            let publish_at = publish_at.and_then(OurDateTime::parse).ok_or_else(|| {
+                OurError::new_bad_request_error(
+                    String::from("A scheduled post needs a valid publish time"),
+                    None,
+                )
+            })?;
+            if publish_at.0 <= Utc::now() {
+                return Err(OurError::new_bad_request_error(
+                    String::from("The publish time must be in the future"),
+                    None,
+                ));
+            }
+            Some(publish_at)
+        } else {
+            None
+        };
+        Ok(PostSettings {
+            visibility,
+            publish_status,
+            publish_at,
+        })
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.publish_status == PublishStatus::Published
+    }
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        PostSettings {
+            visibility: Visibility::Public,
+            publish_status: PublishStatus::Published,
+            publish_at: None,
+        }
+    }
 }
 
 impl Post {
@@ -42,61 +132,226 @@ impl Post {
         VideoPost::new(self)
     }
 
+    pub fn to_audio(&self) -> AudioPost {
+        AudioPost::new(self)
+    }
+
+    pub fn to_album(&self) -> AlbumPost {
+        AlbumPost::new(self)
+    }
+
     pub fn to_media<'a>(&'a self) -> Box<dyn DisplayPostContent + 'a> {
         match self.post_type {
             PostType::Photo => Box::new(self.to_photo()),
             PostType::Text => Box::new(self.to_text()),
             PostType::Video => Box::new(self.to_video()),
+            PostType::Album => Box::new(self.to_album()),
+            PostType::Audio => Box::new(self.to_audio()),
         }
     }
 
+    pub fn media_paths(&self) -> Vec<String> {
+        let mut paths = vec![];
+        if self.post_type != PostType::Text && self.post_type != PostType::Album {
+            paths.push(self.content.clone());
+        }
+        for variant in self.variants.0.iter() {
+            if !paths.contains(&variant.path) {
+                paths.push(variant.path.clone());
+            }
+        }
+        if let Some(poster) = &self.poster {
+            paths.push(poster.clone());
+        }
+        for item in self.media.0.iter() {
+            paths.extend(item.media_paths());
+        }
+        paths
+    }
+
     pub fn to_show_post<'a>(&'a self) -> ShowPost {
+        let post_html = if self.processing_status == ProcessingStatus::Rejected {
+            String::from(REJECTED_HTML)
+        } else {
+            self.to_media().raw_html()
+        };
+        let media = if self.post_type == PostType::Album
+            && self.processing_status != ProcessingStatus::Rejected
+        {
+            self.to_album().items_html()
+        } else {
+            vec![]
+        };
         ShowPost {
             uuid: self.uuid.to_string(),
-            post_html: self.to_media().raw_html(),
+            post_html,
+            processing_status: self.processing_status,
+            processing_error: self.processing_error.clone(),
+            media,
+            visibility: self.visibility,
+            publish_status: self.publish_status,
+            publish_at: self.publish_at.clone(),
         }
     }
 
+    pub fn is_published(&self) -> bool {
+        self.publish_status == PublishStatus::Published
+    }
+
+    pub fn can_view(&self, viewer: Option<&Uuid>) -> bool {
+        viewer == Some(&self.user_uuid) || (self.is_published() && !self.visibility.is_restricted())
+    }
+
     pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Post, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "SELECT * FROM posts WHERE uuid = $1";
-        Ok(sqlx::query_as::<_, Self>(query_str)
+        let query_str = "SELECT * FROM posts WHERE uuid = $1 AND deleted_at IS NULL";
+        let mut post = sqlx::query_as::<_, Self>(query_str)
             .bind(parsed_uuid)
-            .fetch_one(connection)
+            .fetch_one(&mut *connection)
             .await
-            .map_err(OurError::from_sqlx_error)?)
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, std::slice::from_mut(&mut post)).await?;
+        Self::load_link_previews(connection, std::slice::from_mut(&mut post)).await?;
+        Ok(post)
+    }
+
+    pub async fn load_link_previews(
+        connection: &mut PgConnection,
+        posts: &mut [Post],
+    ) -> Result<(), OurError> {
+        let mut urls: Vec<String> = vec![];
+        for url in posts.iter().filter_map(|post| post.link_url.as_ref()) {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        if urls.is_empty() {
+            return Ok(());
+        }
+        let previews = LinkPreview::find_all(connection, &urls).await?;
+        for post in posts.iter_mut() {
+            post.link_preview = Json(
+                previews
+                    .iter()
+                    .find(|preview| Some(&preview.url) == post.link_url.as_ref())
+                    .cloned(),
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn load_media(
+        connection: &mut PgConnection,
+        posts: &mut [Post],
+    ) -> Result<(), OurError> {
+        let album_uuids: Vec<Uuid> = posts
+            .iter()
+            .filter(|post| post.post_type == PostType::Album)
+            .map(|post| post.uuid)
+            .collect();
+        if album_uuids.is_empty() {
+            return Ok(());
+        }
+        let items = PostMedia::find_for_posts(connection, &album_uuids).await?;
+        for post in posts.iter_mut() {
+            post.media = Json(
+                items
+                    .iter()
+                    .filter(|item| item.post_uuid == post.uuid)
+                    .cloned()
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn refresh_album(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<Post, OurError> {
+        let query_str = r#"UPDATE posts
+SET processing_status = (
+    SELECT CASE
+        WHEN bool_or(processing_status = $2) THEN $2
+        WHEN bool_or(processing_status = $3) THEN $3
+        WHEN bool_or(processing_status = $4) THEN $4
+        ELSE $5
+    END
+    FROM post_media WHERE post_uuid = $1
+),
+processing_error = (
+    SELECT processing_error FROM post_media
+    WHERE post_uuid = $1 AND processing_error IS NOT NULL
+    ORDER BY position
+    LIMIT 1
+),
+media_size = (SELECT COALESCE(SUM(media_size), 0) FROM post_media WHERE post_uuid = $1)
+WHERE uuid = $1
+RETURNING *"#;
+        let mut post = sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .bind(ProcessingStatus::Failed)
+            .bind(ProcessingStatus::Processing)
+            .bind(ProcessingStatus::Queued)
+            .bind(ProcessingStatus::Ready)
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, std::slice::from_mut(&mut post)).await?;
+        Ok(post)
     }
 
+    // Lists a user's published posts, newest first. Only public posts are
+    // listed unless `is_owner` is set, in which case the user is viewing
+    // their own profile and sees posts of every visibility.
     pub async fn find_all(
         db: &mut Connection<DBConnection>,
         user_uuid: &str,
         pagination: Option<Pagination>,
+        is_owner: bool,
     ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
         if pagination.is_some() {
-            return Self::find_all_with_pagination(db, user_uuid, &pagination.unwrap()).await;
+            return Self::find_all_with_pagination(
+                db,
+                user_uuid,
+                &pagination.unwrap(),
+                is_owner,
+            )
+            .await;
         } else {
-            return Self::find_all_without_pagination(db, user_uuid).await;
+            return Self::find_all_without_pagination(db, user_uuid, is_owner).await;
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn create(
         connection: &mut PgConnection,
         user_uuid: &str,
         post_type: PostType,
         content: &str,
+        variants: &[MediaVariant],
+        processing_status: ProcessingStatus,
+        media_size: i64,
+        settings: &PostSettings,
     ) -> Result<Self, OurError> {
         let parsed_uuid = Uuid::parse_str(user_uuid).map_err(OurError::from_uuid_error)?;
         let uuid = Uuid::new_v4();
         let query_str = r#"INSERT INTO posts
-(uuid, user_uuid, post_type, content)
+(uuid, user_uuid, post_type, content, variants, processing_status, media_size, visibility, publish_status, publish_at)
 VALUES
-($1, $2, $3, $4)
+($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
 RETURNING *"#;
         Ok(sqlx::query_as::<_, Self>(query_str)
             .bind(uuid)
             .bind(parsed_uuid)
             .bind(post_type)
             .bind(content)
+            .bind(Json(variants))
+            .bind(processing_status)
+            .bind(media_size)
+            .bind(settings.visibility)
+            .bind(settings.publish_status)
+            .bind(&settings.publish_at)
             .fetch_one(connection)
             .await
             .map_err(OurError::from_sqlx_error)?)
@@ -105,26 +360,37 @@ RETURNING *"#;
     async fn find_all_without_pagination(
         db: &mut Connection<DBConnection>,
         user_uuid: &str,
+        is_owner: bool,
     ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
         let parsed_uuid = Uuid::parse_str(user_uuid).map_err(OurError::from_uuid_error)?;
         let query_str = r#"SELECT *
 FROM posts
-WHERE user_uuid = $1
+WHERE user_uuid = $1 AND ($3 OR visibility = $4) AND publish_status = $5 AND deleted_at IS NULL
 ORDER BY created_at DESC
 LIMIT $2"#;
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let posts = sqlx::query_as::<_, Self>(query_str)
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
             .bind(parsed_uuid)
             .bind(DEFAULT_LIMIT as i32)
-            .fetch_all(connection)
+            .bind(is_owner)
+            .bind(Visibility::Public)
+            .bind(PublishStatus::Published)
+            .fetch_all(&mut *connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Self::load_link_previews(connection, &mut posts).await?;
         let mut new_pagination: Option<Pagination> = None;
         if posts.len() == DEFAULT_LIMIT {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM posts WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
+            let query_str = r#"SELECT EXISTS(SELECT 1 FROM posts
+WHERE user_uuid = $1 AND created_at < $2 AND ($3 OR visibility = $4) AND publish_status = $5 AND deleted_at IS NULL)"#;
             let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
             let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
+                .bind(&parsed_uuid)
                 .bind(&posts.last().unwrap().created_at)
+                .bind(is_owner)
+                .bind(Visibility::Public)
+                .bind(PublishStatus::Published)
                 .fetch_one(connection)
                 .await
                 .map_err(OurError::from_sqlx_error)?;
@@ -142,27 +408,38 @@ LIMIT $2"#;
         db: &mut Connection<DBConnection>,
         user_uuid: &str,
         pagination: &Pagination,
+        is_owner: bool,
     ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
         let parsed_uuid = Uuid::parse_str(user_uuid).map_err(OurError::from_uuid_error)?;
         let query_str = r#"SELECT *
 FROM posts
-WHERE user_uuid = $1 AND　created_at < $2
-ORDER BY created_at　DESC
+WHERE user_uuid = $1 AND created_at < $2 AND ($4 OR visibility = $5) AND publish_status = $6 AND deleted_at IS NULL
+ORDER BY created_at DESC
 LIMIT $3"#;
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
-        let posts = sqlx::query_as::<_, Self>(query_str)
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
             .bind(&parsed_uuid)
             .bind(&pagination.next)
             .bind(pagination.limit as i32)
-            .fetch_all(connection)
+            .bind(is_owner)
+            .bind(Visibility::Public)
+            .bind(PublishStatus::Published)
+            .fetch_all(&mut *connection)
             .await
             .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Self::load_link_previews(connection, &mut posts).await?;
         let mut new_pagination: Option<Pagination> = None;
         if posts.len() == pagination.limit {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM posts WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
+            let query_str = r#"SELECT EXISTS(SELECT 1 FROM posts
+WHERE user_uuid = $1 AND created_at < $2 AND ($3 OR visibility = $4) AND publish_status = $5 AND deleted_at IS NULL)"#;
             let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
             let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
+                .bind(&parsed_uuid)
                 .bind(&posts.last().unwrap().created_at)
+                .bind(is_owner)
+                .bind(Visibility::Public)
+                .bind(PublishStatus::Published)
                 .fetch_one(connection)
                 .await
                 .map_err(OurError::from_sqlx_error)?;
@@ -180,18 +457,344 @@ LIMIT $3"#;
         connection: &mut PgConnection,
         uuid: &str,
         content: &str,
+        variants: &[MediaVariant],
+        poster: Option<&str>,
+        duration: Option<f64>,
+        media_size: i64,
     ) -> Result<Post, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = String::from("UPDATE posts SET content = $1 WHERE uuid = $2 RETURNING *");
+        let query_str = String::from(
+            r#"UPDATE posts
+SET content = $1, variants = $2, poster = $3, duration = $4, processing_status = $5, processing_error = NULL, media_size = $6
+WHERE uuid = $7
+RETURNING *"#,
+        );
         Ok(sqlx::query_as::<_, Self>(&query_str)
             .bind(content)
+            .bind(Json(variants))
+            .bind(poster)
+            .bind(duration)
+            .bind(ProcessingStatus::Ready)
+            .bind(media_size)
             .bind(&parsed_uuid)
             .fetch_one(connection)
             .await
             .map_err(OurError::from_sqlx_error))?
     }
 
-    pub async fn destroy(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
+    pub async fn update_processing_status(
+        connection: &mut PgConnection,
+        uuid: &str,
+        processing_status: ProcessingStatus,
+        processing_error: Option<&str>,
+    ) -> Result<Post, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "UPDATE posts SET processing_status = $1, processing_error = $2 WHERE uuid = $3 RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(processing_status)
+            .bind(processing_error)
+            .bind(&parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn retry_processing(
+        connection: &mut PgConnection,
+        storage: &Storage,
+        user_uuid: &str,
+        uuid: &str,
+    ) -> Result<Post, OurError> {
+        let mut transaction = connection
+            .begin()
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let post = Self::find(&mut transaction, uuid).await?;
+        if post.user_uuid.to_string() != user_uuid
+            || post.post_type == PostType::Text
+            || post.post_type == PostType::Photo
+            || post.processing_status != ProcessingStatus::Failed
+        {
+            return Err(OurError::new_bad_request_error(
+                String::from("Post cannot be retried"),
+                None,
+            ));
+        }
+        let post = if post.post_type == PostType::Album {
+            let items = PostMedia::requeue_failed(&mut transaction, &post.uuid).await?;
+            for item in items {
+                requeue_processing(
+                    &mut transaction,
+                    storage,
+                    JobType::ProcessVideo,
+                    &post.uuid,
+                    Some(&item.uuid),
+                    &item.content,
+                )
+                .await?;
+            }
+            Self::refresh_album(&mut transaction, &post.uuid).await?
+        } else {
+            let job_type = if post.post_type == PostType::Audio {
+                JobType::ProcessAudio
+            } else {
+                JobType::ProcessVideo
+            };
+            requeue_processing(
+                &mut transaction,
+                storage,
+                job_type,
+                &post.uuid,
+                None,
+                &post.content,
+            )
+            .await?;
+            Self::update_processing_status(&mut transaction, uuid, ProcessingStatus::Queued, None)
+                .await?
+        };
+        transaction
+            .commit()
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(post)
+    }
+
+    pub async fn media_urls(connection: &mut PgConnection) -> Result<Vec<String>, OurError> {
+        let query_str = r#"SELECT content FROM posts WHERE post_type <> $1
+UNION SELECT poster FROM posts WHERE poster IS NOT NULL
+UNION SELECT variant->>'path' FROM posts, jsonb_array_elements(variants) AS variant
+UNION SELECT content FROM post_media
+UNION SELECT poster FROM post_media WHERE poster IS NOT NULL
+UNION SELECT variant->>'path' FROM post_media, jsonb_array_elements(variants) AS variant"#;
+        Ok(sqlx::query_scalar(query_str)
+            .bind(PostType::Text)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_unpublished(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Post>, OurError> {
+        let query_str = r#"SELECT * FROM posts
+WHERE user_uuid = $1 AND publish_status <> $2 AND deleted_at IS NULL
+ORDER BY publish_status DESC, publish_at, created_at DESC"#;
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .bind(PublishStatus::Published)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Self::load_link_previews(connection, &mut posts).await?;
+        Ok(posts)
+    }
+
+    pub async fn update_draft(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        content: &str,
+        visibility: Visibility,
+    ) -> Result<Post, OurError> {
+        let query_str = r#"UPDATE posts SET content = $1, visibility = $2
+WHERE uuid = $3 AND publish_status <> $4 AND deleted_at IS NULL
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(content)
+            .bind(visibility)
+            .bind(uuid)
+            .bind(PublishStatus::Published)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn update_publish_status(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        settings: &PostSettings,
+    ) -> Result<Post, OurError> {
+        let query_str = r#"UPDATE posts
+SET visibility = $1, publish_status = $2, publish_at = $3,
+    created_at = CASE WHEN $2 = $4 THEN NOW() ELSE created_at END
+WHERE uuid = $5 AND publish_status <> $4 AND deleted_at IS NULL
+RETURNING *"#;
+        let mut post = sqlx::query_as::<_, Self>(query_str)
+            .bind(settings.visibility)
+            .bind(settings.publish_status)
+            .bind(&settings.publish_at)
+            .bind(PublishStatus::Published)
+            .bind(uuid)
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, std::slice::from_mut(&mut post)).await?;
+        Self::load_link_previews(connection, std::slice::from_mut(&mut post)).await?;
+        Ok(post)
+    }
+
+    pub async fn publish_due(
+        connection: &mut PgConnection,
+        uuid: &str,
+    ) -> Result<Option<Post>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE posts
+SET publish_status = $1, publish_at = NULL, created_at = NOW()
+WHERE uuid = $2 AND publish_status = $3 AND publish_at <= NOW() AND deleted_at IS NULL
+RETURNING *"#;
+        let published = sqlx::query_as::<_, Self>(query_str)
+            .bind(PublishStatus::Published)
+            .bind(parsed_uuid)
+            .bind(PublishStatus::Scheduled)
+            .fetch_optional(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let mut post = match published {
+            Some(post) => post,
+            None => return Ok(None),
+        };
+        Self::load_media(connection, std::slice::from_mut(&mut post)).await?;
+        Self::load_link_previews(connection, std::slice::from_mut(&mut post)).await?;
+        Ok(Some(post))
+    }
+
+    pub async fn media_viewers(
+        connection: &mut PgConnection,
+        urls: &[String],
+    ) -> Result<Vec<(Uuid, Visibility)>, OurError> {
+        let query_str = r#"SELECT posts.user_uuid, CASE WHEN posts.publish_status = $3 AND posts.deleted_at IS NULL THEN posts.visibility ELSE $4 END FROM posts
+JOIN users ON users.uuid = posts.user_uuid
+WHERE users.deleted_at IS NULL AND ((posts.post_type <> $2 AND posts.content = ANY($1)) OR posts.poster = ANY($1)
+    OR EXISTS (SELECT 1 FROM jsonb_array_elements(posts.variants) AS variant WHERE variant->>'path' = ANY($1)))
+UNION
+SELECT posts.user_uuid, CASE WHEN posts.publish_status = $3 AND posts.deleted_at IS NULL THEN posts.visibility ELSE $4 END FROM post_media
+JOIN posts ON posts.uuid = post_media.post_uuid
+JOIN users ON users.uuid = posts.user_uuid
+WHERE users.deleted_at IS NULL AND (post_media.content = ANY($1) OR post_media.poster = ANY($1)
+    OR EXISTS (SELECT 1 FROM jsonb_array_elements(post_media.variants) AS variant WHERE variant->>'path' = ANY($1)))"#;
+        Ok(sqlx::query_as::<_, (Uuid, Visibility)>(query_str)
+            .bind(urls)
+            .bind(PostType::Text)
+            .bind(PublishStatus::Published)
+            .bind(Visibility::Private)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy(connection: &mut PgConnection, uuid: &str) -> Result<Post, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "UPDATE posts SET deleted_at = NOW() WHERE uuid = $1 AND deleted_at IS NULL RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_trashed(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Post>, OurError> {
+        let query_str = r#"SELECT * FROM posts
+WHERE user_uuid = $1 AND deleted_at IS NOT NULL
+ORDER BY deleted_at DESC"#;
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Self::load_link_previews(connection, &mut posts).await?;
+        Ok(posts)
+    }
+
+    pub async fn find_deleted(
+        connection: &mut PgConnection,
+        uuid: &str,
+    ) -> Result<Option<Post>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM posts WHERE uuid = $1 AND deleted_at IS NOT NULL";
+        let found = sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_optional(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        let mut post = match found {
+            Some(post) => post,
+            None => return Ok(None),
+        };
+        Self::load_media(connection, std::slice::from_mut(&mut post)).await?;
+        Ok(Some(post))
+    }
+
+    pub async fn find_all_for_purge(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Post>, OurError> {
+        let query_str = "SELECT * FROM posts WHERE user_uuid = $1";
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Ok(posts)
+    }
+
+    pub async fn find_all_for_export(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Post>, OurError> {
+        let query_str =
+            "SELECT * FROM posts WHERE user_uuid = $1 AND deleted_at IS NULL ORDER BY created_at";
+        let mut posts = sqlx::query_as::<_, Self>(query_str)
+            .bind(user_uuid)
+            .fetch_all(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::load_media(connection, &mut posts).await?;
+        Self::load_link_previews(connection, &mut posts).await?;
+        Ok(posts)
+    }
+
+    pub async fn backdate(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        created_at: &OurDateTime,
+    ) -> Result<(), OurError> {
+        sqlx::query("UPDATE posts SET created_at = $1 WHERE uuid = $2")
+            .bind(created_at)
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn restore(
+        connection: &mut PgConnection,
+        uuid: &str,
+        user_uuid: &Uuid,
+        retention: u64,
+    ) -> Result<Post, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE posts SET deleted_at = NULL
+WHERE uuid = $1 AND user_uuid = $2 AND deleted_at > NOW() - ($3 * INTERVAL '1 second')
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .bind(retention as f64)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn purge(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
         let query_str = "DELETE FROM posts WHERE uuid = $1";
         sqlx::query(query_str)
@@ -203,8 +806,117 @@ LIMIT $3"#;
     }
 }
 
+// A failed post normally has a dead job to requeue. Posts whose job is gone,
+// such as videos left in flight before the job queue existed, get a fresh job
+// that reprocesses the media the post points at.
+async fn requeue_processing(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    job_type: JobType,
+    post_uuid: &Uuid,
+    media_uuid: Option<&Uuid>,
+    content: &str,
+) -> Result<(), OurError> {
+    let payload_uuid = media_uuid.unwrap_or(post_uuid).to_string();
+    if Job::requeue_dead(connection, job_type, &payload_uuid)
+        .await?
+        .is_some()
+    {
+        return Ok(());
+    }
+    let orig_filename = storage.key_from_url(content).ok_or_else(|| {
+        OurError::new_bad_request_error(String::from("Post cannot be retried"), None)
+    })?;
+    let ext = if job_type == JobType::ProcessAudio {
+        "m4a"
+    } else {
+        "mp4"
+    };
+    let wm = Message {
+        uuid: post_uuid.to_string(),
+        media_uuid: media_uuid.map(Uuid::to_string).unwrap_or_default(),
+        orig_filename,
+        dest_filename: format!("{}.{}", Uuid::new_v4(), ext),
+    };
+    Job::enqueue(connection, job_type, &wm).await?;
+    Ok(())
+}
+
 #[derive(Debug, FromForm)]
 pub struct NewPost<'r> {
-    pub file: TempFile<'r>,
+    #[field(name = "file")]
+    pub files: Vec<TempFile<'r>>,
+    #[field(default = Visibility::Public)]
+    pub visibility: Visibility,
+    #[field(default = PublishStatus::Published)]
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<&'r str>,
     pub authenticity_token: &'r str,
 }
+
+impl<'r> NewPost<'r> {
+    pub fn settings(&self) -> Result<PostSettings, OurError> {
+        PostSettings::new(self.visibility, self.publish_status, self.publish_at)
+    }
+}
+
+#[derive(FromForm)]
+pub struct NewAPIPost<'r> {
+    #[field(name = "file")]
+    pub files: Vec<TempFile<'r>>,
+    #[field(default = Visibility::Public)]
+    pub visibility: Visibility,
+    #[field(default = PublishStatus::Published)]
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<&'r str>,
+}
+
+impl<'r> NewAPIPost<'r> {
+    pub fn settings(&self) -> Result<PostSettings, OurError> {
+        PostSettings::new(self.visibility, self.publish_status, self.publish_at)
+    }
+}
+
+#[derive(FromForm)]
+pub struct EditDraft<'r> {
+    pub content: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<&'r str>,
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct RetryPost<'r> {
+    pub authenticity_token: &'r str,
+}
+
+#[derive(FromForm)]
+pub struct RestorePost<'r> {
+    pub authenticity_token: &'r str,
+}
+
+#[derive(Serialize)]
+pub struct PostsWrapper {
+    pub posts: Vec<Post>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub pagination: Option<Pagination>,
+}
+
+#[derive(Serialize)]
+pub struct PostStatus {
+    pub uuid: Uuid,
+    pub processing_status: ProcessingStatus,
+    pub processing_error: Option<String>,
+}
+
+impl From<&Post> for PostStatus {
+    fn from(post: &Post) -> Self {
+        PostStatus {
+            uuid: post.uuid,
+            processing_status: post.processing_status,
+            processing_error: post.processing_error.clone(),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use super::media_variant::MediaVariant;
+use super::our_date_time::OurDateTime;
+use super::post::Post;
+use super::post_type::PostType;
+use super::processing_status::ProcessingStatus;
+use crate::errors::our_error::OurError;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::{types::Json, FromRow, PgConnection};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Serialize)]
+pub struct ShowPostMedia {
+    pub uuid: String,
+    pub html: String,
+    pub processing_status: ProcessingStatus,
+}
+
+#[derive(FromRow, Serialize, Deserialize, Clone, Debug)]
+pub struct PostMedia {
+    pub uuid: Uuid,
+    pub post_uuid: Uuid,
+    pub position: i32,
+    pub media_type: PostType,
+    pub content: String,
+    pub variants: Json<Vec<MediaVariant>>,
+    pub poster: Option<String>,
+    pub duration: Option<f64>,
+    pub processing_status: ProcessingStatus,
+    #[serde(skip_serializing, default)]
+    pub processing_error: Option<String>,
+    pub media_size: i64,
+    #[serde(skip_serializing, default)]
+    pub blob_hashes: Vec<String>,
+    pub created_at: OurDateTime,
+}
+
+impl PostMedia {
+    pub fn to_post(&self, album: &Post) -> Post {
+        Post {
+            uuid: self.uuid,
+            user_uuid: album.user_uuid,
+            post_type: self.media_type,
+            content: self.content.clone(),
+            created_at: self.created_at.clone(),
+            processing_status: self.processing_status,
+            processing_error: self.processing_error.clone(),
+            variants: self.variants.clone(),
+            poster: self.poster.clone(),
+            duration: self.duration,
+            media_size: self.media_size,
+            media: Json(vec![]),
+            link_url: None,
+            link_preview: Json(None),
+            visibility: album.visibility,
+            publish_status: album.publish_status,
+            publish_at: album.publish_at.clone(),
+            deleted_at: album.deleted_at.clone(),
+        }
+    }
+
+    pub fn to_show_post_media(&self, album: &Post) -> ShowPostMedia {
+        ShowPostMedia {
+            uuid: self.uuid.to_string(),
+            html: self.to_post(album).to_media().raw_html(),
+            processing_status: self.processing_status,
+        }
+    }
+
+    pub fn media_paths(&self) -> Vec<String> {
+        let mut paths = vec![self.content.clone()];
+        for variant in self.variants.0.iter() {
+            if !paths.contains(&variant.path) {
+                paths.push(variant.path.clone());
+            }
+        }
+        if let Some(poster) = &self.poster {
+            paths.push(poster.clone());
+        }
+        paths
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        position: i32,
+        media_type: PostType,
+        content: &str,
+        variants: &[MediaVariant],
+        processing_status: ProcessingStatus,
+        media_size: i64,
+        blob_hashes: &[String],
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO post_media
+(uuid, post_uuid, position, media_type, content, variants, processing_status, media_size, blob_hashes)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8, $9)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(post_uuid)
+            .bind(position)
+            .bind(media_type)
+            .bind(content)
+            .bind(Json(variants))
+            .bind(processing_status)
+            .bind(media_size)
+            .bind(blob_hashes)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM post_media WHERE uuid = $1";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_for_posts(
+        connection: &mut PgConnection,
+        post_uuids: &[Uuid],
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"SELECT * FROM post_media
+WHERE post_uuid = ANY($1)
+ORDER BY post_uuid, position, created_at"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(post_uuids)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn make_permanent(
+        connection: &mut PgConnection,
+        uuid: &str,
+        content: &str,
+        variants: &[MediaVariant],
+        poster: Option<&str>,
+        duration: Option<f64>,
+        media_size: i64,
+        blob_hashes: &[String],
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE post_media
+SET content = $1, variants = $2, poster = $3, duration = $4, processing_status = $5, processing_error = NULL, media_size = $6, blob_hashes = $7
+WHERE uuid = $8
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(content)
+            .bind(Json(variants))
+            .bind(poster)
+            .bind(duration)
+            .bind(ProcessingStatus::Ready)
+            .bind(media_size)
+            .bind(blob_hashes)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn update_processing_status(
+        connection: &mut PgConnection,
+        uuid: &str,
+        processing_status: ProcessingStatus,
+        processing_error: Option<&str>,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE post_media
+SET processing_status = $1, processing_error = $2
+WHERE uuid = $3
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(processing_status)
+            .bind(processing_error)
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn requeue_failed(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = r#"UPDATE post_media
+SET processing_status = $1, processing_error = NULL
+WHERE post_uuid = $2 AND processing_status = $3
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(ProcessingStatus::Queued)
+            .bind(post_uuid)
+            .bind(ProcessingStatus::Failed)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn reorder(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        order: &[Uuid],
+    ) -> Result<(), OurError> {
+        let query_str = r#"UPDATE post_media
+SET position = ordered.position::INTEGER - 1
+FROM UNNEST($2::UUID[]) WITH ORDINALITY AS ordered(uuid, position)
+WHERE post_media.post_uuid = $1 AND post_media.uuid = ordered.uuid"#;
+        sqlx::query(query_str)
+            .bind(post_uuid)
+            .bind(order)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn destroy(
+        connection: &mut PgConnection,
+        post_uuid: &Uuid,
+        uuids: &[Uuid],
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str =
+            "DELETE FROM post_media WHERE post_uuid = $1 AND uuid = ANY($2) RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(post_uuid)
+            .bind(uuids)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
+
+#[derive(FromForm)]
+pub struct EditAlbum<'r> {
+    pub positions: HashMap<String, i32>,
+    pub remove: Vec<String>,
+    pub authenticity_token: &'r str,
+}
@@ -1,10 +1,13 @@
 use rocket::form::FromFormField;
+use rocket::serde::{Deserialize, Serialize};
 use rocket_db_pools::sqlx;
 
-#[derive(sqlx::Type, Debug, FromFormField, PartialEq)]
+#[derive(sqlx::Type, Debug, FromFormField, Serialize, Deserialize, PartialEq, Clone, Copy)]
 #[repr(i32)]
 pub enum PostType {
     Text = 0,
     Photo = 1,
     Video = 2,
+    Album = 3,
+    Audio = 4,
 }
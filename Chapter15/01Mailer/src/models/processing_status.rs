@@ -0,0 +1,25 @@
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum ProcessingStatus {
+    Queued = 0,
+    Processing = 1,
+    Ready = 2,
+    Failed = 3,
+    Rejected = 4,
+}
+
+impl fmt::Display for ProcessingStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ProcessingStatus::Queued => write!(f, "Queued"),
+            ProcessingStatus::Processing => write!(f, "Processing"),
+            ProcessingStatus::Ready => write!(f, "Ready"),
+            ProcessingStatus::Failed => write!(f, "Failed"),
+            ProcessingStatus::Rejected => write!(f, "Rejected"),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use rocket::form::FromFormField;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, FromFormField, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum PublishStatus {
+    Published = 0,
+    Draft = 1,
+    Scheduled = 2,
+}
+
+impl PublishStatus {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "published" => Some(PublishStatus::Published),
+            "draft" => Some(PublishStatus::Draft),
+            "scheduled" => Some(PublishStatus::Scheduled),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PublishStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PublishStatus::Published => write!(f, "Published"),
+            PublishStatus::Draft => write!(f, "Draft"),
+            PublishStatus::Scheduled => write!(f, "Scheduled"),
+        }
+    }
+}
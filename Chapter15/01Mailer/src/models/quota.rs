@@ -0,0 +1,156 @@
+use super::user::User;
+use crate::errors::our_error::OurError;
+use rocket::form::FromForm;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx::PgConnection;
+
+pub const MEGABYTE: i64 = 1024 * 1024;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub storage: u64,
+    pub posts_per_day: u32,
+    pub album_items: usize,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            storage: 1024 * 1024 * 1024,
+            posts_per_day: 100,
+            album_items: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Quota {
+    pub storage_used: i64,
+    pub storage_limit: i64,
+    pub posts_today: i64,
+    pub posts_per_day: i64,
+    pub storage_override: Option<i64>,
+    pub daily_post_override: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct QuotaContext {
+    pub storage_used: String,
+    pub storage_limit: String,
+    pub storage_percent: i64,
+    pub posts_today: i64,
+    pub posts_per_day: i64,
+    pub storage_quota_mb: Option<i64>,
+    pub daily_post_limit: Option<i32>,
+}
+
+impl Quota {
+    pub fn new(config: &QuotaConfig, user: &User, storage_used: i64, posts_today: i64) -> Self {
+        Quota {
+            storage_used,
+            storage_limit: user.storage_quota.unwrap_or(config.storage as i64),
+            posts_today,
+            posts_per_day: user
+                .daily_post_limit
+                .map(i64::from)
+                .unwrap_or(config.posts_per_day as i64),
+            storage_override: user.storage_quota,
+            daily_post_override: user.daily_post_limit,
+        }
+    }
+
+    pub async fn find(
+        connection: &mut PgConnection,
+        config: &QuotaConfig,
+        user: &User,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"SELECT
+COALESCE(SUM(media_size), 0)::BIGINT,
+COUNT(*) FILTER (WHERE created_at > CURRENT_TIMESTAMP - INTERVAL '1 day')
+FROM posts WHERE user_uuid = $1"#;
+        let (storage_used, posts_today): (i64, i64) = sqlx::query_as(query_str)
+            .bind(user.uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(Quota::new(config, user, storage_used, posts_today))
+    }
+
+    // Locks the user's row for the rest of the transaction before counting,
+    // so concurrent uploads by the same user are checked one after another.
+    pub async fn find_for_update(
+        connection: &mut PgConnection,
+        config: &QuotaConfig,
+        user: &User,
+    ) -> Result<Self, OurError> {
+        sqlx::query("SELECT uuid FROM users WHERE uuid = $1 FOR UPDATE")
+            .bind(user.uuid)
+            .execute(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Self::find(connection, config, user).await
+    }
+
+    pub fn check(&self, upload_size: u64) -> Result<(), OurError> {
+        if self.posts_today >= self.posts_per_day {
+            return Err(OurError::new_too_many_requests_error(
+                format!(
+                    "You can only create {} posts per day, please try again later",
+                    self.posts_per_day
+                ),
+                None,
+            ));
+        }
+        if self.storage_used + upload_size as i64 > self.storage_limit {
+            return Err(OurError::new_payload_too_large_error(
+                format!(
+                    "Upload exceeds your storage quota ({} of {} used)",
+                    format_bytes(self.storage_used),
+                    format_bytes(self.storage_limit)
+                ),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_context(&self) -> QuotaContext {
+        let storage_percent = if self.storage_limit > 0 {
+            (self.storage_used * 100 / self.storage_limit).min(100)
+        } else {
+            100
+        };
+        QuotaContext {
+            storage_used: format_bytes(self.storage_used),
+            storage_limit: format_bytes(self.storage_limit),
+            storage_percent,
+            posts_today: self.posts_today,
+            posts_per_day: self.posts_per_day,
+            storage_quota_mb: self.storage_override.map(|bytes| bytes / MEGABYTE),
+            daily_post_limit: self.daily_post_override,
+        }
+    }
+}
+
+#[derive(FromForm)]
+pub struct QuotaOverride<'r> {
+    pub storage_quota_mb: Option<i64>,
+    pub daily_post_limit: Option<i32>,
+    pub authenticity_token: &'r str,
+}
+
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
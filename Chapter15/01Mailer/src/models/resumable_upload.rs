@@ -0,0 +1,196 @@
+use super::our_date_time::OurDateTime;
+use super::post::PostSettings;
+use super::publish_status::PublishStatus;
+use super::visibility::Visibility;
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Deserialize;
+use rocket_db_pools::sqlx::{FromRow, PgConnection};
+use uuid::Uuid;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TusConfig {
+    pub max_size: u64,
+    pub expiration: u64,
+}
+
+impl Default for TusConfig {
+    fn default() -> Self {
+        TusConfig {
+            max_size: 64 * 1024 * 1024,
+            expiration: 86400,
+        }
+    }
+}
+
+impl TusConfig {
+    pub fn expires_at(&self) -> OurDateTime {
+        OurDateTime(Utc::now() + Duration::seconds(self.expiration as i64))
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct ResumableUpload {
+    pub uuid: Uuid,
+    pub user_uuid: Uuid,
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub upload_length: i64,
+    pub upload_offset: i64,
+    pub post_uuid: Option<Uuid>,
+    pub visibility: Visibility,
+    pub publish_status: PublishStatus,
+    pub publish_at: Option<OurDateTime>,
+    pub created_at: OurDateTime,
+    pub expires_at: OurDateTime,
+}
+
+impl ResumableUpload {
+    pub fn is_complete(&self) -> bool {
+        self.upload_offset >= self.upload_length
+    }
+
+    pub fn settings(&self) -> PostSettings {
+        PostSettings {
+            visibility: self.visibility,
+            publish_status: self.publish_status,
+            publish_at: self.publish_at.clone(),
+        }
+    }
+
+    pub async fn create(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+        filename: Option<&str>,
+        content_type: &str,
+        upload_length: i64,
+        settings: &PostSettings,
+        expires_at: &OurDateTime,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO resumable_uploads
+(uuid, user_uuid, filename, content_type, upload_length, visibility, publish_status, publish_at, expires_at)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $8, $9)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(user_uuid)
+            .bind(filename)
+            .bind(content_type)
+            .bind(upload_length)
+            .bind(settings.visibility)
+            .bind(settings.publish_status)
+            .bind(&settings.publish_at)
+            .bind(expires_at)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find(
+        connection: &mut PgConnection,
+        uuid: &str,
+        user_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid)
+            .map_err(|_| OurError::new_not_found_error(String::from("Upload not found"), None))?;
+        let query_str = r#"SELECT * FROM resumable_uploads
+WHERE uuid = $1 AND user_uuid = $2 AND expires_at > CURRENT_TIMESTAMP"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .bind(user_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    // Rows locked by another request are skipped, so a concurrent PATCH to the
+    // same upload gets `None` instead of waiting for the first one to finish.
+    pub async fn lock(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = r#"SELECT * FROM resumable_uploads
+WHERE uuid = $1 AND expires_at > CURRENT_TIMESTAMP
+FOR UPDATE SKIP LOCKED"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(uuid)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn advance(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        from_offset: i64,
+        to_offset: i64,
+        expires_at: &OurDateTime,
+    ) -> Result<Option<Self>, OurError> {
+        let query_str = r#"UPDATE resumable_uploads
+SET upload_offset = $1, expires_at = $2
+WHERE uuid = $3 AND upload_offset = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(to_offset)
+            .bind(expires_at)
+            .bind(uuid)
+            .bind(from_offset)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn complete(
+        connection: &mut PgConnection,
+        uuid: &Uuid,
+        post_uuid: &Uuid,
+    ) -> Result<Self, OurError> {
+        let query_str = "UPDATE resumable_uploads SET post_uuid = $1 WHERE uuid = $2 RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(post_uuid)
+            .bind(uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy(connection: &mut PgConnection, uuid: &Uuid) -> Result<(), OurError> {
+        sqlx::query("DELETE FROM resumable_uploads WHERE uuid = $1")
+            .bind(uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(())
+    }
+
+    pub async fn destroy_for_user(
+        connection: &mut PgConnection,
+        user_uuid: &Uuid,
+    ) -> Result<Vec<Uuid>, OurError> {
+        let query_str = "DELETE FROM resumable_uploads WHERE user_uuid = $1 RETURNING uuid";
+        Ok(sqlx::query_scalar(query_str)
+            .bind(user_uuid)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_expired(connection: &mut PgConnection) -> Result<Vec<Uuid>, OurError> {
+        let query_str = "SELECT uuid FROM resumable_uploads WHERE expires_at <= CURRENT_TIMESTAMP";
+        Ok(sqlx::query_scalar(query_str)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn destroy_expired(connection: &mut PgConnection) -> Result<Vec<Uuid>, OurError> {
+        let query_str =
+            "DELETE FROM resumable_uploads WHERE expires_at <= CURRENT_TIMESTAMP RETURNING uuid";
+        Ok(sqlx::query_scalar(query_str)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+}
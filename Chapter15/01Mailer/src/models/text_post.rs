@@ -1,3 +1,4 @@
+use crate::media::link_preview::linkify;
 use crate::models::post::Post;
 use crate::traits::DisplayPostContent;
 
@@ -11,7 +12,14 @@ impl<'a> TextPost<'a> {
 
 impl<'a> DisplayPostContent for TextPost<'a> {
     fn raw_html(&self) -> String {
-        format!("<p>{}</p>", self.0.content)
+        let card = self
+            .0
+            .link_preview
+            .0
+            .as_ref()
+            .and_then(|preview| preview.to_html())
+            .unwrap_or_default();
+        format!("<p>{}</p>{}", linkify(&self.0.content), card)
     }
 }
 
@@ -21,8 +29,12 @@ mod tests {
     use crate::models::our_date_time::OurDateTime;
     use crate::models::post::Post;
     use crate::models::post_type::PostType;
+    use crate::models::processing_status::ProcessingStatus;
+    use crate::models::publish_status::PublishStatus;
+    use crate::models::visibility::Visibility;
     use crate::traits::DisplayPostContent;
     use chrono::{offset::Utc, TimeZone};
+    use sqlx::types::Json;
     use uuid::Uuid;
 
     #[test]
@@ -34,6 +46,19 @@ mod tests {
             post_type: PostType::Text,
             content: String::from("hello"),
             created_at: created_at,
+            processing_status: ProcessingStatus::Ready,
+            processing_error: None,
+            variants: Json(vec![]),
+            poster: None,
+            duration: None,
+            media_size: 0,
+            media: Json(vec![]),
+            link_url: None,
+            link_preview: Json(None),
+            visibility: Visibility::Public,
+            publish_status: PublishStatus::Published,
+            publish_at: None,
+            deleted_at: None,
         };
         let text_post = TextPost::new(&post);
         assert!(
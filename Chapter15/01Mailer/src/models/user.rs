@@ -33,12 +33,19 @@ pub struct User {
     pub status: UserStatus,
     pub created_at: OurDateTime,
     pub updated_at: OurDateTime,
+    pub is_admin: bool,
+    #[serde(skip_serializing)]
+    pub storage_quota: Option<i64>,
+    #[serde(skip_serializing)]
+    pub daily_post_limit: Option<i32>,
+    #[serde(skip_serializing)]
+    pub deleted_at: Option<OurDateTime>,
 }
 
 impl User {
     pub async fn find(connection: &mut PgConnection, uuid: &str) -> Result<Self, OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
-        let query_str = "SELECT * FROM users WHERE uuid = $1";
+        let query_str = "SELECT * FROM users WHERE uuid = $1 AND deleted_at IS NULL";
         Ok(sqlx::query_as::<_, Self>(query_str)
             .bind(parsed_uuid)
             .fetch_one(connection)
@@ -50,7 +57,7 @@ impl User {
         connection: &mut PgConnection,
         login: &'r Login<'r>,
     ) -> Result<Self, OurError> {
-        let query_str = "SELECT * FROM users WHERE username = $1";
+        let query_str = "SELECT * FROM users WHERE username = $1 AND deleted_at IS NULL";
         let user = sqlx::query_as::<_, Self>(query_str)
             .bind(&login.username)
             .fetch_one(connection)
@@ -61,6 +68,18 @@ impl User {
         Ok(user)
     }
 
+    pub async fn find_by_usernames(
+        connection: &mut PgConnection,
+        usernames: &[String],
+    ) -> Result<Vec<Self>, OurError> {
+        let query_str = "SELECT * FROM users WHERE username = ANY($1) AND deleted_at IS NULL";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(usernames)
+            .fetch_all(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
     pub async fn find_all(
         db: &mut Connection<DBConnection>,
         pagination: Option<Pagination>,
@@ -75,7 +94,8 @@ impl User {
     async fn find_all_without_pagination(
         db: &mut Connection<DBConnection>,
     ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
-        let query_str = "SELECT * FROM users ORDER BY created_at DESC LIMIT $1";
+        let query_str =
+            "SELECT * FROM users WHERE deleted_at IS NULL ORDER BY created_at DESC LIMIT $1";
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
         let users = sqlx::query_as::<_, Self>(query_str)
             .bind(DEFAULT_LIMIT as i32)
@@ -84,7 +104,7 @@ impl User {
             .map_err(OurError::from_sqlx_error)?;
         let mut new_pagination: Option<Pagination> = None;
         if users.len() == DEFAULT_LIMIT {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
+            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT 1)";
             let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
             let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
                 .bind(&users.last().unwrap().created_at)
@@ -106,7 +126,7 @@ impl User {
         pagination: &Pagination,
     ) -> Result<(Vec<Self>, Option<Pagination>), OurError> {
         let query_str =
-            "SELECT * FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT $2";
+            "SELECT * FROM users WHERE created_at < $1 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT $2";
         let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
         let users = sqlx::query_as::<_, Self>(query_str)
             .bind(&pagination.next)
@@ -116,7 +136,7 @@ impl User {
             .map_err(OurError::from_sqlx_error)?;
         let mut new_pagination: Option<Pagination> = None;
         if users.len() == pagination.limit {
-            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 ORDER BY created_at DESC LIMIT 1)";
+            let query_str = "SELECT EXISTS(SELECT 1 FROM users WHERE created_at < $1 AND deleted_at IS NULL ORDER BY created_at DESC LIMIT 1)";
             let connection = db.acquire().await.map_err(OurError::from_sqlx_error)?;
             let exists = sqlx::query_as::<_, BoolWrapper>(query_str)
                 .bind(&users.last().unwrap().created_at)
@@ -140,16 +160,7 @@ impl User {
         let uuid = Uuid::new_v4();
         let username = &(clean_html(new_user.username));
         let description = &(new_user.description.map(|desc| clean_html(desc)));
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2
-            .hash_password(new_user.password.as_bytes(), &salt)
-            .map_err(|e| {
-                OurError::new_internal_server_error(
-                    String::from("Something went wrong"),
-                    Some(Box::new(e)),
-                )
-            })?;
+        let password_hash = hash_password(new_user.password)?;
 
         let query_str = r#"INSERT INTO users
 (uuid, username, email, password_hash, description, status)
@@ -160,7 +171,7 @@ RETURNING *"#;
             .bind(uuid)
             .bind(username)
             .bind(new_user.email)
-            .bind(password_hash.to_string())
+            .bind(password_hash)
             .bind(description)
             .bind(UserStatus::Inactive)
             .fetch_one(connection)
@@ -168,6 +179,32 @@ RETURNING *"#;
             .map_err(OurError::from_sqlx_error)?)
     }
 
+    pub async fn import(
+        connection: &mut PgConnection,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+        description: Option<&str>,
+        created_at: &OurDateTime,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"INSERT INTO users
+(uuid, username, email, password_hash, description, status, created_at, updated_at)
+VALUES
+($1, $2, $3, $4, $5, $6, $7, $7)
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(Uuid::new_v4())
+            .bind(clean_html(username))
+            .bind(email)
+            .bind(password_hash)
+            .bind(description.map(clean_html))
+            .bind(UserStatus::Active)
+            .bind(created_at)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
     pub async fn update<'r>(
         db: &mut Connection<DBConnection>,
         uuid: &'r str,
@@ -225,7 +262,102 @@ RETURNING *"#;
             .map_err(OurError::from_sqlx_error)?)
     }
 
+    pub async fn update_quota(
+        connection: &mut PgConnection,
+        uuid: &str,
+        storage_quota: Option<i64>,
+        daily_post_limit: Option<i32>,
+    ) -> Result<Self, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = r#"UPDATE users
+SET storage_quota = $1, daily_post_limit = $2, updated_at = $3
+WHERE uuid = $4
+RETURNING *"#;
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(storage_quota)
+            .bind(daily_post_limit)
+            .bind(OurDateTime(Utc::now()))
+            .bind(parsed_uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
     pub async fn destroy(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str =
+            "UPDATE users SET deleted_at = NOW() WHERE uuid = $1 AND deleted_at IS NULL";
+        let result = sqlx::query(query_str)
+            .bind(parsed_uuid)
+            .execute(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        if result.rows_affected() == 0 {
+            return Err(OurError::new_not_found_error(
+                String::from("User not found"),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> Result<(), OurError> {
+        verify_password(&Argon2::default(), &self.password_hash, password).map_err(|_| {
+            OurError::new_bad_request_error(String::from("Password is incorrect"), None)
+        })
+    }
+
+    pub async fn is_pending_deletion<'r>(
+        connection: &mut PgConnection,
+        login: &'r Login<'r>,
+    ) -> Result<bool, OurError> {
+        let query_str = "SELECT * FROM users WHERE username = $1 AND deleted_at IS NOT NULL";
+        let user = sqlx::query_as::<_, Self>(query_str)
+            .bind(&login.username)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        Ok(user.map_or(false, |user| user.check_password(login.password).is_ok()))
+    }
+
+    pub async fn restore<'r>(
+        connection: &mut PgConnection,
+        login: &'r Login<'r>,
+        grace_period: u64,
+    ) -> Result<Self, OurError> {
+        let query_str = r#"SELECT * FROM users
+WHERE username = $1 AND deleted_at > NOW() - ($2 * INTERVAL '1 second')"#;
+        let user = sqlx::query_as::<_, Self>(query_str)
+            .bind(&login.username)
+            .bind(grace_period as f64)
+            .fetch_one(&mut *connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?;
+        user.check_password(login.password)?;
+        let query_str =
+            "UPDATE users SET deleted_at = NULL, updated_at = $1 WHERE uuid = $2 RETURNING *";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(OurDateTime(Utc::now()))
+            .bind(user.uuid)
+            .fetch_one(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn find_deleted(
+        connection: &mut PgConnection,
+        uuid: &str,
+    ) -> Result<Option<Self>, OurError> {
+        let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
+        let query_str = "SELECT * FROM users WHERE uuid = $1 AND deleted_at IS NOT NULL";
+        Ok(sqlx::query_as::<_, Self>(query_str)
+            .bind(parsed_uuid)
+            .fetch_optional(connection)
+            .await
+            .map_err(OurError::from_sqlx_error)?)
+    }
+
+    pub async fn purge(connection: &mut PgConnection, uuid: &str) -> Result<(), OurError> {
         let parsed_uuid = Uuid::parse_str(uuid).map_err(OurError::from_uuid_error)?;
         let query_str = "DELETE FROM users WHERE uuid = $1";
         sqlx::query(query_str)
@@ -320,6 +452,19 @@ fn skip_validate_password<'v>(
     Ok(())
 }
 
+pub fn hash_password(password: &str) -> Result<String, OurError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| {
+            OurError::new_internal_server_error(
+                String::from("Something went wrong"),
+                Some(Box::new(e)),
+            )
+        })?;
+    Ok(password_hash.to_string())
+}
+
 fn verify_password(ag: &Argon2, reference: &str, password: &str) -> Result<(), OurError> {
     let reference_hash = PasswordHash::new(reference).map_err(|e| {
         OurError::new_internal_server_error(String::from("Input error"), Some(Box::new(e)))
@@ -334,6 +479,12 @@ fn verify_password(ag: &Argon2, reference: &str, password: &str) -> Result<(), O
         })?)
 }
 
+#[derive(FromForm)]
+pub struct DeleteAccount<'r> {
+    pub password: &'r str,
+    pub authenticity_token: &'r str,
+}
+
 #[derive(FromForm)]
 pub struct Login<'r> {
     pub username: &'r str,
@@ -1,4 +1,5 @@
-use super::post::Post;
+use super::post::{Post, REJECTED_HTML};
+use super::processing_status::ProcessingStatus;
 use crate::traits::DisplayPostContent;
 
 pub struct VideoPost<'a>(&'a Post);
@@ -11,17 +12,53 @@ impl<'a> VideoPost<'a> {
 
 impl<'a> DisplayPostContent for VideoPost<'a> {
     fn raw_html(&self) -> String {
-        if self.0.content.starts_with("loading") {
-            return String::from(
-                "<figure><img src=\"/assets/loading.gif\" class=\"section media\"/></figure>",
-            );
+        match self.0.processing_status {
+            ProcessingStatus::Queued | ProcessingStatus::Processing => {
+                return String::from(
+                    "<figure><img src=\"/assets/loading.gif\" class=\"section media\"/></figure>",
+                );
+            }
+            ProcessingStatus::Failed => {
+                return String::from(
+                    "<figure class=\"section media\"><figcaption>This video could not be processed.</figcaption></figure>",
+                );
+            }
+            ProcessingStatus::Rejected => {
+                return String::from(REJECTED_HTML);
+            }
+            ProcessingStatus::Ready => {}
         }
+        let poster = match &self.0.poster {
+            Some(poster) => format!(" poster=\"{}\"", poster),
+            None => String::new(),
+        };
+        let sources = if self.0.variants.0.is_empty() {
+            format!(
+                "    <source src=\"{}\" type=\"video/mp4\">\n",
+                self.0.content
+            )
+        } else {
+            let (hls, progressive): (Vec<_>, Vec<_>) = self
+                .0
+                .variants
+                .0
+                .iter()
+                .partition(|variant| variant.label == "hls");
+            hls.into_iter()
+                .chain(progressive)
+                .map(|variant| {
+                    format!(
+                        "    <source src=\"{}\" type=\"{}\">\n",
+                        variant.path, variant.mime
+                    )
+                })
+                .collect::<String>()
+        };
         format!(
-            r#"<video width="320" height="240" controls>
-    <source src="{}" type="video/mp4">
-    Your browser does not support the video tag.
+            r#"<video width="320" height="240" controls preload="metadata"{}>
+{}    Your browser does not support the video tag.
 </video>"#,
-            self.0.content
+            poster, sources
         )
     }
 }
@@ -0,0 +1,51 @@
+use rocket::form::FromFormField;
+use rocket::serde::{Deserialize, Serialize};
+use rocket_db_pools::sqlx;
+use std::fmt;
+
+#[derive(sqlx::Type, Debug, FromFormField, Serialize, Deserialize, PartialEq, Clone, Copy)]
+#[repr(i32)]
+pub enum Visibility {
+    Public = 0,
+    Unlisted = 1,
+    Followers = 2,
+    Private = 3,
+}
+
+impl Visibility {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "public" => Some(Visibility::Public),
+            "unlisted" => Some(Visibility::Unlisted),
+            "followers" => Some(Visibility::Followers),
+            "private" => Some(Visibility::Private),
+            _ => None,
+        }
+    }
+
+    pub fn is_listed(&self) -> bool {
+        *self == Visibility::Public
+    }
+
+    // There is no follower graph yet, so followers-only posts are limited to their owner.
+    pub fn is_restricted(&self) -> bool {
+        *self == Visibility::Followers || *self == Visibility::Private
+    }
+}
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility::Public
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Visibility::Public => write!(f, "Public"),
+            Visibility::Unlisted => write!(f, "Unlisted"),
+            Visibility::Followers => write!(f, "Followers"),
+            Visibility::Private => write!(f, "Private"),
+        }
+    }
+}
@@ -1,5 +1,10 @@
+use rocket::serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
 pub struct Message {
     pub uuid: String,
+    #[serde(default)]
+    pub media_uuid: String,
     pub orig_filename: String,
     pub dest_filename: String,
 }
@@ -8,8 +13,33 @@ impl Message {
     pub fn new() -> Self {
         Message {
             uuid: String::new(),
+            media_uuid: String::new(),
             orig_filename: String::new(),
             dest_filename: String::new(),
         }
     }
+
+    pub fn is_album_item(&self) -> bool {
+        !self.media_uuid.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LinkPreviewMessage {
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PublishMessage {
+    pub uuid: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PurgeMessage {
+    pub uuid: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportMessage {
+    pub uuid: String,
 }
@@ -0,0 +1,2 @@
+pub mod ranged_file;
+pub mod tus;
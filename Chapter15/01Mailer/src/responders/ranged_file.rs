@@ -0,0 +1,263 @@
+use chrono::{offset::Utc, DateTime};
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+// Per RFC 9110 a Range header that cannot be parsed is ignored and the full
+// representation is served. Only well-formed ranges outside the file are
+// unsatisfiable.
+impl ByteRange {
+    pub fn parse(header: &str, len: u64) -> Self {
+        let header = header.trim();
+        let specs = match header.split_once('=') {
+            Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+            _ => return ByteRange::Full,
+        };
+        let specs: Vec<&str> = specs.split(',').map(str::trim).collect();
+        if specs.len() != 1 {
+            return ByteRange::Full;
+        }
+        let (first, last) = match specs[0].split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => return ByteRange::Full,
+        };
+        if first.is_empty() {
+            return match parse_position(last) {
+                Some(suffix) if suffix > 0 && len > 0 => {
+                    ByteRange::Partial(len.saturating_sub(suffix), len - 1)
+                }
+                Some(_) => ByteRange::Unsatisfiable,
+                None => ByteRange::Full,
+            };
+        }
+        let start = match parse_position(first) {
+            Some(start) => start,
+            None => return ByteRange::Full,
+        };
+        let end = match last {
+            "" => None,
+            last => match parse_position(last) {
+                Some(end) if end >= start => Some(end),
+                _ => return ByteRange::Full,
+            },
+        };
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial(start, end.map_or(len - 1, |end| end.min(len - 1)))
+    }
+}
+
+fn parse_position(position: &str) -> Option<u64> {
+    if position.is_empty() || !position.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    position.parse().ok()
+}
+
+pub struct RangedFile {
+    file: File,
+    len: u64,
+    etag: String,
+    last_modified: Option<String>,
+    content_type: Option<ContentType>,
+}
+
+impl RangedFile {
+    pub async fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = File::open(path.as_ref()).await?;
+        let metadata = file.metadata().await?;
+        if !metadata.is_file() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Not a regular file",
+            ));
+        }
+        let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+        let etag = format!(
+            "\"{:x}-{:x}.{:x}\"",
+            metadata.len(),
+            modified.map(|m| m.timestamp()).unwrap_or(0),
+            modified.map(|m| m.timestamp_subsec_nanos()).unwrap_or(0)
+        );
+        let content_type = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ContentType::from_extension(&ext.to_string_lossy()));
+        Ok(RangedFile {
+            file,
+            len: metadata.len(),
+            etag,
+            last_modified: modified.map(|m| m.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+            content_type,
+        })
+    }
+
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = Some(content_type);
+        self
+    }
+
+    fn if_range_matches(&self, if_range: Option<&str>) -> bool {
+        match if_range.map(str::trim) {
+            None => true,
+            Some(validator) if validator.starts_with("W/") => false,
+            Some(validator) if validator.starts_with('"') => validator == self.etag,
+            Some(date) => self.last_modified.as_deref() == Some(date),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for RangedFile {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let headers = request.headers();
+        let range = match headers.get_one("Range") {
+            Some(range) if self.if_range_matches(headers.get_one("If-Range")) => {
+                ByteRange::parse(range, self.len)
+            }
+            _ => ByteRange::Full,
+        };
+
+        let mut response = Response::build();
+        response
+            .raw_header("Accept-Ranges", "bytes")
+            .raw_header("ETag", self.etag.clone());
+        if let Some(last_modified) = self.last_modified.clone() {
+            response.raw_header("Last-Modified", last_modified);
+        }
+        if let Some(content_type) = self.content_type.clone() {
+            response.header(content_type);
+        }
+
+        let (start, end) = match range {
+            ByteRange::Full => (0, self.len),
+            ByteRange::Partial(start, end) => {
+                response.status(Status::PartialContent).header(Header::new(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, self.len),
+                ));
+                (start, end + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                return Response::build()
+                    .status(Status::RangeNotSatisfiable)
+                    .raw_header("Accept-Ranges", "bytes")
+                    .raw_header("Content-Range", format!("bytes */{}", self.len))
+                    .ok();
+            }
+        };
+
+        let body = FileWindow {
+            file: self.file,
+            start,
+            end,
+            position: start,
+            seek: InitialSeek::Pending,
+        };
+        response.sized_body(Some((end - start) as usize), body).ok()
+    }
+}
+
+struct FileWindow {
+    file: File,
+    start: u64,
+    end: u64,
+    position: u64,
+    seek: InitialSeek,
+}
+
+// The responder cannot await, so the window seeks to its start on first read.
+#[derive(PartialEq)]
+enum InitialSeek {
+    Pending,
+    Running,
+    Done,
+}
+
+impl FileWindow {
+    fn poll_initial_seek(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if self.seek == InitialSeek::Pending {
+            Pin::new(&mut self.file).start_seek(SeekFrom::Start(self.start))?;
+            self.seek = InitialSeek::Running;
+        }
+        if self.seek == InitialSeek::Running {
+            match Pin::new(&mut self.file).poll_complete(cx) {
+                Poll::Ready(Ok(_)) => self.seek = InitialSeek::Done,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for FileWindow {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let remaining = self.end.saturating_sub(self.position) as usize;
+        if remaining == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        match self.poll_initial_seek(cx) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+        let limit = remaining.min(buf.remaining());
+        let destination = buf.initialize_unfilled_to(limit);
+        let mut window = ReadBuf::new(destination);
+        match Pin::new(&mut self.file).poll_read(cx, &mut window) {
+            Poll::Ready(Ok(())) => {
+                let read = window.filled().len();
+                buf.advance(read);
+                self.position += read as u64;
+                Poll::Ready(Ok(()))
+            }
+            other => other,
+        }
+    }
+}
+
+impl AsyncSeek for FileWindow {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let target = match position {
+            SeekFrom::Start(offset) => self.start as i128 + offset as i128,
+            SeekFrom::End(offset) => self.end as i128 + offset as i128,
+            SeekFrom::Current(offset) => self.position as i128 + offset as i128,
+        };
+        if target < self.start as i128 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Seek before the start of the range",
+            ));
+        }
+        let target = (target as u64).min(self.end);
+        self.seek = InitialSeek::Done;
+        Pin::new(&mut self.file).start_seek(SeekFrom::Start(target))
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        match Pin::new(&mut self.file).poll_complete(cx) {
+            Poll::Ready(Ok(position)) => {
+                self.position = position;
+                Poll::Ready(Ok(position - self.start))
+            }
+            other => other,
+        }
+    }
+}
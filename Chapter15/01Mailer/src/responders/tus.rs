@@ -0,0 +1,66 @@
+use crate::errors::our_error::OurError;
+use chrono::{offset::Utc, DateTime};
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::Cursor;
+
+pub const TUS_VERSION: &str = "1.0.0";
+
+pub struct TusResponse {
+    status: Status,
+    headers: Vec<Header<'static>>,
+    body: Option<String>,
+}
+
+impl TusResponse {
+    pub fn new(status: Status) -> Self {
+        TusResponse {
+            status,
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    pub fn error(error: OurError) -> Self {
+        TusResponse::new(error.status).body(error.message)
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.headers.push(Header::new(name, value.to_string()));
+        self
+    }
+
+    pub fn expires(self, expires_at: &DateTime<Utc>) -> Self {
+        self.header(
+            "Upload-Expires",
+            expires_at.format("%a, %d %b %Y %H:%M:%S GMT"),
+        )
+    }
+
+    pub fn body(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+impl<'r> Responder<'r, 'static> for TusResponse {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response
+            .status(self.status)
+            .raw_header("Tus-Resumable", TUS_VERSION)
+            .raw_header("Cache-Control", "no-store");
+        for header in self.headers {
+            response.header(header);
+        }
+        if let Some(body) = self.body {
+            response.sized_body(body.len(), Cursor::new(body));
+        }
+        response.ok()
+    }
+}
@@ -1,11 +1,18 @@
+use super::post::save_upload;
 use crate::errors::our_error::OurError;
 use crate::fairings::db::DBConnection;
 use crate::guards::auth::APIUser;
+use crate::guards::upload::UploadPipeline;
 use crate::models::{
+    notification::{Notification, NotificationsWrapper, UnreadCount},
     pagination::Pagination,
+    post::{NewAPIPost, Post, PostStatus, PostsWrapper},
     user::{Auth, JWTLogin, User, UsersWrapper},
 };
 use crate::states::JWToken;
+use crate::storage::Storage;
+use rocket::form::Form;
+use rocket::response::status::Custom;
 use rocket::serde::json::Json;
 use rocket::State;
 use rocket_db_pools::{sqlx::Acquire, Connection};
@@ -54,3 +61,209 @@ pub async fn login<'r>(
             .map_err(|_| OurError::new_internal_server_error(String::from("Cannot login"), None))?,
     ))
 }
+
+#[get("/notifications", format = "json", data = "<pagination>")]
+pub async fn notifications(
+    mut db: Connection<DBConnection>,
+    pagination: Option<Json<Pagination>>,
+    authorized_user: APIUser,
+) -> Result<Json<NotificationsWrapper>, Json<OurError>> {
+    let parsed_pagination = pagination.map(|p| p.into_inner());
+    let user_uuid = authorized_user.user.uuid.to_string();
+    let (notifications, new_pagination) =
+        Notification::find_all(&mut db, &user_uuid, parsed_pagination)
+            .await
+            .map_err(|_| {
+                OurError::new_internal_server_error(String::from("Internal Error"), None)
+            })?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    let unread_count = Notification::count_unread(connection, &authorized_user.user.uuid)
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Ok(Json(NotificationsWrapper {
+        notifications,
+        unread_count,
+        pagination: new_pagination,
+    }))
+}
+
+#[get("/notifications/unread_count", format = "json")]
+pub async fn unread_notifications_count(
+    mut db: Connection<DBConnection>,
+    authorized_user: APIUser,
+) -> Result<Json<UnreadCount>, Json<OurError>> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    let unread_count = Notification::count_unread(connection, &authorized_user.user.uuid)
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Ok(Json(UnreadCount { unread_count }))
+}
+
+#[post("/notifications/<uuid>/read", format = "json")]
+pub async fn read_notification(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    authorized_user: APIUser,
+) -> Result<Json<Notification>, Json<OurError>> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Ok(Json(
+        Notification::mark_read(connection, &authorized_user.user.uuid, uuid)
+            .await
+            .map_err(|e| {
+                OurError::new_not_found_error(
+                    String::from("Cannot find notification"),
+                    Some(Box::new(e)),
+                )
+            })?,
+    ))
+}
+
+#[post("/notifications/read_all", format = "json")]
+pub async fn read_all_notifications(
+    mut db: Connection<DBConnection>,
+    authorized_user: APIUser,
+) -> Result<Json<UnreadCount>, Json<OurError>> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Notification::mark_all_read(connection, &authorized_user.user.uuid)
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Ok(Json(UnreadCount { unread_count: 0 }))
+}
+
+#[get("/users/<user_uuid>/posts", format = "json", data = "<pagination>")]
+pub async fn posts(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    pagination: Option<Json<Pagination>>,
+    authorized_user: Option<APIUser>,
+) -> Result<Json<PostsWrapper>, Json<OurError>> {
+    let parsed_pagination = pagination.map(|p| p.into_inner());
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    User::find(connection, user_uuid).await.map_err(Json)?;
+    let is_owner = authorized_user.map_or(false, |au| au.user.uuid.to_string() == user_uuid);
+    let (posts, new_pagination) = Post::find_all(&mut db, user_uuid, parsed_pagination, is_owner)
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    Ok(Json(PostsWrapper {
+        posts,
+        pagination: new_pagination,
+    }))
+}
+
+#[post(
+    "/users/<user_uuid>/posts",
+    format = "multipart/form-data",
+    data = "<upload>"
+)]
+pub async fn create_post<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    mut upload: Form<NewAPIPost<'r>>,
+    pipeline: UploadPipeline<'_>,
+    authorized_user: APIUser,
+) -> Result<Json<Post>, Custom<Json<OurError>>> {
+    let error = |e: OurError| Custom(e.status, Json(e));
+    if authorized_user.user.uuid.to_string() != user_uuid {
+        return Err(error(OurError::new_unauthorized_error(None)));
+    }
+    let connection = db.acquire().await.map_err(|_| {
+        error(OurError::new_internal_server_error(
+            String::from("Internal Error"),
+            None,
+        ))
+    })?;
+    let settings = upload.settings().map_err(error)?;
+    let post = save_upload(
+        connection,
+        &authorized_user.user,
+        &mut upload.files,
+        &settings,
+        &pipeline,
+    )
+    .await
+    .map_err(error)?;
+    Ok(Json(post))
+}
+
+#[get("/users/<user_uuid>/posts/<uuid>", format = "json")]
+pub async fn post(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    authorized_user: Option<APIUser>,
+) -> Result<Json<Post>, Json<OurError>> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    User::find(connection, user_uuid).await.map_err(Json)?;
+    let post = Post::find(connection, uuid).await.map_err(Json)?;
+    let viewer = authorized_user.map(|au| au.user.uuid);
+    if post.user_uuid.to_string() != user_uuid || !post.can_view(viewer.as_ref()) {
+        return Err(Json(OurError::new_not_found_error(
+            String::from("Not found"),
+            None,
+        )));
+    }
+    Ok(Json(post))
+}
+
+#[get("/users/<user_uuid>/posts/<uuid>/status", format = "json")]
+pub async fn post_status(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    authorized_user: APIUser,
+) -> Result<Json<PostStatus>, Json<OurError>> {
+    if authorized_user.user.uuid.to_string() != user_uuid {
+        return Err(Json(OurError::new_unauthorized_error(None)));
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    let post = Post::find(connection, uuid).await.map_err(Json)?;
+    if post.user_uuid != authorized_user.user.uuid {
+        return Err(Json(OurError::new_not_found_error(
+            String::from("Not found"),
+            None,
+        )));
+    }
+    Ok(Json(PostStatus::from(&post)))
+}
+
+#[post("/users/<user_uuid>/posts/<uuid>/retry", format = "json")]
+pub async fn retry_post(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    storage: &State<Storage>,
+    authorized_user: APIUser,
+) -> Result<Json<PostStatus>, Json<OurError>> {
+    if authorized_user.user.uuid.to_string() != user_uuid {
+        return Err(Json(OurError::new_unauthorized_error(None)));
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| OurError::new_internal_server_error(String::from("Internal Error"), None))?;
+    let post = Post::retry_processing(connection, storage, user_uuid, uuid)
+        .await
+        .map_err(Json)?;
+    Ok(Json(PostStatus::from(&post)))
+}
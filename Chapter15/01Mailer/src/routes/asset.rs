@@ -0,0 +1,158 @@
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::LOGIN_COOKIE_NAME;
+use crate::models::post::Post;
+use crate::responders::ranged_file::RangedFile;
+use crate::storage::{validate_key, Storage, UPLOAD_PREFIX};
+use crate::workers::export::EXPORT_PREFIX;
+use crate::workers::gc::is_managed;
+use rocket::fs::relative;
+use rocket::http::{ContentType, CookieJar, Header, Status};
+use rocket::response::Redirect;
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const PLAYLIST_CACHE: &str = "public, max-age=60";
+const SEGMENT_CACHE: &str = "public, max-age=31536000, immutable";
+const MEDIA_CACHE: &str = "public, max-age=86400";
+const PRIVATE_CACHE: &str = "private, no-cache";
+
+#[derive(Responder)]
+pub struct MediaFile {
+    file: RangedFile,
+    cache_control: Header<'static>,
+    content_security_policy: Header<'static>,
+    content_type_options: Header<'static>,
+}
+
+impl MediaFile {
+    pub fn new(file: RangedFile, cache_control: &'static str) -> Self {
+        MediaFile {
+            file,
+            cache_control: Header::new("Cache-Control", cache_control),
+            content_security_policy: Header::new("Content-Security-Policy", "sandbox"),
+            content_type_options: Header::new("X-Content-Type-Options", "nosniff"),
+        }
+    }
+}
+
+#[derive(Responder)]
+pub enum MediaResponse {
+    File(MediaFile),
+    Redirect(Redirect),
+}
+
+async fn stored_media(
+    storage: &Storage,
+    key: &str,
+    content_type: Option<ContentType>,
+    cache_control: &'static str,
+) -> Result<MediaResponse, Status> {
+    validate_key(key).map_err(|_| Status::NotFound)?;
+    match storage.local_path(key) {
+        Some(path) => {
+            let mut file = RangedFile::open(path).await.map_err(|_| Status::NotFound)?;
+            if let Some(content_type) = content_type {
+                file = file.content_type(content_type);
+            }
+            Ok(MediaResponse::File(MediaFile::new(file, cache_control)))
+        }
+        None => {
+            let url = storage
+                .presigned_url(key, storage.presign_expiry)
+                .map_err(|_| Status::NotFound)?;
+            Ok(MediaResponse::Redirect(Redirect::temporary(url)))
+        }
+    }
+}
+
+// Media that only belongs to restricted posts is served to their owners and
+// must not end up in shared caches. Managed media that no post references is
+// not served at all.
+async fn authorize(
+    db: &mut Connection<DBConnection>,
+    cookies: &CookieJar<'_>,
+    urls: &[String],
+    cache_control: &'static str,
+) -> Result<&'static str, Status> {
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let viewers = Post::media_viewers(connection, urls)
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    if viewers.is_empty() {
+        return Err(Status::NotFound);
+    }
+    if viewers
+        .iter()
+        .all(|(_, visibility)| !visibility.is_restricted())
+    {
+        return Ok(cache_control);
+    }
+    let current_user = cookies
+        .get_private(LOGIN_COOKIE_NAME)
+        .and_then(|cookie| Uuid::parse_str(cookie.value()).ok());
+    let allowed = viewers.iter().any(|(user_uuid, visibility)| {
+        !visibility.is_restricted() || Some(user_uuid) == current_user.as_ref()
+    });
+    if !allowed {
+        return Err(Status::NotFound);
+    }
+    Ok(PRIVATE_CACHE)
+}
+
+#[get("/hls/<uuid>/<filename>")]
+pub async fn hls(
+    mut db: Connection<DBConnection>,
+    cookies: &CookieJar<'_>,
+    uuid: &str,
+    filename: &str,
+    storage: &State<Storage>,
+) -> Result<MediaResponse, Status> {
+    let parsed_uuid = Uuid::parse_str(uuid).map_err(|_| Status::NotFound)?;
+    let (stem, extension) = filename.rsplit_once('.').ok_or(Status::NotFound)?;
+    if stem.is_empty() || !stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Status::NotFound);
+    }
+    let (content_type, cache_control) = match extension {
+        "m3u8" => (
+            ContentType::new("application", "vnd.apple.mpegurl"),
+            PLAYLIST_CACHE,
+        ),
+        "ts" => (ContentType::new("video", "mp2t"), SEGMENT_CACHE),
+        _ => return Err(Status::NotFound),
+    };
+    let key = format!("hls/{}/{}", parsed_uuid, filename);
+    let master = storage.url(&format!("hls/{}/master.m3u8", parsed_uuid));
+    let cache_control = authorize(&mut db, cookies, &[master], cache_control).await?;
+    stored_media(storage, &key, Some(content_type), cache_control).await
+}
+
+#[get("/<path..>", rank = 10)]
+pub async fn file(
+    mut db: Connection<DBConnection>,
+    cookies: &CookieJar<'_>,
+    path: PathBuf,
+    storage: &State<Storage>,
+) -> Result<MediaResponse, Status> {
+    let key = path
+        .iter()
+        .map(|segment| segment.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    if key.starts_with(EXPORT_PREFIX) || key.starts_with(UPLOAD_PREFIX) {
+        return Err(Status::NotFound);
+    }
+    let cache_control = if is_managed(&key) {
+        authorize(&mut db, cookies, &[storage.url(&key)], MEDIA_CACHE).await?
+    } else {
+        MEDIA_CACHE
+    };
+    if let Ok(file) = RangedFile::open(Path::new(relative!("static")).join(&path)).await {
+        return Ok(MediaResponse::File(MediaFile::new(file, cache_control)));
+    }
+    stored_media(storage, &key, None, cache_control).await
+}
@@ -0,0 +1,192 @@
+use super::post::{attach_link_preview, schedule_publish};
+use super::HtmlResponse;
+use crate::events::{notify_mentions, publish_new_post, EventHub};
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::media::link_preview::find_urls;
+use crate::models::{
+    post::{EditDraft, Post, PostSettings, ShowPost},
+    post_type::PostType,
+    publish_status::PublishStatus,
+};
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+
+const DRAFT_ERROR: &str = "Something went wrong when updating draft";
+
+#[get("/users/<user_uuid>/drafts", format = "text/html")]
+pub async fn get_drafts(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    user_uuid: &str,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let posts = Post::find_unpublished(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let show_posts: Vec<ShowPost> = posts.into_iter().map(|post| post.to_show_post()).collect();
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        posts: &show_posts,
+        csrf_token,
+    };
+    Ok(Template::render("drafts/index", context))
+}
+
+#[get("/users/<user_uuid>/drafts/<uuid>", format = "text/html")]
+pub async fn edit_draft(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    user_uuid: &str,
+    uuid: &str,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let post = Post::find(connection, uuid).await.map_err(|e| e.status)?;
+    if post.user_uuid != current_user.user.uuid || post.is_published() {
+        return Err(Status::NotFound);
+    }
+    let content = if post.post_type == PostType::Text {
+        Some(post.content.clone())
+    } else {
+        None
+    };
+    let publish_at = post
+        .publish_at
+        .as_ref()
+        .map(|publish_at| publish_at.0.format("%Y-%m-%dT%H:%M").to_string());
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        post: &(post.to_show_post()),
+        content,
+        publish_at,
+        csrf_token,
+    };
+    Ok(Template::render("drafts/edit", context))
+}
+
+#[post(
+    "/users/<user_uuid>/drafts/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<draft>"
+)]
+pub async fn update_draft<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    draft: Form<EditDraft<'r>>,
+    hub: &State<EventHub>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let edit_err = |message: &str| {
+        Flash::error(
+            Redirect::to(format!("/users/{}/drafts/{}", user_uuid, uuid)),
+            String::from(message),
+        )
+    };
+    csrf_token
+        .verify(&draft.authenticity_token)
+        .map_err(|_| edit_err(DRAFT_ERROR))?;
+    if current_user.is_not(user_uuid) {
+        return Err(edit_err(DRAFT_ERROR));
+    }
+    let connection = db.acquire().await.map_err(|_| edit_err(DRAFT_ERROR))?;
+    let post = Post::find(connection, uuid)
+        .await
+        .map_err(|_| edit_err(DRAFT_ERROR))?;
+    if post.user_uuid != current_user.user.uuid {
+        return Err(edit_err(DRAFT_ERROR));
+    }
+    if post.is_published() {
+        return Err(edit_err("Post has already been published"));
+    }
+    let settings = PostSettings::new(
+        draft.visibility.unwrap_or(post.visibility),
+        draft.publish_status,
+        draft.publish_at,
+    )
+    .map_err(|e| edit_err(&e.message))?;
+    let content = match (&draft.content, post.post_type) {
+        (Some(content), PostType::Text) => Some(content.as_str()),
+        _ => None,
+    };
+    if content.map_or(false, |content| content.trim().is_empty()) {
+        return Err(edit_err("Content cannot be empty"));
+    }
+    let updated = save_draft(connection, post, content, &settings).await;
+    let post = updated.map_err(|e| {
+        log::warn!("Cannot update draft {}: {}", uuid, e);
+        edit_err(DRAFT_ERROR)
+    })?;
+
+    let (location, message) = match post.publish_status {
+        PublishStatus::Published => {
+            publish_new_post(hub, &post);
+            notify_mentions(connection, hub, &post).await;
+            (
+                format!("/users/{}/posts/{}", user_uuid, uuid),
+                "Successfully published post",
+            )
+        }
+        PublishStatus::Scheduled => (
+            format!("/users/{}/drafts", user_uuid),
+            "Successfully scheduled post",
+        ),
+        PublishStatus::Draft => (
+            format!("/users/{}/drafts", user_uuid),
+            "Successfully saved draft",
+        ),
+    };
+    Ok(Flash::success(Redirect::to(location), message))
+}
+
+async fn save_draft(
+    connection: &mut PgConnection,
+    mut post: Post,
+    content: Option<&str>,
+    settings: &PostSettings,
+) -> Result<Post, String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    if let Some(content) = content {
+        post = Post::update_draft(&mut transaction, &post.uuid, content, settings.visibility)
+            .await
+            .map_err(|e| e.message)?;
+        let url = find_urls(content).into_iter().next();
+        if url != post.link_url {
+            attach_link_preview(&mut transaction, &mut post, url).await?;
+        }
+    }
+    let post = Post::update_publish_status(&mut transaction, &post.uuid, settings)
+        .await
+        .map_err(|e| e.message)?;
+    schedule_publish(&mut transaction, &post).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(post)
+}
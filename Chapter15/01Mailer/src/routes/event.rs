@@ -0,0 +1,37 @@
+use crate::events::EventHub;
+use crate::guards::auth::AuthenticatedUser;
+use rocket::response::stream::{Event as StreamEvent, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Shutdown, State};
+use uuid::Uuid;
+
+#[get("/events?<watch>")]
+pub async fn events(
+    hub: &State<EventHub>,
+    watch: Vec<&str>,
+    authenticated_user: AuthenticatedUser,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    let mut rx = hub.subscribe();
+    let user_uuid = authenticated_user.user.uuid;
+    let watched: Vec<Uuid> = watch
+        .iter()
+        .filter_map(|uuid| Uuid::parse_str(uuid).ok())
+        .collect();
+    EventStream! {
+        loop {
+            let event = select! {
+                received = rx.recv() => match received {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+            if event.is_for(&user_uuid, &watched) {
+                yield StreamEvent::json(&event).event(event.name());
+            }
+        }
+    }
+}
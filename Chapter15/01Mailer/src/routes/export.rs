@@ -0,0 +1,209 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::models::export::{Export, RequestExport};
+use crate::models::export_status::ExportStatus;
+use crate::models::job::Job;
+use crate::models::job_type::JobType;
+use crate::models::quota::format_bytes;
+use crate::models::worker::ExportMessage;
+use crate::responders::ranged_file::RangedFile;
+use crate::storage::Storage;
+use crate::workers::export::ExportConfig;
+use chrono::offset::Utc;
+use rocket::form::Form;
+use rocket::http::{ContentType, Header, Status};
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use uuid::Uuid;
+
+const EXPORT_CACHE: &str = "private, no-store";
+
+#[derive(Serialize)]
+struct ExportItem {
+    uuid: String,
+    status: ExportStatus,
+    size: String,
+    error: Option<String>,
+    created_at: String,
+    expires_at: Option<String>,
+    downloadable: bool,
+}
+
+#[derive(Responder)]
+pub struct ExportFile {
+    file: RangedFile,
+    cache_control: Header<'static>,
+    content_disposition: Header<'static>,
+}
+
+#[derive(Responder)]
+pub enum ExportResponse {
+    File(ExportFile),
+    Redirect(Redirect),
+}
+
+#[get("/users/<user_uuid>/exports", format = "text/html")]
+pub async fn get_exports(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    user_uuid: &str,
+    export_config: &State<ExportConfig>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let exports = Export::find_all(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let items: Vec<ExportItem> = exports
+        .iter()
+        .map(|export| ExportItem {
+            uuid: export.uuid.to_string(),
+            status: export.status,
+            size: format_bytes(export.size),
+            error: export.error.clone(),
+            created_at: export.created_at.0.format("%Y-%m-%d %H:%M UTC").to_string(),
+            expires_at: export
+                .expires_at
+                .as_ref()
+                .map(|expires_at| expires_at.0.format("%Y-%m-%d %H:%M UTC").to_string()),
+            downloadable: export.is_downloadable(),
+        })
+        .collect();
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        items,
+        ttl_days: export_config.ttl / 86400,
+        csrf_token,
+    };
+    Ok(Template::render("exports/index", context))
+}
+
+#[post(
+    "/users/<user_uuid>/exports",
+    format = "application/x-www-form-urlencoded",
+    data = "<request>"
+)]
+pub async fn create_export<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    request: Form<RequestExport<'r>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let export_err = |message: &str| {
+        Flash::error(
+            Redirect::to(format!("/users/{}/exports", user_uuid)),
+            String::from(message),
+        )
+    };
+    csrf_token
+        .verify(&request.authenticity_token)
+        .map_err(|_| export_err("Something went wrong when requesting export"))?;
+    if current_user.is_not(user_uuid) {
+        return Err(export_err("Something went wrong when requesting export"));
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| export_err("Something went wrong when requesting export"))?;
+    let pending = Export::has_pending(connection, &current_user.user.uuid)
+        .await
+        .map_err(|_| export_err("Something went wrong when requesting export"))?;
+    if pending {
+        return Err(export_err("An export is already being prepared"));
+    }
+    enqueue_export(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| {
+            log::warn!("Cannot request export for {}: {}", user_uuid, e);
+            export_err("Something went wrong when requesting export")
+        })?;
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/exports", user_uuid)),
+        "Your export is being prepared, you will be notified when it is ready",
+    ))
+}
+
+#[get("/users/<user_uuid>/exports/<uuid>/download")]
+pub async fn download_export(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    storage: &State<Storage>,
+    current_user: CurrentUser,
+) -> Result<ExportResponse, Status> {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::NotFound);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let export = Export::find(connection, uuid, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    if !export.is_downloadable() {
+        return Err(Status::NotFound);
+    }
+    let key = export.storage_key.as_deref().ok_or(Status::NotFound)?;
+    match storage.local_path(key) {
+        Some(path) => {
+            let file = RangedFile::open(path)
+                .await
+                .map_err(|_| Status::NotFound)?
+                .content_type(ContentType::ZIP);
+            Ok(ExportResponse::File(ExportFile {
+                file,
+                cache_control: Header::new("Cache-Control", EXPORT_CACHE),
+                content_disposition: Header::new(
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}\"", export.filename()),
+                ),
+            }))
+        }
+        None => {
+            let remaining = export
+                .expires_at
+                .as_ref()
+                .map_or(0, |expires_at| (expires_at.0 - Utc::now()).num_seconds())
+                .max(1) as u64;
+            let url = storage
+                .presigned_url(key, storage.presign_expiry.min(remaining))
+                .map_err(|_| Status::NotFound)?;
+            Ok(ExportResponse::Redirect(Redirect::temporary(url)))
+        }
+    }
+}
+
+async fn enqueue_export(connection: &mut PgConnection, user_uuid: &Uuid) -> Result<(), String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let export = Export::create(&mut transaction, user_uuid)
+        .await
+        .map_err(|e| e.message)?;
+    Job::enqueue(
+        &mut transaction,
+        JobType::ExportData,
+        &ExportMessage {
+            uuid: export.uuid.to_string(),
+        },
+    )
+    .await
+    .map_err(|e| e.message)?;
+    transaction.commit().await.map_err(|e| e.to_string())
+}
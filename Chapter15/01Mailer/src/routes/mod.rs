@@ -3,8 +3,15 @@ use rocket::response::Redirect;
 use rocket::Shutdown;
 use rocket_dyn_templates::Template;
 
+pub mod asset;
+pub mod draft;
+pub mod event;
+pub mod export;
+pub mod notification;
 pub mod post;
 pub mod session;
+pub mod trash;
+pub mod tus;
 pub mod user;
 pub mod api;
 
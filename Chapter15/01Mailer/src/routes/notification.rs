@@ -0,0 +1,98 @@
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::models::{
+    notification::{Notification, ReadNotification},
+    pagination::Pagination,
+};
+use rocket::form::Form;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_dyn_templates::{context, Template};
+
+#[get("/notifications?<pagination>", format = "text/html")]
+pub async fn get_notifications(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    pagination: Option<Pagination>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    let flash_message = flash.map(|fm| String::from(fm.message()));
+    let user_uuid = current_user.user.uuid.to_string();
+    let (notifications, new_pagination) = Notification::find_all(&mut db, &user_uuid, pagination)
+        .await
+        .map_err(|e| e.status)?;
+    let context = context! {
+        flash: flash_message,
+        current_user,
+        notifications,
+        pagination: new_pagination.map(|pg| pg.to_context()),
+        csrf_token,
+    };
+    Ok(Template::render("notifications/index", context))
+}
+
+#[post(
+    "/notifications/<uuid>/read",
+    format = "application/x-www-form-urlencoded",
+    data = "<read_notification>"
+)]
+pub async fn read_notification<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    read_notification: Form<ReadNotification<'r>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Redirect, Flash<Redirect>> {
+    let read_error = || {
+        Flash::error(
+            Redirect::to("/notifications"),
+            "Something went wrong when updating notification",
+        )
+    };
+    csrf_token
+        .verify(&read_notification.authenticity_token)
+        .map_err(|_| read_error())?;
+    let connection = db.acquire().await.map_err(|_| read_error())?;
+    let notification = Notification::mark_read(connection, &current_user.user.uuid, uuid)
+        .await
+        .map_err(|_| read_error())?;
+    Ok(Redirect::to(
+        notification
+            .link
+            .unwrap_or_else(|| String::from("/notifications")),
+    ))
+}
+
+#[post(
+    "/notifications/read_all",
+    format = "application/x-www-form-urlencoded",
+    data = "<read_notification>"
+)]
+pub async fn read_all_notifications<'r>(
+    mut db: Connection<DBConnection>,
+    read_notification: Form<ReadNotification<'r>>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let read_error = || {
+        Flash::error(
+            Redirect::to("/notifications"),
+            "Something went wrong when updating notifications",
+        )
+    };
+    csrf_token
+        .verify(&read_notification.authenticity_token)
+        .map_err(|_| read_error())?;
+    let connection = db.acquire().await.map_err(|_| read_error())?;
+    Notification::mark_all_read(connection, &current_user.user.uuid)
+        .await
+        .map_err(|_| read_error())?;
+    Ok(Flash::success(
+        Redirect::to("/notifications"),
+        "All notifications marked as read",
+    ))
+}
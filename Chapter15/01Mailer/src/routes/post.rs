@@ -1,32 +1,66 @@
 use super::HtmlResponse;
 use crate::errors::our_error::OurError;
+use crate::events::{notify_mentions, publish_new_post};
 use crate::fairings::csrf::Token as CsrfToken;
 use crate::fairings::db::DBConnection;
 use crate::guards::auth::CurrentUser;
+use crate::guards::upload::UploadPipeline;
+use crate::media::link_preview::find_urls;
+use crate::media::photo::{process_photo, ProcessedPhoto};
+use crate::media::scan::{check_upload, quarantine, ScanConfig, ScanResult};
+use crate::media::svg::{rasterize, sanitize};
 use crate::models::{
+    job::Job,
+    job_type::JobType,
+    link_preview::LinkPreview,
+    media_blob::MediaBlob,
+    media_variant::MediaVariant,
+    our_date_time::OurDateTime,
     pagination::Pagination,
-    post::{NewPost, Post, ShowPost},
+    post::{NewPost, Post, PostSettings, RetryPost, ShowPost},
+    post_media::{EditAlbum, PostMedia, ShowPostMedia},
     post_type::PostType,
+    processing_status::ProcessingStatus,
+    publish_status::PublishStatus,
+    quota::Quota,
     user::User,
-    worker::Message,
+    visibility::Visibility,
+    worker::{LinkPreviewMessage, Message, PublishMessage},
 };
-use flume::Sender;
-use image::codecs::jpeg::JpegEncoder;
-use image::error::ImageError;
-use image::io::Reader as ImageReader;
-use image::{DynamicImage, ImageEncoder};
+use crate::storage::blobs::{delete_blobs, is_shared, store_dir};
+use crate::storage::{Storage, UPLOAD_PREFIX};
+use crate::workers::trash::{schedule_purge, TrashConfig};
+use chrono::offset::Utc;
 use rocket::form::Form;
-use rocket::http::Status;
+use rocket::fs::TempFile;
+use rocket::http::{ContentType, Status};
 use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
 use rocket::State;
-use rocket_db_pools::{sqlx::Acquire, Connection};
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
 use rocket_dyn_templates::{context, Template};
-use std::io::Cursor;
 use std::ops::Deref;
-use std::path::Path;
-use tokio::fs::{remove_file, File};
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, remove_dir_all, remove_file, File};
 use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+const UPLOAD_ERROR: &str = "Something went wrong when uploading file";
+const AUDIO_SUBTYPES: &[(&str, &str)] = &[
+    ("mpeg", "mp3"),
+    ("mp3", "mp3"),
+    ("ogg", "ogg"),
+    ("wav", "wav"),
+    ("x-wav", "wav"),
+    ("wave", "wav"),
+    ("vnd.wave", "wav"),
+    ("flac", "flac"),
+    ("x-flac", "flac"),
+    ("mp4", "m4a"),
+    ("m4a", "m4a"),
+    ("x-m4a", "m4a"),
+];
 
 #[get("/users/<user_uuid>/posts/<uuid>", format = "text/html")]
 pub async fn get_post(
@@ -50,6 +84,9 @@ pub async fn get_post(
     if post.user_uuid != user.uuid {
         return Err(Status::InternalServerError);
     }
+    if !post.can_view(current_user.as_ref().map(|cu| &cu.user.uuid)) {
+        return Err(Status::NotFound);
+    }
 
     let context = context! {
         user,
@@ -70,7 +107,8 @@ pub async fn get_posts(
 ) -> HtmlResponse {
     let flash_message = flash.map(|fm| String::from(fm.message()));
     let user = User::find(&mut db, user_uuid).await.map_err(|e| e.status)?;
-    let (posts, new_pagination) = Post::find_all(&mut db, user_uuid, pagination)
+    let is_owner = current_user.as_ref().map_or(false, |cu| cu.is(user_uuid));
+    let (posts, new_pagination) = Post::find_all(&mut db, user_uuid, pagination, is_owner)
         .await
         .map_err(|e| e.status)?;
 
@@ -96,135 +134,42 @@ pub async fn create_post<'r>(
     mut db: Connection<DBConnection>,
     user_uuid: &str,
     mut upload: Form<NewPost<'r>>,
-    tx: &State<Sender<Message>>,
+    pipeline: UploadPipeline<'_>,
     csrf_token: CsrfToken,
     current_user: CurrentUser,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    let create_err = || {
+    let create_err = |message: &str| {
         Flash::error(
             Redirect::to(format!("/users/{}/posts", user_uuid)),
-            "Something went wrong when uploading file",
+            String::from(message),
         )
     };
     csrf_token
         .verify(&upload.authenticity_token)
-        .map_err(|_| create_err())?;
+        .map_err(|_| create_err(UPLOAD_ERROR))?;
     if current_user.is_not(user_uuid) {
-        return Err(create_err());
-    }
-    let file_uuid = uuid::Uuid::new_v4().to_string();
-    if upload.file.content_type().is_none() {
-        return Err(create_err());
-    }
-    let ext = upload.file.content_type().unwrap().extension().unwrap();
-    let tmp_filename = format!("/tmp/{}.{}", &file_uuid, &ext);
-    upload
-        .file
-        .persist_to(tmp_filename)
-        .await
-        .map_err(|_| create_err())?;
-    let mut content = String::new();
-    let mut post_type = PostType::Text;
-    let mt = upload.file.content_type().unwrap().deref();
-    let mut wm = Message::new();
-    let mut is_video = false;
-    if mt.is_text() {
-        let orig_path = upload.file.path().unwrap().to_string_lossy().to_string();
-        let mut text_content = vec![];
-        let _ = File::open(orig_path)
-            .await
-            .map_err(|_| create_err())?
-            .read_to_end(&mut text_content)
-            .await
-            .map_err(|_| create_err())?;
-        content.push_str(std::str::from_utf8(&text_content).unwrap());
-    } else if mt.is_bmp() || mt.is_jpeg() || mt.is_png() || mt.is_gif() {
-        post_type = PostType::Photo;
-        let orig_path = upload.file.path().unwrap().to_string_lossy().to_string();
-        let dest_filename = format!("{}.jpg", file_uuid);
-        content.push_str("/assets/");
-        content.push_str(&dest_filename);
-
-        let orig_file = tokio::fs::read(orig_path).await.map_err(|_| create_err())?;
-        let read_buffer = Cursor::new(orig_file);
-        let encoded_result: Result<DynamicImage, ()> = tokio::task::spawn_blocking(|| {
-            Ok(ImageReader::new(read_buffer)
-                .with_guessed_format()
-                .map_err(|_| ())?
-                .decode()
-                .map_err(|_| ())?)
-        })
-        .await
-        .map_err(|_| create_err())?;
-        let image = encoded_result.map_err(|_| create_err())?;
-
-        let write_result: Result<Vec<u8>, ImageError> = tokio::task::spawn_blocking(move || {
-            let mut write_buffer: Vec<u8> = vec![];
-            let mut write_cursor = Cursor::new(&mut write_buffer);
-            let _ = JpegEncoder::new_with_quality(&mut write_cursor, 75).write_image(
-                image.as_bytes(),
-                image.width(),
-                image.height(),
-                image.color(),
-            )?;
-
-            Ok(write_buffer)
-        })
-        .await
-        .map_err(|_| create_err())?;
-        let write_bytes = write_result.map_err(|_| create_err())?;
-        let dest_path = Path::new(rocket::fs::relative!("static")).join(&dest_filename);
-        tokio::fs::write(dest_path, &write_bytes)
-            .await
-            .map_err(|_| create_err())?;
-    } else if mt.is_svg() {
-        post_type = PostType::Photo;
-        let dest_filename = format!("{}.svg", file_uuid);
-        content.push_str("/assets/");
-        content.push_str(&dest_filename);
-        let dest_path = Path::new(rocket::fs::relative!("static")).join(&dest_filename);
-        upload
-            .file
-            .move_copy_to(&dest_path)
-            .await
-            .map_err(|_| create_err())?;
-    } else if mt.is_mp4() || mt.is_mpeg() || mt.is_ogg() || mt.is_mov() || mt.is_webm() {
-        post_type = PostType::Video;
-        let dest_filename = format!("{}.mp4", file_uuid);
-        content.push_str("loading/assets/");
-        content.push_str(&dest_filename);
-        is_video = true;
-        wm.orig_filename = upload
-            .file
-            .path()
-            .unwrap()
-            .to_string_lossy()
-            .to_string()
-            .clone();
-        wm.dest_filename = dest_filename.clone();
-    } else {
-        return Err(create_err());
+        return Err(create_err(UPLOAD_ERROR));
     }
-    let connection = db.acquire().await.map_err(|_| create_err())?;
-
-    Ok(Post::create(connection, user_uuid, post_type, &content)
-        .await
-        .and_then(move |post| {
-            if is_video {
-                wm.uuid = post.uuid.to_string();
-                let _ = tx.send(wm).map_err(|_| {
-                    OurError::new_internal_server_error(
-                        String::from("Cannot process message"),
-                        None,
-                    )
-                })?;
-            }
-            Ok(Flash::success(
-                Redirect::to(format!("/users/{}/posts", user_uuid)),
-                "Successfully created post",
-            ))
-        })
-        .map_err(|_| create_err())?)
+    let settings = upload.settings().map_err(|e| create_err(&e.message))?;
+    let connection = db.acquire().await.map_err(|_| create_err(UPLOAD_ERROR))?;
+    save_upload(
+        connection,
+        &current_user.user,
+        &mut upload.files,
+        &settings,
+        &pipeline,
+    )
+    .await
+    .map_err(|e| create_err(&e.message))?;
+    let message = match settings.publish_status {
+        PublishStatus::Published => "Successfully created post",
+        PublishStatus::Draft => "Successfully saved draft",
+        PublishStatus::Scheduled => "Successfully scheduled post",
+    };
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/posts", user_uuid)),
+        message,
+    ))
 }
 
 #[post(
@@ -235,6 +180,7 @@ pub async fn delete_post(
     mut db: Connection<DBConnection>,
     user_uuid: &str,
     uuid: &str,
+    trash_config: &State<TrashConfig>,
     current_user: CurrentUser,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
     let delete_err = || {
@@ -254,19 +200,962 @@ pub async fn delete_post(
         return Err(delete_err());
     }
 
-    Ok({
-        let _ = Post::destroy(connection, uuid)
+    trash_post(connection, trash_config, &post)
+        .await
+        .map_err(|_| delete_err())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/posts", user_uuid)),
+        "Post moved to trash",
+    ))
+}
+
+#[post(
+    "/users/<user_uuid>/posts/<uuid>/retry",
+    format = "application/x-www-form-urlencoded",
+    data = "<retry>"
+)]
+pub async fn retry_post<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    retry: Form<RetryPost<'r>>,
+    storage: &State<Storage>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let retry_err = || {
+        Flash::error(
+            Redirect::to(format!("/users/{}/posts", user_uuid)),
+            "Something went wrong when retrying post",
+        )
+    };
+    csrf_token
+        .verify(&retry.authenticity_token)
+        .map_err(|_| retry_err())?;
+    if current_user.is_not(user_uuid) {
+        return Err(retry_err());
+    }
+    let connection = db.acquire().await.map_err(|_| retry_err())?;
+    Post::retry_processing(connection, storage, user_uuid, uuid)
+        .await
+        .map_err(|_| retry_err())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/posts", user_uuid)),
+        "Post has been queued for processing again",
+    ))
+}
+
+#[get("/users/<user_uuid>/posts/<uuid>/edit", format = "text/html")]
+pub async fn edit_album(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    user_uuid: &str,
+    uuid: &str,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let post = Post::find(connection, uuid).await.map_err(|e| e.status)?;
+    if post.user_uuid != current_user.user.uuid || post.post_type != PostType::Album {
+        return Err(Status::NotFound);
+    }
+    let items: Vec<ShowPostMedia> = post
+        .media
+        .0
+        .iter()
+        .map(|item| item.to_show_post_media(&post))
+        .collect();
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        post: &(post.to_show_post()),
+        items,
+        csrf_token,
+    };
+    Ok(Template::render("posts/edit", context))
+}
+
+#[post(
+    "/users/<user_uuid>/posts/<uuid>/media",
+    format = "application/x-www-form-urlencoded",
+    data = "<album>"
+)]
+pub async fn update_album<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    album: Form<EditAlbum<'r>>,
+    storage: &State<Storage>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let edit_err = |message: &str| {
+        Flash::error(
+            Redirect::to(format!("/users/{}/posts/{}/edit", user_uuid, uuid)),
+            String::from(message),
+        )
+    };
+    let generic_err = "Something went wrong when updating album";
+    csrf_token
+        .verify(&album.authenticity_token)
+        .map_err(|_| edit_err(generic_err))?;
+    if current_user.is_not(user_uuid) {
+        return Err(edit_err(generic_err));
+    }
+    let connection = db.acquire().await.map_err(|_| edit_err(generic_err))?;
+    let post = Post::find(connection, uuid)
+        .await
+        .map_err(|_| edit_err(generic_err))?;
+    if post.user_uuid != current_user.user.uuid || post.post_type != PostType::Album {
+        return Err(edit_err(generic_err));
+    }
+    let removed: Vec<Uuid> = post
+        .media
+        .0
+        .iter()
+        .filter(|item| album.remove.contains(&item.uuid.to_string()))
+        .map(|item| item.uuid)
+        .collect();
+    if removed.len() == post.media.0.len() {
+        return Err(edit_err("An album must keep at least one item"));
+    }
+    let mut order: Vec<(i32, usize, Uuid)> = post
+        .media
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !removed.contains(&item.uuid))
+        .map(|(index, item)| {
+            let position = album
+                .positions
+                .get(&item.uuid.to_string())
+                .copied()
+                .unwrap_or(index as i32 + 1);
+            (position, index, item.uuid)
+        })
+        .collect();
+    order.sort_unstable();
+    let order: Vec<Uuid> = order.into_iter().map(|(_, _, uuid)| uuid).collect();
+
+    let (released, removed_items) = edit_album_media(connection, &post, &order, &removed)
+        .await
+        .map_err(|_| edit_err(generic_err))?;
+    delete_blobs(connection, storage, &released).await;
+    for path in removed_items.iter().flat_map(|item| item.media_paths()) {
+        if storage
+            .key_from_url(&path)
+            .map_or(false, |key| is_shared(&key))
+        {
+            continue;
+        }
+        if let Err(e) = storage.delete_url(&path).await {
+            log::warn!("Cannot delete {}: {}", path, e);
+        }
+    }
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/posts/{}", user_uuid, uuid)),
+        "Successfully updated album",
+    ))
+}
+
+pub async fn save_upload(
+    connection: &mut PgConnection,
+    user: &User,
+    files: &mut [TempFile<'_>],
+    settings: &PostSettings,
+    pipeline: &UploadPipeline<'_>,
+) -> Result<Post, OurError> {
+    let upload_err = || OurError::new_bad_request_error(String::from(UPLOAD_ERROR), None);
+    if files.is_empty() {
+        return Err(upload_err());
+    }
+    if files.len() > pipeline.quota.album_items {
+        return Err(OurError::new_bad_request_error(
+            format!(
+                "An album can contain at most {} files",
+                pipeline.quota.album_items
+            ),
+            None,
+        ));
+    }
+    let mut staged: Vec<(PathBuf, ContentType)> = vec![];
+    let mut persisted = true;
+    for file in files.iter_mut() {
+        match persist_upload(file, pipeline.storage).await {
+            Some(upload) => staged.push(upload),
+            None => {
+                persisted = false;
+                break;
+            }
+        }
+    }
+    let saved = if !persisted {
+        Err(upload_err())
+    } else if staged.len() == 1 {
+        let (staged_path, content_type) = &staged[0];
+        store_upload(
+            connection,
+            user,
+            staged_path,
+            content_type,
+            settings,
+            pipeline,
+        )
+        .await
+    } else {
+        store_album(connection, user, &staged, settings, pipeline).await
+    };
+    for (staged_path, _) in staged.iter() {
+        let _ = std::fs::remove_file(staged_path);
+    }
+    saved
+}
+
+async fn persist_upload(
+    file: &mut TempFile<'_>,
+    storage: &Storage,
+) -> Option<(PathBuf, ContentType)> {
+    let content_type = file.content_type().cloned()?;
+    let ext = extension_for(&content_type)?;
+    let staged_path = storage.staging_path(&format!("{}.{}", Uuid::new_v4(), ext));
+    file.persist_to(&staged_path).await.ok()?;
+    Some((staged_path, content_type))
+}
+
+pub async fn save_staged_upload(
+    connection: &mut PgConnection,
+    user: &User,
+    staged_path: &Path,
+    content_type: &ContentType,
+    settings: &PostSettings,
+    pipeline: &UploadPipeline<'_>,
+) -> Result<Post, OurError> {
+    let saved = store_upload(
+        connection,
+        user,
+        staged_path,
+        content_type,
+        settings,
+        pipeline,
+    )
+    .await;
+    let _ = std::fs::remove_file(staged_path);
+    saved
+}
+
+pub async fn save_staged_album(
+    connection: &mut PgConnection,
+    user: &User,
+    staged: &[(PathBuf, ContentType)],
+    settings: &PostSettings,
+    pipeline: &UploadPipeline<'_>,
+) -> Result<Post, OurError> {
+    let saved = if staged.len() > pipeline.quota.album_items {
+        Err(OurError::new_bad_request_error(
+            format!(
+                "An album can contain at most {} files",
+                pipeline.quota.album_items
+            ),
+            None,
+        ))
+    } else {
+        store_album(connection, user, staged, settings, pipeline).await
+    };
+    for (staged_path, _) in staged.iter() {
+        let _ = std::fs::remove_file(staged_path);
+    }
+    saved
+}
+
+async fn store_upload(
+    connection: &mut PgConnection,
+    user: &User,
+    staged_path: &Path,
+    content_type: &ContentType,
+    settings: &PostSettings,
+    pipeline: &UploadPipeline<'_>,
+) -> Result<Post, OurError> {
+    let upload_err = || OurError::new_bad_request_error(String::from(UPLOAD_ERROR), None);
+    let storage = pipeline.storage;
+    let upload_size = match tokio::fs::metadata(staged_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Err(upload_err()),
+    };
+    // Rejects uploads that are already over quota before processing them.
+    // The check is repeated under a lock when the post is inserted.
+    Quota::find(connection, pipeline.quota, user)
+        .await?
+        .check(upload_size)?;
+    let user_uuid = user.uuid.to_string();
+    let post_type = post_type_for(content_type).unwrap_or(PostType::Text);
+    scan_upload(
+        connection,
+        pipeline.scan,
+        &user_uuid,
+        post_type,
+        staged_path,
+    )
+    .await?;
+    let file_uuid = Uuid::new_v4().to_string();
+    let staging_dir = storage.staging_path(&file_uuid);
+    let mut uploaded: Vec<String> = vec![];
+    let prepared = prepare_media(
+        staged_path,
+        content_type,
+        &file_uuid,
+        pipeline,
+        &mut uploaded,
+    )
+    .await;
+    let _ = remove_file(staged_path).await;
+
+    let saved = match prepared {
+        Ok(media) => {
+            insert_post(
+                connection,
+                pipeline,
+                user,
+                media,
+                settings,
+                upload_size,
+                &mut uploaded,
+            )
+            .await
+        }
+        Err(e) => Err(InsertError::Failed(e)),
+    };
+    let _ = remove_dir_all(&staging_dir).await;
+    let post = match saved {
+        Ok(post) => post,
+        Err(e) => {
+            if let InsertError::Failed(message) = &e {
+                log::warn!("Cannot store upload {}: {}", file_uuid, message);
+            }
+            storage.delete_keys(&uploaded).await;
+            return Err(e.into_response(upload_size, upload_err()));
+        }
+    };
+    publish_new_post(pipeline.hub, &post);
+    notify_mentions(connection, pipeline.hub, &post).await;
+    Ok(post)
+}
+
+async fn store_album(
+    connection: &mut PgConnection,
+    user: &User,
+    staged: &[(PathBuf, ContentType)],
+    settings: &PostSettings,
+    pipeline: &UploadPipeline<'_>,
+) -> Result<Post, OurError> {
+    let upload_err = || OurError::new_bad_request_error(String::from(UPLOAD_ERROR), None);
+    let storage = pipeline.storage;
+    let mut upload_size = 0;
+    for (staged_path, content_type) in staged.iter() {
+        match post_type_for(content_type) {
+            Some(PostType::Photo) | Some(PostType::Video) => {}
+            _ => {
+                return Err(OurError::new_bad_request_error(
+                    String::from("Albums can only contain photos and videos"),
+                    None,
+                ))
+            }
+        }
+        upload_size += match tokio::fs::metadata(staged_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Err(upload_err()),
+        };
+    }
+    Quota::find(connection, pipeline.quota, user)
+        .await?
+        .check(upload_size)?;
+    let user_uuid = user.uuid.to_string();
+    for (staged_path, _) in staged.iter() {
+        scan_upload(
+            connection,
+            pipeline.scan,
+            &user_uuid,
+            PostType::Album,
+            staged_path,
+        )
+        .await?;
+    }
+    let mut uploaded: Vec<String> = vec![];
+    let mut staging_dirs = vec![];
+    let mut items = vec![];
+    let mut prepared = Ok(());
+    for (staged_path, content_type) in staged.iter() {
+        let file_uuid = Uuid::new_v4().to_string();
+        staging_dirs.push(storage.staging_path(&file_uuid));
+        match prepare_media(
+            staged_path,
+            content_type,
+            &file_uuid,
+            pipeline,
+            &mut uploaded,
+        )
+        .await
+        {
+            Ok(media) => items.push(media),
+            Err(e) => {
+                prepared = Err(e);
+                break;
+            }
+        }
+    }
+
+    let saved = match prepared {
+        Ok(()) => {
+            insert_album(
+                connection,
+                pipeline,
+                user,
+                items,
+                settings,
+                upload_size,
+                &mut uploaded,
+            )
             .await
-            .map_err(|_| delete_err())?;
-        if post.post_type == PostType::Photo || post.post_type == PostType::Video {
-            remove_file(post.content.replacen("/assets/", "static/", 1))
+        }
+        Err(e) => Err(InsertError::Failed(e)),
+    };
+    for staging_dir in staging_dirs.iter() {
+        let _ = remove_dir_all(staging_dir).await;
+    }
+    let post = match saved {
+        Ok(post) => post,
+        Err(e) => {
+            if let InsertError::Failed(message) = &e {
+                log::warn!("Cannot store album for {}: {}", user_uuid, message);
+            }
+            storage.delete_keys(&uploaded).await;
+            return Err(e.into_response(upload_size, upload_err()));
+        }
+    };
+    publish_new_post(pipeline.hub, &post);
+    Ok(post)
+}
+
+async fn scan_upload(
+    connection: &mut PgConnection,
+    scan_config: &ScanConfig,
+    user_uuid: &str,
+    post_type: PostType,
+    staged_path: &Path,
+) -> Result<(), OurError> {
+    match check_upload(scan_config, staged_path).await {
+        Ok(ScanResult::Clean) => Ok(()),
+        Ok(ScanResult::Infected(signature)) => {
+            reject_upload(
+                connection,
+                scan_config,
+                user_uuid,
+                post_type,
+                staged_path,
+                &signature,
+            )
+            .await;
+            Err(OurError::new_bad_request_error(
+                String::from("Upload was rejected because it contains malware"),
+                None,
+            ))
+        }
+        Err(e) => {
+            log::error!("Cannot scan upload {}: {}", staged_path.display(), e);
+            Err(OurError::new_service_unavailable_error(
+                String::from("Uploads cannot be scanned right now, please try again later"),
+                None,
+            ))
+        }
+    }
+}
+
+struct PreparedMedia {
+    post_type: PostType,
+    content: String,
+    staging_dir: PathBuf,
+    processed: Option<ProcessedPhoto>,
+    variants: Vec<MediaVariant>,
+    hashes: Vec<String>,
+    job: Option<(JobType, Message)>,
+    link_url: Option<String>,
+    media_size: i64,
+}
+
+impl PreparedMedia {
+    fn processing_status(&self) -> ProcessingStatus {
+        if self.job.is_some() {
+            ProcessingStatus::Queued
+        } else {
+            ProcessingStatus::Ready
+        }
+    }
+
+    async fn store(
+        &mut self,
+        connection: &mut PgConnection,
+        storage: &Storage,
+        uploaded: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if let Some(photo) = self.processed.take() {
+            let stored = store_dir(connection, storage, &self.staging_dir).await?;
+            uploaded.extend(stored.uploaded.iter().cloned());
+            self.content = stored.url(storage, &photo.content);
+            self.variants = stored.publish(storage, photo.variants);
+            self.media_size = stored.size;
+            self.hashes = stored.hashes;
+        }
+        Ok(())
+    }
+}
+
+async fn prepare_media(
+    staged_path: &Path,
+    content_type: &ContentType,
+    file_uuid: &str,
+    pipeline: &UploadPipeline<'_>,
+    uploaded: &mut Vec<String>,
+) -> Result<PreparedMedia, String> {
+    let (photo_config, storage) = (pipeline.photo, pipeline.storage);
+    let ext = extension_for(content_type).ok_or_else(|| String::from("Unknown upload type"))?;
+    let mut media = PreparedMedia {
+        post_type: PostType::Text,
+        content: String::new(),
+        staging_dir: storage.staging_path(file_uuid),
+        processed: None,
+        variants: vec![],
+        hashes: vec![],
+        job: None,
+        link_url: None,
+        media_size: 0,
+    };
+    let mt = content_type.deref();
+    if mt.is_text() {
+        let mut text_content = vec![];
+        let mut text_file = File::open(staged_path)
+            .await
+            .map_err(|e| format!("Cannot read upload: {}", e))?;
+        text_file
+            .read_to_end(&mut text_content)
+            .await
+            .map_err(|e| format!("Cannot read upload: {}", e))?;
+        media.content.push_str(
+            std::str::from_utf8(&text_content)
+                .map_err(|e| format!("Upload is not valid text: {}", e))?,
+        );
+        media.link_url = find_urls(&media.content).into_iter().next();
+    } else if mt.is_bmp() || mt.is_jpeg() || mt.is_png() || mt.is_gif() {
+        media.post_type = PostType::Photo;
+        let orig_file = tokio::fs::read(staged_path)
+            .await
+            .map_err(|e| format!("Cannot read upload: {}", e))?;
+        let config = photo_config.clone();
+        let photo = stage_photo(
+            &media.staging_dir,
+            file_uuid,
+            move |output_dir, base_name| process_photo(&config, &orig_file, output_dir, base_name),
+        )
+        .await?;
+        media.processed = Some(photo);
+    } else if mt.is_svg() {
+        media.post_type = PostType::Photo;
+        let svg = tokio::fs::read_to_string(staged_path)
+            .await
+            .map_err(|e| format!("Cannot read upload: {}", e))?;
+        let sanitized = sanitize(&svg)?;
+        let config = photo_config.clone();
+        let photo = stage_photo(
+            &media.staging_dir,
+            file_uuid,
+            move |output_dir, base_name| {
+                if !config.rasterize_svg {
+                    let filename = format!("{}.svg", base_name);
+                    std::fs::write(output_dir.join(&filename), sanitized)
+                        .map_err(|e| format!("Cannot write SVG: {}", e))?;
+                    return Ok(ProcessedPhoto {
+                        content: filename,
+                        variants: vec![],
+                    });
+                }
+                let max_width = config.sizes.iter().map(|s| s.width).max().unwrap_or(1600);
+                let png = rasterize(&sanitized, max_width)?;
+                process_photo(&config, &png, output_dir, base_name)
+            },
+        )
+        .await?;
+        media.processed = Some(photo);
+    } else if let Some(post_type @ (PostType::Video | PostType::Audio)) =
+        post_type_for(content_type)
+    {
+        let (job_type, dest_ext) = match post_type {
+            PostType::Audio => (JobType::ProcessAudio, "m4a"),
+            _ => (JobType::ProcessVideo, "mp4"),
+        };
+        media.post_type = post_type;
+        let dest_filename = format!("{}.{}", file_uuid, dest_ext);
+        media.content.push_str(&storage.url(&dest_filename));
+        let key = format!("{}{}.{}", UPLOAD_PREFIX, file_uuid, ext);
+        let mut wm = Message::new();
+        wm.orig_filename = key.clone();
+        wm.dest_filename = dest_filename;
+        uploaded.push(key.clone());
+        storage.put_file(&key, staged_path).await?;
+        media.media_size = tokio::fs::metadata(staged_path)
+            .await
+            .map(|metadata| metadata.len() as i64)
+            .unwrap_or(0);
+        media.job = Some((job_type, wm));
+    } else {
+        return Err(String::from("Unsupported upload type"));
+    }
+    Ok(media)
+}
+
+pub fn post_type_for(content_type: &ContentType) -> Option<PostType> {
+    let mt = content_type.deref();
+    if is_audio(content_type) {
+        Some(PostType::Audio)
+    } else if mt.is_text() {
+        Some(PostType::Text)
+    } else if mt.is_bmp() || mt.is_jpeg() || mt.is_png() || mt.is_gif() || mt.is_svg() {
+        Some(PostType::Photo)
+    } else if mt.is_mp4() || mt.is_mpeg() || mt.is_ogg() || mt.is_mov() || mt.is_webm() {
+        Some(PostType::Video)
+    } else {
+        None
+    }
+}
+
+fn is_audio(content_type: &ContentType) -> bool {
+    content_type.top() == "audio"
+        && AUDIO_SUBTYPES
+            .iter()
+            .any(|(sub, _)| content_type.sub().as_str().eq_ignore_ascii_case(sub))
+}
+
+pub fn extension_for(content_type: &ContentType) -> Option<String> {
+    if let Some(ext) = content_type.extension() {
+        return Some(ext.to_string());
+    }
+    if !is_audio(content_type) {
+        return None;
+    }
+    AUDIO_SUBTYPES
+        .iter()
+        .find(|(sub, _)| content_type.sub().as_str().eq_ignore_ascii_case(sub))
+        .map(|(_, ext)| String::from(*ext))
+}
+
+async fn reject_upload(
+    connection: &mut PgConnection,
+    scan_config: &ScanConfig,
+    user_uuid: &str,
+    post_type: PostType,
+    staged_path: &Path,
+    signature: &str,
+) {
+    let name = staged_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match quarantine(scan_config, staged_path, &name).await {
+        Ok(path) => log::warn!("Quarantined {} ({})", path.display(), signature),
+        Err(e) => {
+            log::error!("{}", e);
+            let _ = remove_file(staged_path).await;
+        }
+    }
+    let reason = format!("Rejected by malware scanner: {}", signature);
+    let rejected = match Post::create(
+        connection,
+        user_uuid,
+        post_type,
+        "",
+        &[],
+        ProcessingStatus::Rejected,
+        0,
+        &PostSettings {
+            visibility: Visibility::Private,
+            ..PostSettings::default()
+        },
+    )
+    .await
+    {
+        Ok(post) => post,
+        Err(e) => {
+            log::error!("Cannot record rejected upload: {}", e.message);
+            return;
+        }
+    };
+    let _ = Post::update_processing_status(
+        connection,
+        &rejected.uuid.to_string(),
+        ProcessingStatus::Rejected,
+        Some(&reason),
+    )
+    .await;
+}
+
+async fn stage_photo<F>(
+    staging_dir: &Path,
+    file_uuid: &str,
+    process: F,
+) -> Result<ProcessedPhoto, String>
+where
+    F: FnOnce(&Path, &str) -> Result<ProcessedPhoto, String> + Send + 'static,
+{
+    create_dir_all(staging_dir)
+        .await
+        .map_err(|e| format!("Cannot create staging directory: {}", e))?;
+    let output_dir = staging_dir.to_path_buf();
+    let base_name = String::from(file_uuid);
+    tokio::task::spawn_blocking(move || process(&output_dir, &base_name))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+// Failures while inserting an upload. Going over quota is reported to the
+// user, anything else is logged and shown as a generic upload error.
+enum InsertError {
+    OverQuota(Quota),
+    Failed(String),
+}
+
+impl InsertError {
+    fn into_response(self, upload_size: u64, upload_err: OurError) -> OurError {
+        match self {
+            InsertError::OverQuota(quota) => quota.check(upload_size).err().unwrap_or(upload_err),
+            InsertError::Failed(_) => upload_err,
+        }
+    }
+}
+
+impl From<String> for InsertError {
+    fn from(message: String) -> Self {
+        InsertError::Failed(message)
+    }
+}
+
+async fn lock_quota(
+    connection: &mut PgConnection,
+    pipeline: &UploadPipeline<'_>,
+    user: &User,
+    upload_size: u64,
+) -> Result<(), InsertError> {
+    let quota = Quota::find_for_update(connection, pipeline.quota, user)
+        .await
+        .map_err(|e| InsertError::Failed(e.message))?;
+    if quota.check(upload_size).is_err() {
+        return Err(InsertError::OverQuota(quota));
+    }
+    Ok(())
+}
+
+async fn insert_post(
+    connection: &mut PgConnection,
+    pipeline: &UploadPipeline<'_>,
+    user: &User,
+    mut media: PreparedMedia,
+    settings: &PostSettings,
+    upload_size: u64,
+    uploaded: &mut Vec<String>,
+) -> Result<Post, InsertError> {
+    let user_uuid = &user.uuid.to_string();
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    lock_quota(&mut transaction, pipeline, user, upload_size).await?;
+    media
+        .store(&mut transaction, pipeline.storage, uploaded)
+        .await?;
+    let mut post = Post::create(
+        &mut transaction,
+        user_uuid,
+        media.post_type,
+        &media.content,
+        &media.variants,
+        media.processing_status(),
+        media.media_size,
+        settings,
+    )
+    .await
+    .map_err(|e| e.message)?;
+    MediaBlob::link(&mut transaction, &post.uuid, &media.hashes)
+        .await
+        .map_err(|e| e.message)?;
+    if let Some((job_type, wm)) = media.job.as_mut() {
+        wm.uuid = post.uuid.to_string();
+        Job::enqueue(&mut transaction, *job_type, wm)
+            .await
+            .map_err(|e| e.message)?;
+    }
+    if let Some(url) = media.link_url.take() {
+        attach_link_preview(&mut transaction, &mut post, Some(url)).await?;
+    }
+    schedule_publish(&mut transaction, &post).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(post)
+}
+
+async fn insert_album(
+    connection: &mut PgConnection,
+    pipeline: &UploadPipeline<'_>,
+    user: &User,
+    mut items: Vec<PreparedMedia>,
+    settings: &PostSettings,
+    upload_size: u64,
+    uploaded: &mut Vec<String>,
+) -> Result<Post, InsertError> {
+    let user_uuid = &user.uuid.to_string();
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    lock_quota(&mut transaction, pipeline, user, upload_size).await?;
+    for media in items.iter_mut() {
+        media
+            .store(&mut transaction, pipeline.storage, uploaded)
+            .await?;
+    }
+    let post = Post::create(
+        &mut transaction,
+        user_uuid,
+        PostType::Album,
+        "",
+        &[],
+        ProcessingStatus::Queued,
+        0,
+        settings,
+    )
+    .await
+    .map_err(|e| e.message)?;
+    for (position, media) in items.iter_mut().enumerate() {
+        let item = PostMedia::create(
+            &mut transaction,
+            &post.uuid,
+            position as i32,
+            media.post_type,
+            &media.content,
+            &media.variants,
+            media.processing_status(),
+            media.media_size,
+            &media.hashes,
+        )
+        .await
+        .map_err(|e| e.message)?;
+        MediaBlob::link(&mut transaction, &post.uuid, &media.hashes)
+            .await
+            .map_err(|e| e.message)?;
+        if let Some((job_type, wm)) = media.job.as_mut() {
+            wm.uuid = post.uuid.to_string();
+            wm.media_uuid = item.uuid.to_string();
+            Job::enqueue(&mut transaction, *job_type, wm)
                 .await
-                .map_err(|_| delete_err())?;
+                .map_err(|e| e.message)?;
         }
+    }
+    schedule_publish(&mut transaction, &post).await?;
+    let post = Post::refresh_album(&mut transaction, &post.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(post)
+}
 
-        Flash::success(
-            Redirect::to(format!("/users/{}/posts", user_uuid)),
-            "Successfully deleted post",
+pub async fn attach_link_preview(
+    connection: &mut PgConnection,
+    post: &mut Post,
+    url: Option<String>,
+) -> Result<(), String> {
+    LinkPreview::attach(connection, &post.uuid, url.as_deref())
+        .await
+        .map_err(|e| e.message)?;
+    if let Some(url) = url.as_ref() {
+        let requested = LinkPreview::request(connection, url)
+            .await
+            .map_err(|e| e.message)?;
+        if requested.is_some() {
+            Job::enqueue(
+                connection,
+                JobType::FetchLinkPreview,
+                &LinkPreviewMessage { url: url.clone() },
+            )
+            .await
+            .map_err(|e| e.message)?;
+        }
+    }
+    post.link_url = url;
+    Post::load_link_previews(connection, std::slice::from_mut(post))
+        .await
+        .map_err(|e| e.message)
+}
+
+pub async fn schedule_publish(connection: &mut PgConnection, post: &Post) -> Result<(), String> {
+    if let (PublishStatus::Scheduled, Some(publish_at)) = (post.publish_status, &post.publish_at) {
+        Job::enqueue_at(
+            connection,
+            JobType::PublishPost,
+            &PublishMessage {
+                uuid: post.uuid.to_string(),
+            },
+            publish_at,
         )
-    })
+        .await
+        .map_err(|e| e.message)?;
+    }
+    Ok(())
+}
+
+async fn edit_album_media(
+    connection: &mut PgConnection,
+    post: &Post,
+    order: &[Uuid],
+    removed: &[Uuid],
+) -> Result<(Vec<String>, Vec<PostMedia>), String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let removed_items = PostMedia::destroy(&mut transaction, &post.uuid, removed)
+        .await
+        .map_err(|e| e.message)?;
+    let mut hashes: Vec<String> = vec![];
+    for hash in removed_items
+        .iter()
+        .flat_map(|item| item.blob_hashes.iter())
+    {
+        let still_used = post
+            .media
+            .0
+            .iter()
+            .filter(|item| !removed.contains(&item.uuid))
+            .any(|item| item.blob_hashes.contains(hash));
+        if !still_used && !hashes.contains(hash) {
+            hashes.push(hash.clone());
+        }
+    }
+    let released = MediaBlob::unlink(&mut transaction, &post.uuid, &hashes)
+        .await
+        .map_err(|e| e.message)?;
+    PostMedia::reorder(&mut transaction, &post.uuid, order)
+        .await
+        .map_err(|e| e.message)?;
+    Post::refresh_album(&mut transaction, &post.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok((released, removed_items))
+}
+
+async fn trash_post(
+    connection: &mut PgConnection,
+    trash_config: &TrashConfig,
+    post: &Post,
+) -> Result<(), String> {
+    let uuid = post.uuid.to_string();
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    Post::destroy(&mut transaction, &uuid)
+        .await
+        .map_err(|e| e.message)?;
+    let purge_at = trash_config.purge_at(&OurDateTime(Utc::now()));
+    schedule_purge(&mut transaction, JobType::PurgePost, &uuid, &purge_at).await?;
+    transaction.commit().await.map_err(|e| e.to_string())
 }
@@ -42,9 +42,23 @@ pub async fn create<'r>(
         .verify(&login.authenticity_token)
         .map_err(|_| login_error())?;
     let connection = db.acquire().await.map_err(|_| login_error())?;
-    let user = User::find_by_login(connection, login)
-        .await
-        .map_err(|_| login_error())?;
+    let found = User::find_by_login(connection, login).await.ok();
+    let user = match found {
+        Some(user) => user,
+        None => {
+            let pending = User::is_pending_deletion(connection, login)
+                .await
+                .unwrap_or(false);
+            return Err(if pending {
+                Flash::error(
+                    Redirect::to("/users/restore"),
+                    "This account is scheduled for deletion, log in below to restore it",
+                )
+            } else {
+                login_error()
+            });
+        }
+    };
     cookies.add_private(Cookie::new(LOGIN_COOKIE_NAME, user.uuid.to_string()));
 
     Ok(Flash::success(Redirect::to("/users"), "Login successfully"))
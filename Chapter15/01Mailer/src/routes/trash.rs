@@ -0,0 +1,125 @@
+use super::post::schedule_publish;
+use super::HtmlResponse;
+use crate::fairings::csrf::Token as CsrfToken;
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::CurrentUser;
+use crate::models::post::{Post, RestorePost, ShowPost};
+use crate::workers::trash::TrashConfig;
+use rocket::form::Form;
+use rocket::http::Status;
+use rocket::request::FlashMessage;
+use rocket::response::{Flash, Redirect};
+use rocket::serde::Serialize;
+use rocket::State;
+use rocket_db_pools::sqlx::{Acquire, PgConnection};
+use rocket_db_pools::Connection;
+use rocket_dyn_templates::{context, Template};
+use uuid::Uuid;
+
+#[derive(Serialize)]
+struct TrashedPost {
+    post: ShowPost,
+    purge_at: String,
+}
+
+#[get("/users/<user_uuid>/trash", format = "text/html")]
+pub async fn get_trash(
+    mut db: Connection<DBConnection>,
+    flash: Option<FlashMessage<'_>>,
+    user_uuid: &str,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(user_uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| Status::InternalServerError)?;
+    let posts = Post::find_trashed(connection, &current_user.user.uuid)
+        .await
+        .map_err(|e| e.status)?;
+    let items: Vec<TrashedPost> = posts
+        .iter()
+        .filter_map(|post| {
+            let deleted_at = post.deleted_at.as_ref()?;
+            Some(TrashedPost {
+                post: post.to_show_post(),
+                purge_at: trash_config
+                    .purge_at(deleted_at)
+                    .0
+                    .format("%Y-%m-%d %H:%M UTC")
+                    .to_string(),
+            })
+        })
+        .collect();
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        items,
+        retention_days: trash_config.retention / 86400,
+        csrf_token,
+    };
+    Ok(Template::render("trash/index", context))
+}
+
+#[post(
+    "/users/<user_uuid>/trash/<uuid>/restore",
+    format = "application/x-www-form-urlencoded",
+    data = "<restore>"
+)]
+pub async fn restore_post<'r>(
+    mut db: Connection<DBConnection>,
+    user_uuid: &str,
+    uuid: &str,
+    restore: Form<RestorePost<'r>>,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let restore_err = || {
+        Flash::error(
+            Redirect::to(format!("/users/{}/trash", user_uuid)),
+            "Post cannot be restored",
+        )
+    };
+    csrf_token
+        .verify(&restore.authenticity_token)
+        .map_err(|_| restore_err())?;
+    if current_user.is_not(user_uuid) {
+        return Err(restore_err());
+    }
+    let connection = db.acquire().await.map_err(|_| restore_err())?;
+    restore_from_trash(
+        connection,
+        uuid,
+        &current_user.user.uuid,
+        trash_config.retention,
+    )
+    .await
+    .map_err(|e| {
+        log::warn!("Cannot restore post {}: {}", uuid, e);
+        restore_err()
+    })?;
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}/posts/{}", user_uuid, uuid)),
+        "Successfully restored post",
+    ))
+}
+
+async fn restore_from_trash(
+    connection: &mut PgConnection,
+    uuid: &str,
+    user_uuid: &Uuid,
+    retention: u64,
+) -> Result<(), String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let post = Post::restore(&mut transaction, uuid, user_uuid, retention)
+        .await
+        .map_err(|e| e.message)?;
+    schedule_publish(&mut transaction, &post).await?;
+    transaction.commit().await.map_err(|e| e.to_string())
+}
@@ -0,0 +1,320 @@
+use super::post::{post_type_for, save_staged_upload};
+use crate::fairings::db::DBConnection;
+use crate::guards::auth::AuthenticatedUser;
+use crate::guards::upload::UploadPipeline;
+use crate::models::post::PostSettings;
+use crate::models::publish_status::PublishStatus;
+use crate::models::quota::Quota;
+use crate::models::resumable_upload::{ResumableUpload, TusConfig};
+use crate::models::visibility::Visibility;
+use crate::responders::tus::{TusResponse, TUS_VERSION};
+use crate::storage::Storage;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::State;
+use rocket_db_pools::{sqlx::Acquire, Connection};
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use tokio::fs::{create_dir_all, remove_file, File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+pub struct TusHeaders<'r> {
+    pub resumable: Option<&'r str>,
+    pub upload_length: Option<&'r str>,
+    pub upload_offset: Option<&'r str>,
+    pub upload_metadata: Option<&'r str>,
+    pub content_type: Option<&'r str>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TusHeaders<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        Outcome::Success(TusHeaders {
+            resumable: headers.get_one("Tus-Resumable"),
+            upload_length: headers.get_one("Upload-Length"),
+            upload_offset: headers.get_one("Upload-Offset"),
+            upload_metadata: headers.get_one("Upload-Metadata"),
+            content_type: headers.get_one("Content-Type"),
+        })
+    }
+}
+
+impl<'r> TusHeaders<'r> {
+    // Requiring the custom header also keeps cookie-authenticated requests
+    // from being forged cross-site without a CORS preflight.
+    fn check_version(&self) -> Result<(), TusResponse> {
+        if self.resumable != Some(TUS_VERSION) {
+            return Err(
+                TusResponse::new(Status::PreconditionFailed).header("Tus-Version", TUS_VERSION)
+            );
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_metadata(header: &str) -> Result<HashMap<String, String>, String> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, value)) => (key, value.trim()),
+            None => (pair, ""),
+        };
+        if key.is_empty() || metadata.contains_key(key) {
+            return Err(format!("Invalid metadata key: {}", key));
+        }
+        let decoded =
+            base64::decode(value).map_err(|_| format!("Invalid metadata value for {}", key))?;
+        let value = String::from_utf8(decoded)
+            .map_err(|_| format!("Invalid metadata value for {}", key))?;
+        metadata.insert(String::from(key), value);
+    }
+    Ok(metadata)
+}
+
+pub fn content_type_from_metadata(metadata: &HashMap<String, String>) -> Option<ContentType> {
+    let declared = ["filetype", "type", "contentType"]
+        .iter()
+        .find_map(|key| metadata.get(*key))
+        .and_then(|filetype| ContentType::parse_flexible(filetype));
+    let content_type = declared.or_else(|| {
+        metadata
+            .get("filename")
+            .and_then(|filename| filename.rsplit_once('.'))
+            .and_then(|(_, ext)| ContentType::from_extension(ext))
+    })?;
+    post_type_for(&content_type).map(|_| content_type)
+}
+
+fn parse_offset(value: Option<&str>) -> Result<u64, TusResponse> {
+    value
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or_else(|| TusResponse::new(Status::BadRequest))
+}
+
+#[options("/uploads")]
+pub async fn options(config: &State<TusConfig>) -> TusResponse {
+    TusResponse::new(Status::NoContent)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS)
+        .header("Tus-Max-Size", config.max_size)
+}
+
+#[post("/uploads")]
+pub async fn create(
+    mut db: Connection<DBConnection>,
+    tus: TusHeaders<'_>,
+    config: &State<TusConfig>,
+    pipeline: UploadPipeline<'_>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<TusResponse, TusResponse> {
+    tus.check_version()?;
+    let upload_length = parse_offset(tus.upload_length)?;
+    if upload_length > config.max_size {
+        return Err(TusResponse::new(Status::PayloadTooLarge));
+    }
+    let metadata = parse_metadata(tus.upload_metadata.unwrap_or(""))
+        .map_err(|e| TusResponse::new(Status::BadRequest).body(e))?;
+    let content_type = content_type_from_metadata(&metadata)
+        .ok_or_else(|| TusResponse::new(Status::UnsupportedMediaType))?;
+    let visibility = match metadata.get("visibility") {
+        Some(value) => Visibility::parse(value).ok_or_else(|| {
+            TusResponse::new(Status::BadRequest).body(String::from("Invalid visibility"))
+        })?,
+        None => Visibility::Public,
+    };
+    let publish_status = match metadata.get("publish_status") {
+        Some(value) => PublishStatus::parse(value).ok_or_else(|| {
+            TusResponse::new(Status::BadRequest).body(String::from("Invalid publish status"))
+        })?,
+        None => PublishStatus::Published,
+    };
+    let settings = PostSettings::new(
+        visibility,
+        publish_status,
+        metadata.get("publish_at").map(String::as_str),
+    )
+    .map_err(TusResponse::error)?;
+
+    let internal_err = || TusResponse::new(Status::InternalServerError);
+    let connection = db.acquire().await.map_err(|_| internal_err())?;
+    let user = &authenticated_user.user;
+    Quota::find(connection, pipeline.quota, user)
+        .await
+        .and_then(|quota| quota.check(upload_length))
+        .map_err(TusResponse::error)?;
+    let upload = ResumableUpload::create(
+        connection,
+        &user.uuid,
+        metadata.get("filename").map(String::as_str),
+        &content_type.to_string(),
+        upload_length as i64,
+        &settings,
+        &config.expires_at(),
+    )
+    .await
+    .map_err(TusResponse::error)?;
+    let path = pipeline.storage.resumable_path(&upload.uuid.to_string());
+    let created = match path.parent() {
+        Some(dir) => create_dir_all(dir)
+            .await
+            .and(File::create(&path).await.map(|_| ())),
+        None => Ok(()),
+    };
+    if created.is_err() {
+        let _ = ResumableUpload::destroy(connection, &upload.uuid).await;
+        return Err(internal_err());
+    }
+    Ok(TusResponse::new(Status::Created)
+        .header("Location", format!("/uploads/{}", upload.uuid))
+        .expires(&upload.expires_at.0))
+}
+
+#[head("/uploads/<uuid>")]
+pub async fn head(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    tus: TusHeaders<'_>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<TusResponse, TusResponse> {
+    tus.check_version()?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| TusResponse::new(Status::InternalServerError))?;
+    let upload = ResumableUpload::find(connection, uuid, &authenticated_user.user.uuid)
+        .await
+        .map_err(|_| TusResponse::new(Status::NotFound))?;
+    Ok(TusResponse::new(Status::Ok)
+        .header("Upload-Offset", upload.upload_offset)
+        .header("Upload-Length", upload.upload_length)
+        .expires(&upload.expires_at.0))
+}
+
+#[patch("/uploads/<uuid>", data = "<data>")]
+pub async fn patch(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    data: Data<'_>,
+    tus: TusHeaders<'_>,
+    config: &State<TusConfig>,
+    pipeline: UploadPipeline<'_>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<TusResponse, TusResponse> {
+    tus.check_version()?;
+    if tus.content_type.map(str::trim) != Some(OFFSET_CONTENT_TYPE) {
+        return Err(TusResponse::new(Status::UnsupportedMediaType));
+    }
+    let offset = parse_offset(tus.upload_offset)?;
+    let internal_err = || TusResponse::new(Status::InternalServerError);
+    let connection = db.acquire().await.map_err(|_| internal_err())?;
+    let user = &authenticated_user.user;
+    let upload = ResumableUpload::find(connection, uuid, &user.uuid)
+        .await
+        .map_err(|_| TusResponse::new(Status::NotFound))?;
+
+    // The row lock is held until the new offset is committed, so only one
+    // request at a time can write to the file.
+    let mut transaction = connection.begin().await.map_err(|_| internal_err())?;
+    let upload = ResumableUpload::lock(&mut transaction, &upload.uuid)
+        .await
+        .map_err(|_| internal_err())?
+        .ok_or_else(|| TusResponse::new(Status::Locked))?;
+    if upload.is_complete() || upload.upload_offset as u64 != offset {
+        return Err(
+            TusResponse::new(Status::Conflict).header("Upload-Offset", upload.upload_offset)
+        );
+    }
+
+    let path = pipeline.storage.resumable_path(&upload.uuid.to_string());
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .await
+        .map_err(|_| TusResponse::new(Status::NotFound))?;
+    file.set_len(offset).await.map_err(|_| internal_err())?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(|_| internal_err())?;
+    let remaining = upload.upload_length as u64 - offset;
+    let streamed = data.open(remaining.bytes()).stream_to(&mut file).await;
+    file.flush().await.map_err(|_| internal_err())?;
+    let written = file.metadata().await.map_err(|_| internal_err())?.len();
+    let new_offset = written.min(upload.upload_length as u64);
+
+    let upload = ResumableUpload::advance(
+        &mut transaction,
+        &upload.uuid,
+        offset as i64,
+        new_offset as i64,
+        &config.expires_at(),
+    )
+    .await
+    .map_err(|_| internal_err())?
+    .ok_or_else(|| TusResponse::new(Status::Conflict))?;
+    transaction.commit().await.map_err(|_| internal_err())?;
+    if streamed.is_err() {
+        return Err(TusResponse::new(Status::BadRequest).header("Upload-Offset", new_offset));
+    }
+    if !upload.is_complete() {
+        return Ok(TusResponse::new(Status::NoContent)
+            .header("Upload-Offset", new_offset)
+            .expires(&upload.expires_at.0));
+    }
+
+    let content_type =
+        ContentType::parse_flexible(&upload.content_type).ok_or_else(internal_err)?;
+    let saved = save_staged_upload(
+        connection,
+        user,
+        &path,
+        &content_type,
+        &upload.settings(),
+        &pipeline,
+    )
+    .await
+    .map_err(TusResponse::error);
+    let post = match saved {
+        Ok(post) => post,
+        Err(response) => {
+            let _ = ResumableUpload::destroy(connection, &upload.uuid).await;
+            return Err(response.header("Upload-Offset", new_offset));
+        }
+    };
+    let _ = ResumableUpload::complete(connection, &upload.uuid, &post.uuid).await;
+    Ok(TusResponse::new(Status::NoContent)
+        .header("Upload-Offset", new_offset)
+        .header(
+            "Post-Location",
+            format!("/users/{}/posts/{}", post.user_uuid, post.uuid),
+        ))
+}
+
+#[delete("/uploads/<uuid>")]
+pub async fn terminate(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    tus: TusHeaders<'_>,
+    storage: &State<Storage>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<TusResponse, TusResponse> {
+    tus.check_version()?;
+    let connection = db
+        .acquire()
+        .await
+        .map_err(|_| TusResponse::new(Status::InternalServerError))?;
+    let upload = ResumableUpload::find(connection, uuid, &authenticated_user.user.uuid)
+        .await
+        .map_err(|_| TusResponse::new(Status::NotFound))?;
+    ResumableUpload::destroy(connection, &upload.uuid)
+        .await
+        .map_err(|_| TusResponse::new(Status::InternalServerError))?;
+    let _ = remove_file(storage.resumable_path(&upload.uuid.to_string())).await;
+    Ok(TusResponse::new(Status::NoContent))
+}
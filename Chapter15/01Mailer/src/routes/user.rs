@@ -1,15 +1,21 @@
 use super::HtmlResponse;
 use crate::fairings::csrf::Token as CsrfToken;
 use crate::fairings::db::DBConnection;
-use crate::guards::auth::CurrentUser;
+use crate::guards::auth::{AdminUser, CurrentUser, LOGIN_COOKIE_NAME};
 use crate::models::{
+    job_type::JobType,
+    our_date_time::OurDateTime,
     pagination::Pagination,
-    user::{EditedUser, NewUser, User},
+    quota::{Quota, QuotaConfig, QuotaOverride, MEGABYTE},
+    user::{DeleteAccount, EditedUser, Login, NewUser, User},
 };
+use crate::workers::trash::{schedule_purge, TrashConfig};
+use chrono::offset::Utc;
 use rocket::form::{Contextual, Form};
-use rocket::http::Status;
+use rocket::http::{Cookie, CookieJar, Status};
 use rocket::request::FlashMessage;
 use rocket::response::{Flash, Redirect};
+use rocket::State;
 use rocket_db_pools::{sqlx::Acquire, Connection};
 use rocket_dyn_templates::{context, Template};
 
@@ -18,6 +24,8 @@ pub async fn get_user(
     mut db: Connection<DBConnection>,
     uuid: &str,
     flash: Option<FlashMessage<'_>>,
+    quota_config: &State<QuotaConfig>,
+    csrf_token: CsrfToken,
     current_user: Option<CurrentUser>,
 ) -> HtmlResponse {
     let connection = db
@@ -25,11 +33,24 @@ pub async fn get_user(
         .await
         .map_err(|_| Status::InternalServerError)?;
     let user = User::find(connection, uuid).await.map_err(|e| e.status)?;
+    let can_see_quota = current_user
+        .as_ref()
+        .map_or(false, |cu| cu.is(uuid) || cu.user.is_admin);
+    let quota = if can_see_quota {
+        let quota = Quota::find(connection, quota_config, &user)
+            .await
+            .map_err(|e| e.status)?;
+        Some(quota.to_context())
+    } else {
+        None
+    };
     let flash_message = flash.map(|fm| String::from(fm.message()));
     let context = context! {
         user,
         current_user,
+        quota,
         flash: flash_message,
+        csrf_token,
     };
     Ok(Template::render("users/show", context))
 }
@@ -221,40 +242,190 @@ pub async fn patch_user<'r>(
     put_user(db, uuid, user_context, csrf_token, current_user).await
 }
 
+#[get("/users/delete/<uuid>", format = "text/html")]
+pub async fn confirm_delete_user(
+    uuid: &str,
+    flash: Option<FlashMessage<'_>>,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    current_user: CurrentUser,
+) -> HtmlResponse {
+    if current_user.is_not(uuid) {
+        return Err(Status::Unauthorized);
+    }
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        user: &current_user.user,
+        current_user: &current_user,
+        grace_days: trash_config.account_grace_period / 86400,
+        csrf_token,
+    };
+    Ok(Template::render("users/delete", context))
+}
+
 #[post(
     "/users/delete/<uuid>",
     format = "application/x-www-form-urlencoded",
+    data = "<account>",
     rank = 2
 )]
-pub async fn delete_user_entry_point(
+pub async fn delete_user_entry_point<'r>(
     db: Connection<DBConnection>,
     uuid: &str,
+    account: Form<DeleteAccount<'r>>,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
     current_user: CurrentUser,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    delete_user(db, uuid, current_user).await
+    delete_user(
+        db,
+        uuid,
+        account,
+        trash_config,
+        csrf_token,
+        cookies,
+        current_user,
+    )
+    .await
 }
 
-#[delete("/users/<uuid>", format = "application/x-www-form-urlencoded")]
-pub async fn delete_user(
+#[delete(
+    "/users/<uuid>",
+    format = "application/x-www-form-urlencoded",
+    data = "<account>"
+)]
+pub async fn delete_user<'r>(
     mut db: Connection<DBConnection>,
     uuid: &str,
+    account: Form<DeleteAccount<'r>>,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
     current_user: CurrentUser,
 ) -> Result<Flash<Redirect>, Flash<Redirect>> {
-    let delete_error = || {
+    let delete_error = |message: &str| {
         Flash::error(
-            Redirect::to("/users"),
-            "Something went wrong when deleting user",
+            Redirect::to(format!("/users/delete/{}", uuid)),
+            String::from(message),
         )
     };
+    let generic_error = "Something went wrong when deleting user";
+    csrf_token
+        .verify(&account.authenticity_token)
+        .map_err(|_| delete_error(generic_error))?;
     if current_user.is_not(uuid) {
-        return Err(delete_error());
+        return Err(delete_error(generic_error));
     }
-    let connection = db.acquire().await.map_err(|_| delete_error())?;
-    User::destroy(connection, uuid)
+    current_user
+        .user
+        .check_password(account.password)
+        .map_err(|e| delete_error(&e.message))?;
+    let connection = db
+        .acquire()
         .await
-        .map_err(|_| delete_error())?;
+        .map_err(|_| delete_error(generic_error))?;
+    let purge_at = trash_config.account_purge_at(&OurDateTime(Utc::now()));
+    let mut transaction = connection
+        .begin()
+        .await
+        .map_err(|_| delete_error(generic_error))?;
+    User::destroy(&mut transaction, uuid)
+        .await
+        .map_err(|_| delete_error(generic_error))?;
+    schedule_purge(&mut transaction, JobType::PurgeUser, uuid, &purge_at)
+        .await
+        .map_err(|_| delete_error(generic_error))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|_| delete_error(generic_error))?;
+    cookies.remove_private(Cookie::named(LOGIN_COOKIE_NAME));
     Ok(Flash::success(
         Redirect::to("/users"),
-        "Successfully deleted user",
+        format!(
+            "Your account is disabled and will be permanently deleted after {}. You can restore it until then.",
+            purge_at.0.format("%Y-%m-%d %H:%M UTC")
+        ),
+    ))
+}
+
+#[get("/users/restore", format = "text/html")]
+pub async fn restore_user_form(
+    flash: Option<FlashMessage<'_>>,
+    csrf_token: CsrfToken,
+) -> HtmlResponse {
+    let context = context! {
+        flash: flash.map(|fm| String::from(fm.message())),
+        csrf_token,
+    };
+    Ok(Template::render("users/restore", context))
+}
+
+#[post(
+    "/users/restore",
+    format = "application/x-www-form-urlencoded",
+    data = "<login>"
+)]
+pub async fn restore_user<'r>(
+    mut db: Connection<DBConnection>,
+    login: Form<Login<'r>>,
+    trash_config: &State<TrashConfig>,
+    csrf_token: CsrfToken,
+    cookies: &CookieJar<'_>,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let restore_error = || Flash::error(Redirect::to("/users/restore"), "Cannot restore account");
+    csrf_token
+        .verify(&login.authenticity_token)
+        .map_err(|_| restore_error())?;
+    let connection = db.acquire().await.map_err(|_| restore_error())?;
+    let user = User::restore(connection, &login, trash_config.account_grace_period)
+        .await
+        .map_err(|_| restore_error())?;
+    cookies.add_private(Cookie::new(LOGIN_COOKIE_NAME, user.uuid.to_string()));
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}", user.uuid)),
+        "Your account has been restored",
+    ))
+}
+
+#[post(
+    "/users/<uuid>/quota",
+    format = "application/x-www-form-urlencoded",
+    data = "<quota>"
+)]
+pub async fn update_quota<'r>(
+    mut db: Connection<DBConnection>,
+    uuid: &str,
+    quota: Form<QuotaOverride<'r>>,
+    csrf_token: CsrfToken,
+    _admin: AdminUser,
+) -> Result<Flash<Redirect>, Flash<Redirect>> {
+    let quota_error = || {
+        Flash::error(
+            Redirect::to(format!("/users/{}", uuid)),
+            "Something went wrong when updating quota",
+        )
+    };
+    csrf_token
+        .verify(&quota.authenticity_token)
+        .map_err(|_| quota_error())?;
+    if quota.storage_quota_mb.map_or(false, |mb| mb < 0)
+        || quota.daily_post_limit.map_or(false, |limit| limit < 0)
+    {
+        return Err(quota_error());
+    }
+    let connection = db.acquire().await.map_err(|_| quota_error())?;
+    User::update_quota(
+        connection,
+        uuid,
+        quota.storage_quota_mb.map(|mb| mb.saturating_mul(MEGABYTE)),
+        quota.daily_post_limit,
+    )
+    .await
+    .map_err(|_| quota_error())?;
+    Ok(Flash::success(
+        Redirect::to(format!("/users/{}", uuid)),
+        "Successfully updated quota",
     ))
 }
@@ -0,0 +1,162 @@
+use super::{staged_files, Storage};
+use crate::models::media_blob::MediaBlob;
+use crate::models::media_variant::MediaVariant;
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, PgConnection};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+const UNSHARED_PREFIXES: &[&str] = &["hls/"];
+
+#[derive(Debug, Default)]
+pub struct StoredMedia {
+    pub keys: HashMap<String, String>,
+    pub hashes: Vec<String>,
+    pub uploaded: Vec<String>,
+    pub size: i64,
+}
+
+impl StoredMedia {
+    pub fn key<'a>(&'a self, staged: &'a str) -> &'a str {
+        self.keys.get(staged).map(String::as_str).unwrap_or(staged)
+    }
+
+    pub fn url(&self, storage: &Storage, staged: &str) -> String {
+        storage.url(self.key(staged))
+    }
+
+    pub fn publish(&self, storage: &Storage, variants: Vec<MediaVariant>) -> Vec<MediaVariant> {
+        variants
+            .into_iter()
+            .map(|variant| MediaVariant {
+                path: self.url(storage, &variant.path),
+                ..variant
+            })
+            .collect()
+    }
+}
+
+pub fn is_shared(key: &str) -> bool {
+    key.starts_with("blobs/")
+}
+
+// Deletes the file of a released blob unless the blob has been stored again
+// since. Returns whether the file was deleted.
+pub async fn delete_blob(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    key: &str,
+) -> Result<bool, String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    if let Some(hash) = blob_hash(key) {
+        MediaBlob::lock(&mut transaction, hash)
+            .await
+            .map_err(|e| e.message)?;
+    }
+    if MediaBlob::is_stored(&mut transaction, key)
+        .await
+        .map_err(|e| e.message)?
+    {
+        return Ok(false);
+    }
+    storage.delete(key).await?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+pub async fn delete_blobs(connection: &mut PgConnection, storage: &Storage, keys: &[String]) {
+    for key in keys {
+        if let Err(e) = delete_blob(connection, storage, key).await {
+            log::warn!("Cannot delete {}: {}", key, e);
+        }
+    }
+}
+
+fn blob_hash(key: &str) -> Option<&str> {
+    let name = key.strip_prefix("blobs/")?.rsplit('/').next()?;
+    name.split('.').next()
+}
+
+pub async fn hash_file(path: &Path) -> Result<(String, i64), String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as i64;
+    }
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok((hash, size))
+}
+
+pub async fn store_dir(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    dir: &Path,
+) -> Result<StoredMedia, String> {
+    let mut stored = StoredMedia::default();
+    let result = store_files(connection, storage, dir, &mut stored).await;
+    if result.is_err() {
+        storage.delete_keys(&stored.uploaded).await;
+    }
+    result.map(|_| stored)
+}
+
+async fn store_files(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    dir: &Path,
+    stored: &mut StoredMedia,
+) -> Result<(), String> {
+    let mut by_hash: HashMap<String, String> = HashMap::new();
+    for (staged, path) in staged_files(dir).await? {
+        if UNSHARED_PREFIXES.iter().any(|p| staged.starts_with(p)) {
+            let metadata = tokio::fs::metadata(&path)
+                .await
+                .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+            storage.put_file(&staged, &path).await?;
+            stored.size += metadata.len() as i64;
+            stored.uploaded.push(staged.clone());
+            stored.keys.insert(staged.clone(), staged);
+            continue;
+        }
+        let (hash, size) = hash_file(&path).await?;
+        if let Some(key) = by_hash.get(&hash) {
+            stored.keys.insert(staged, key.clone());
+            continue;
+        }
+        let extension = staged.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("bin");
+        let (key, inserted) = MediaBlob::acquire(
+            connection,
+            &hash,
+            &MediaBlob::key_for(&hash, extension),
+            size,
+        )
+        .await
+        .map_err(|e| e.message)?;
+        if inserted {
+            storage.put_file(&key, &path).await?;
+            stored.uploaded.push(key.clone());
+        }
+        by_hash.insert(hash.clone(), key.clone());
+        stored.size += size;
+        stored.hashes.push(hash);
+        stored.keys.insert(staged, key);
+    }
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use super::{validate_key, MediaStorage, StoredObject};
+use chrono::{offset::Utc, DateTime};
+use rocket::serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LocalConfig {
+    pub root: String,
+    pub public_url: String,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        LocalConfig {
+            root: String::from(rocket::fs::relative!("static")),
+            public_url: String::from("/assets"),
+        }
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+    public_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(config: &LocalConfig) -> Result<Self, String> {
+        std::fs::create_dir_all(&config.root)
+            .map_err(|e| format!("Cannot create storage directory {}: {}", config.root, e))?;
+        Ok(LocalStorage {
+            root: PathBuf::from(&config.root),
+            public_url: String::from(config.public_url.trim_end_matches('/')),
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStorage for LocalStorage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> Result<(), String> {
+        let path = self.path(key)?;
+        create_parent(&path).await?;
+        fs::write(&path, bytes)
+            .await
+            .map_err(|e| format!("Cannot write {}: {}", key, e))
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        fs::read(self.path(key)?)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", key, e))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(format!("Cannot delete {}: {}", key, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), String> {
+        let path = self.path(prefix.trim_end_matches('/'))?;
+        match fs::remove_dir_all(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(format!("Cannot delete {}: {}", prefix, e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, String> {
+        let mut objects = vec![];
+        let mut pending = vec![self.root.clone()];
+        while let Some(current) = pending.pop() {
+            let mut entries = match fs::read_dir(&current).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Cannot list {}: {}", current.display(), e)),
+            };
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| format!("Cannot list {}: {}", current.display(), e))?
+            {
+                let metadata = entry
+                    .metadata()
+                    .await
+                    .map_err(|e| format!("Cannot list {}: {}", entry.path().display(), e))?;
+                if metadata.is_dir() {
+                    pending.push(entry.path());
+                    continue;
+                }
+                let key = entry
+                    .path()
+                    .strip_prefix(&self.root)
+                    .map(|path| {
+                        path.components()
+                            .map(|c| c.as_os_str().to_string_lossy().to_string())
+                            .collect::<Vec<String>>()
+                            .join("/")
+                    })
+                    .unwrap_or_default();
+                if key.starts_with(prefix) {
+                    objects.push(StoredObject {
+                        key,
+                        size: metadata.len(),
+                        modified: metadata
+                            .modified()
+                            .map(DateTime::<Utc>::from)
+                            .unwrap_or_else(|_| Utc::now()),
+                    });
+                }
+            }
+        }
+        objects.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(objects)
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.public_url, key)
+    }
+
+    fn presigned_url(&self, key: &str, _expires_in: u64) -> Result<String, String> {
+        validate_key(key)?;
+        Ok(self.url(key))
+    }
+
+    fn key_from_url(&self, url: &str) -> Option<String> {
+        let key = url.strip_prefix(&self.public_url)?.strip_prefix('/')?;
+        validate_key(key).ok()?;
+        Some(String::from(key))
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        self.path(key).ok()
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<(), String> {
+        let path = self.path(key)?;
+        create_parent(&path).await?;
+        fs::copy(source, &path)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Cannot write {}: {}", key, e))
+    }
+
+    async fn get_file(&self, key: &str, dest: &Path) -> Result<(), String> {
+        fs::copy(self.path(key)?, dest)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Cannot read {}: {}", key, e))
+    }
+}
+
+async fn create_parent(path: &Path) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Cannot create {}: {}", parent.display(), e))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use chrono::{offset::Utc, DateTime};
+use local::{LocalConfig, LocalStorage};
+use rocket::serde::Deserialize;
+use s3::{S3Config, S3Storage};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub mod blobs;
+pub mod local;
+pub mod s3;
+
+pub const UPLOAD_PREFIX: &str = "uploads/";
+pub const PREVIEW_PREFIX: &str = "previews/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+#[rocket::async_trait]
+pub trait MediaStorage: Send + Sync {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), String>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String>;
+
+    async fn delete(&self, key: &str) -> Result<(), String>;
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), String>;
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, String>;
+
+    fn url(&self, key: &str) -> String;
+
+    fn presigned_url(&self, key: &str, expires_in: u64) -> Result<String, String>;
+
+    fn key_from_url(&self, url: &str) -> Option<String>;
+
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<(), String> {
+        let bytes = tokio::fs::read(source)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", source.display(), e))?;
+        self.put(key, bytes, &content_type_for(key)).await
+    }
+
+    async fn get_file(&self, key: &str, dest: &Path) -> Result<(), String> {
+        let bytes = self.get(key).await?;
+        tokio::fs::write(dest, bytes)
+            .await
+            .map_err(|e| format!("Cannot write {}: {}", dest.display(), e))
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    S3,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub staging_dir: String,
+    pub presign_expiry: u64,
+    pub local: LocalConfig,
+    pub s3: S3Config,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Local,
+            staging_dir: std::env::temp_dir()
+                .join("our_application")
+                .to_string_lossy()
+                .to_string(),
+            presign_expiry: 3600,
+            local: LocalConfig::default(),
+            s3: S3Config::default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Storage {
+    backend: Arc<dyn MediaStorage>,
+    pub staging_dir: PathBuf,
+    pub presign_expiry: u64,
+}
+
+impl Storage {
+    pub fn new(config: &StorageConfig) -> Result<Self, String> {
+        std::fs::create_dir_all(&config.staging_dir).map_err(|e| {
+            format!(
+                "Cannot create staging directory {}: {}",
+                config.staging_dir, e
+            )
+        })?;
+        let backend: Arc<dyn MediaStorage> = match config.backend {
+            StorageBackend::Local => Arc::new(LocalStorage::new(&config.local)?),
+            StorageBackend::S3 => Arc::new(S3Storage::new(&config.s3)?),
+        };
+        Ok(Storage::from_backend(backend, config))
+    }
+
+    pub fn from_backend(backend: Arc<dyn MediaStorage>, config: &StorageConfig) -> Self {
+        Storage {
+            backend,
+            staging_dir: PathBuf::from(&config.staging_dir),
+            presign_expiry: config.presign_expiry,
+        }
+    }
+
+    pub fn staging_path(&self, name: &str) -> PathBuf {
+        self.staging_dir.join(name)
+    }
+
+    pub fn resumable_path(&self, uuid: &str) -> PathBuf {
+        self.staging_dir.join("tus").join(uuid)
+    }
+
+    pub async fn delete_keys(&self, keys: &[String]) {
+        for key in keys {
+            let _ = self.delete(key).await;
+        }
+    }
+
+    pub async fn delete_url(&self, url: &str) -> Result<(), String> {
+        let key = match self.key_from_url(url) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        if key.ends_with(".m3u8") {
+            if let Some((dir, _)) = key.rsplit_once('/') {
+                return self.delete_prefix(&format!("{}/", dir)).await;
+            }
+        }
+        self.delete(&key).await
+    }
+}
+
+impl Deref for Storage {
+    type Target = dyn MediaStorage;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref()
+    }
+}
+
+pub async fn staged_files(dir: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut files = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&current)
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", current.display(), e))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| format!("Cannot read {}: {}", current.display(), e))?
+        {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let key = path
+                .strip_prefix(dir)
+                .map_err(|_| String::from("Staged file outside of staging directory"))?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+                .join("/");
+            files.push((key, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty()
+        || key.starts_with('/')
+        || key.contains('\\')
+        || key
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == "..")
+    {
+        return Err(format!("Invalid storage key: {}", key));
+    }
+    Ok(())
+}
+
+pub fn content_type_for(key: &str) -> String {
+    let extension = key.rsplit_once('.').map(|(_, ext)| ext).unwrap_or("");
+    let content_type = match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "flac" => "audio/flac",
+        "m3u8" => "application/vnd.apple.mpegurl",
+        "ts" => "video/mp2t",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    };
+    String::from(content_type)
+}
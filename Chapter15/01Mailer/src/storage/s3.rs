@@ -0,0 +1,410 @@
+use super::{validate_key, MediaStorage, StoredObject};
+use chrono::{offset::Utc, DateTime};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, Url};
+use rocket::serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub path_style: bool,
+    pub public_url: Option<String>,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        S3Config {
+            endpoint: String::from("https://s3.amazonaws.com"),
+            region: String::from("us-east-1"),
+            bucket: String::new(),
+            access_key: String::new(),
+            secret_key: String::new(),
+            path_style: true,
+            public_url: None,
+        }
+    }
+}
+
+pub struct S3Storage {
+    client: Client,
+    scheme: String,
+    host: String,
+    base_path: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+struct SignedRequest {
+    url: String,
+    amz_date: String,
+    authorization: String,
+}
+
+impl S3Storage {
+    pub fn new(config: &S3Config) -> Result<Self, String> {
+        if config.bucket.is_empty() {
+            return Err(String::from("S3 storage requires a bucket"));
+        }
+        // Media is only handed out through presigned redirects from /assets,
+        // which checks post visibility first.
+        if config.public_url.is_some() {
+            return Err(String::from(
+                "S3 storage does not support public_url, media is served through /assets",
+            ));
+        }
+        let endpoint = Url::parse(&config.endpoint)
+            .map_err(|e| format!("Invalid S3 endpoint {}: {}", config.endpoint, e))?;
+        let endpoint_host = endpoint
+            .host_str()
+            .ok_or_else(|| format!("Invalid S3 endpoint {}", config.endpoint))?;
+        let mut host = if config.path_style {
+            String::from(endpoint_host)
+        } else {
+            format!("{}.{}", config.bucket, endpoint_host)
+        };
+        if let Some(port) = endpoint.port() {
+            host.push_str(&format!(":{}", port));
+        }
+        let mut base_path = String::from(endpoint.path().trim_end_matches('/'));
+        if config.path_style {
+            base_path.push('/');
+            base_path.push_str(&uri_encode(&config.bucket, true));
+        }
+        Ok(S3Storage {
+            client: Client::new(),
+            scheme: String::from(endpoint.scheme()),
+            host,
+            base_path,
+            region: config.region.clone(),
+            access_key: config.access_key.clone(),
+            secret_key: config.secret_key.clone(),
+        })
+    }
+
+    pub fn presigned_url_at(
+        &self,
+        method: &str,
+        key: &str,
+        expires_in: u64,
+        time: DateTime<Utc>,
+    ) -> Result<String, String> {
+        validate_key(key)?;
+        let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+        let credential = format!("{}/{}", self.access_key, self.scope(&time));
+        let query = vec![
+            (String::from("X-Amz-Algorithm"), String::from(ALGORITHM)),
+            (String::from("X-Amz-Credential"), credential),
+            (String::from("X-Amz-Date"), amz_date.clone()),
+            (String::from("X-Amz-Expires"), expires_in.to_string()),
+            (String::from("X-Amz-SignedHeaders"), String::from("host")),
+        ];
+        let uri = self.uri(key);
+        let headers = vec![(String::from("host"), self.host.clone())];
+        let signature = self.signature(
+            method,
+            &uri,
+            &canonical_query(&query),
+            &headers,
+            UNSIGNED_PAYLOAD,
+            &time,
+        );
+        Ok(format!(
+            "{}://{}{}?{}&X-Amz-Signature={}",
+            self.scheme,
+            self.host,
+            uri,
+            canonical_query(&query),
+            signature
+        ))
+    }
+
+    fn uri(&self, key: &str) -> String {
+        if key.is_empty() && !self.base_path.is_empty() {
+            return self.base_path.clone();
+        }
+        format!("{}/{}", self.base_path, uri_encode(key, false))
+    }
+
+    fn scope(&self, time: &DateTime<Utc>) -> String {
+        format!("{}/{}/s3/aws4_request", time.format("%Y%m%d"), self.region)
+    }
+
+    fn signature(
+        &self,
+        method: &str,
+        uri: &str,
+        query: &str,
+        headers: &[(String, String)],
+        payload_hash: &str,
+        time: &DateTime<Utc>,
+    ) -> String {
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            uri,
+            query,
+            canonical_headers,
+            signed_headers(headers),
+            payload_hash
+        );
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            ALGORITHM,
+            time.format("%Y%m%dT%H%M%SZ"),
+            self.scope(time),
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let date_key = hmac(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            time.format("%Y%m%d").to_string().as_bytes(),
+        );
+        let region_key = hmac(&date_key, self.region.as_bytes());
+        let service_key = hmac(&region_key, b"s3");
+        let signing_key = hmac(&service_key, b"aws4_request");
+        hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+    }
+
+    fn sign(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(String, String)],
+        payload_hash: &str,
+    ) -> SignedRequest {
+        let time = Utc::now();
+        let amz_date = time.format("%Y%m%dT%H%M%SZ").to_string();
+        let uri = self.uri(key);
+        let query = canonical_query(query);
+        let headers = vec![
+            (String::from("host"), self.host.clone()),
+            (
+                String::from("x-amz-content-sha256"),
+                String::from(payload_hash),
+            ),
+            (String::from("x-amz-date"), amz_date.clone()),
+        ];
+        let signature = self.signature(method, &uri, &query, &headers, payload_hash, &time);
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            ALGORITHM,
+            self.access_key,
+            self.scope(&time),
+            signed_headers(&headers),
+            signature
+        );
+        let mut url = format!("{}://{}{}", self.scheme, self.host, uri);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        SignedRequest {
+            url,
+            amz_date,
+            authorization,
+        }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(String, String)],
+        body: Vec<u8>,
+        content_type: Option<&str>,
+    ) -> Result<reqwest::Response, String> {
+        let payload_hash = hex(&Sha256::digest(&body));
+        let signed = self.sign(method.as_str(), key, query, &payload_hash);
+        let mut request = self
+            .client
+            .request(method, &signed.url)
+            .header("x-amz-date", signed.amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", signed.authorization);
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("Cannot reach S3: {}", e))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(format!("S3 responded with {}: {}", status, text));
+        }
+        Ok(response)
+    }
+
+    async fn list_page(
+        &self,
+        prefix: &str,
+        continuation: Option<String>,
+    ) -> Result<(Vec<StoredObject>, Option<String>), String> {
+        let mut query = vec![
+            (String::from("list-type"), String::from("2")),
+            (String::from("prefix"), String::from(prefix)),
+        ];
+        if let Some(token) = continuation {
+            query.push((String::from("continuation-token"), token));
+        }
+        let body = self
+            .send(Method::GET, "", &query, vec![], None)
+            .await?
+            .text()
+            .await
+            .map_err(|e| format!("Cannot read S3 listing: {}", e))?;
+        let document = roxmltree::Document::parse(&body)
+            .map_err(|e| format!("Cannot parse S3 listing: {}", e))?;
+        let text_of = |node: roxmltree::Node, name: &str| {
+            node.children()
+                .find(|n| n.has_tag_name(name))
+                .and_then(|n| n.text())
+                .map(String::from)
+        };
+        let objects = document
+            .root_element()
+            .children()
+            .filter(|n| n.has_tag_name("Contents"))
+            .filter_map(|n| {
+                Some(StoredObject {
+                    key: text_of(n, "Key")?,
+                    size: text_of(n, "Size")
+                        .and_then(|size| size.parse().ok())
+                        .unwrap_or(0),
+                    modified: text_of(n, "LastModified")
+                        .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                        .map(|date| date.with_timezone(&Utc))
+                        .unwrap_or_else(Utc::now),
+                })
+            })
+            .collect();
+        let root = document.root_element();
+        let next = match text_of(root, "IsTruncated").as_deref() {
+            Some("true") => text_of(root, "NextContinuationToken"),
+            _ => None,
+        };
+        Ok((objects, next))
+    }
+}
+
+#[rocket::async_trait]
+impl MediaStorage for S3Storage {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<(), String> {
+        validate_key(key)?;
+        self.send(Method::PUT, key, &[], bytes, Some(content_type))
+            .await
+            .map(|_| ())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, String> {
+        validate_key(key)?;
+        let response = self.send(Method::GET, key, &[], vec![], None).await?;
+        response
+            .bytes()
+            .await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| format!("Cannot read {}: {}", key, e))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), String> {
+        validate_key(key)?;
+        self.send(Method::DELETE, key, &[], vec![], None)
+            .await
+            .map(|_| ())
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<(), String> {
+        validate_key(prefix.trim_end_matches('/'))?;
+        for object in self.list(prefix).await? {
+            self.delete(&object.key).await?;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<StoredObject>, String> {
+        let mut objects = vec![];
+        let mut continuation = None;
+        loop {
+            let (page, next) = self.list_page(prefix, continuation).await?;
+            objects.extend(page);
+            match next {
+                Some(token) => continuation = Some(token),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("/assets/{}", key)
+    }
+
+    fn presigned_url(&self, key: &str, expires_in: u64) -> Result<String, String> {
+        self.presigned_url_at("GET", key, expires_in, Utc::now())
+    }
+
+    fn key_from_url(&self, url: &str) -> Option<String> {
+        let key = url.strip_prefix("/assets/")?;
+        validate_key(key).ok()?;
+        Some(String::from(key))
+    }
+}
+
+fn canonical_query(query: &[(String, String)]) -> String {
+    let mut pairs: Vec<(String, String)> = query
+        .iter()
+        .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+fn signed_headers(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<&str>>()
+        .join(";")
+}
+
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,191 @@
+use super::video::{ffprobe, process_upload, run, TranscodedVideo, VideoConfig};
+use crate::models::media_variant::MediaVariant;
+use crate::models::worker::Message;
+use crate::workers::{JobError, WorkerContext};
+use rocket::serde::Deserialize;
+use sqlx::PgConnection;
+use std::fs::remove_file;
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioConfig {
+    pub max_duration: f64,
+    pub bitrate: String,
+    pub sample_rate: u32,
+    pub loudness: f64,
+    pub true_peak: f64,
+    pub loudness_range: f64,
+    pub waveform_width: u32,
+    pub waveform_height: u32,
+    pub waveform_color: String,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            max_duration: 3600.0,
+            bitrate: String::from("128k"),
+            sample_rate: 44100,
+            loudness: -16.0,
+            true_peak: -1.5,
+            loudness_range: 11.0,
+            waveform_width: 1200,
+            waveform_height: 200,
+            waveform_color: String::from("#1e6bb8"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AudioProbe {
+    pub duration: f64,
+    pub codec: String,
+    pub channels: u32,
+}
+
+pub fn probe_audio(
+    tools: &VideoConfig,
+    config: &AudioConfig,
+    input: &str,
+) -> Result<AudioProbe, JobError> {
+    let json = ffprobe(&tools.ffprobe, input)?;
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let audio = streams
+        .iter()
+        .find(|s| s["codec_type"] == "audio")
+        .ok_or_else(|| {
+            JobError::Permanent(String::from("Upload does not contain an audio stream"))
+        })?;
+    let duration = json["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse::<f64>().ok())
+        .or_else(|| json["format"]["duration"].as_f64())
+        .ok_or_else(|| JobError::Permanent(String::from("Cannot determine audio duration")))?;
+    let codec = audio["codec_name"].as_str().unwrap_or("").to_string();
+    if codec.is_empty() {
+        return Err(JobError::Permanent(String::from("Unsupported audio codec")));
+    }
+    if duration > config.max_duration {
+        return Err(JobError::Permanent(format!(
+            "Audio is {:.0} seconds long, the limit is {:.0} seconds",
+            duration, config.max_duration
+        )));
+    }
+    Ok(AudioProbe {
+        duration,
+        codec,
+        channels: audio["channels"].as_u64().unwrap_or(0) as u32,
+    })
+}
+
+pub fn loudness_filter(config: &AudioConfig) -> String {
+    format!(
+        "loudnorm=I={}:TP={}:LRA={}",
+        config.loudness, config.true_peak, config.loudness_range
+    )
+}
+
+pub fn transcode_audio(
+    tools: &VideoConfig,
+    config: &AudioConfig,
+    input: &str,
+    output_dir: &Path,
+    base_name: &str,
+) -> Result<TranscodedVideo, JobError> {
+    let probe = probe_audio(tools, config, input)?;
+    let filename = format!("{}.m4a", base_name);
+    let dest = output_dir.join(&filename);
+    let waveform_filename = format!("{}_waveform.png", base_name);
+    let waveform_dest = output_dir.join(&waveform_filename);
+    let result = encode(tools, config, input, &dest, &waveform_dest);
+    if let Err(e) = result {
+        let _ = remove_file(&dest);
+        let _ = remove_file(&waveform_dest);
+        return Err(JobError::from(e));
+    }
+    Ok(TranscodedVideo {
+        variants: vec![MediaVariant {
+            path: filename,
+            mime: String::from("audio/mp4"),
+            label: String::from("aac"),
+            width: None,
+            height: None,
+        }],
+        poster: Some(waveform_filename),
+        duration: probe.duration,
+    })
+}
+
+fn encode(
+    tools: &VideoConfig,
+    config: &AudioConfig,
+    input: &str,
+    dest: &Path,
+    waveform_dest: &Path,
+) -> Result<(), String> {
+    let filter = loudness_filter(config);
+    let sample_rate = config.sample_rate.to_string();
+    let dest_str = dest.to_string_lossy();
+    run(
+        &tools.ffmpeg,
+        &[
+            "-nostdin",
+            "-y",
+            "-i",
+            input,
+            "-map_metadata",
+            "-1",
+            "-map",
+            "0:a:0",
+            "-vn",
+            "-af",
+            &filter,
+            "-ar",
+            &sample_rate,
+            "-c:a",
+            "aac",
+            "-b:a",
+            &config.bitrate,
+            "-movflags",
+            "+faststart",
+            &dest_str,
+        ],
+    )?;
+    let waveform = format!(
+        "showwavespic=s={}x{}:colors={}",
+        config.waveform_width, config.waveform_height, config.waveform_color
+    );
+    let waveform_str = waveform_dest.to_string_lossy();
+    run(
+        &tools.ffmpeg,
+        &[
+            "-nostdin",
+            "-y",
+            "-i",
+            &dest_str,
+            "-filter_complex",
+            &waveform,
+            "-frames:v",
+            "1",
+            &waveform_str,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn process_audio(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    wm: &Message,
+) -> Result<(), JobError> {
+    process_upload(connection, context, wm, |source, output_dir, base_name| {
+        transcode_audio(
+            &context.video,
+            &context.audio,
+            source,
+            output_dir,
+            base_name,
+        )
+    })
+}
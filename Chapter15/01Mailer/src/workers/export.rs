@@ -0,0 +1,227 @@
+use crate::events::notify;
+use crate::models::export::Export;
+use crate::models::notification::{Notification, NotificationEvent};
+use crate::models::our_date_time::OurDateTime;
+use crate::models::post::Post;
+use crate::models::user::User;
+use crate::models::worker::ExportMessage;
+use crate::storage::Storage;
+use crate::workers::WorkerContext;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::Path;
+use tokio::runtime::Handle;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+pub const EXPORT_PREFIX: &str = "exports/";
+
+const README: &str = r#"This archive contains a copy of your our_application data.
+
+profile.json        your account details
+posts.json          every post you have not deleted, including drafts and scheduled posts;
+                    the "files" list of each post names its media inside this archive
+notifications.json  the notifications you have received
+media/              the original uploads and processed versions of your media
+"#;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ExportConfig {
+    pub ttl: u64,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig { ttl: 7 * 86400 }
+    }
+}
+
+impl ExportConfig {
+    pub fn expires_at(&self) -> OurDateTime {
+        OurDateTime(Utc::now() + Duration::seconds(self.ttl as i64))
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExportedPost<'a> {
+    #[serde(flatten)]
+    pub post: &'a Post,
+    pub files: Vec<String>,
+}
+
+pub fn export_key(export: &Export) -> String {
+    format!("{}{}/{}.zip", EXPORT_PREFIX, export.user_uuid, export.uuid)
+}
+
+pub fn media_keys(storage: &Storage, post: &Post) -> Vec<String> {
+    post.media_paths()
+        .iter()
+        .filter_map(|path| storage.key_from_url(path))
+        .filter(|key| !key.ends_with(".m3u8"))
+        .collect()
+}
+
+pub fn archive_name(key: &str) -> String {
+    format!("media/{}", key)
+}
+
+pub fn write_documents<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    user: &User,
+    posts: &[ExportedPost],
+    notifications: &[Notification],
+) -> Result<(), String> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("README.txt", options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(README.as_bytes())
+        .map_err(|e| e.to_string())?;
+    write_json(zip, "profile.json", user)?;
+    write_json(zip, "posts.json", &posts)?;
+    write_json(zip, "notifications.json", &notifications)
+}
+
+fn write_json<W: Write + Seek, T: Serialize>(
+    zip: &mut ZipWriter<W>,
+    name: &str,
+    value: &T,
+) -> Result<(), String> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(name, options).map_err(|e| e.to_string())?;
+    serde_json::to_writer_pretty(zip, value).map_err(|e| format!("Cannot write {}: {}", name, e))
+}
+
+pub fn export_data(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &ExportMessage,
+) -> Result<(), String> {
+    let handle = Handle::current();
+    let pending = handle
+        .block_on(Export::find_pending(connection, &message.uuid))
+        .map_err(|e| e.message)?;
+    let export = match pending {
+        Some(export) => export,
+        None => return Ok(()),
+    };
+    let user = match handle.block_on(User::find(connection, &export.user_uuid.to_string())) {
+        Ok(user) => user,
+        Err(_) => {
+            let _ = handle.block_on(Export::fail(
+                connection,
+                &message.uuid,
+                "Account no longer exists",
+            ));
+            return Ok(());
+        }
+    };
+    let posts = handle
+        .block_on(Post::find_all_for_export(connection, &user.uuid))
+        .map_err(|e| e.message)?;
+    let notifications = handle
+        .block_on(Notification::find_all_for_export(connection, &user.uuid))
+        .map_err(|e| e.message)?;
+
+    let storage = &context.storage;
+    let staged = storage.staging_path(&format!("{}.zip", export.uuid));
+    let written = write_archive(&handle, storage, &staged, &user, &posts, &notifications);
+    let key = export_key(&export);
+    let stored = written.and_then(|size| {
+        handle.block_on(storage.put_file(&key, &staged))?;
+        Ok(size)
+    });
+    let _ = std::fs::remove_file(&staged);
+    let size = stored?;
+
+    let export = handle
+        .block_on(Export::complete(
+            connection,
+            &export.uuid,
+            &key,
+            size as i64,
+            &context.export.expires_at(),
+        ))
+        .map_err(|e| e.message)?;
+    log::info!("Exported data of user {} ({} bytes)", user.uuid, size);
+    handle.block_on(notify(
+        connection,
+        &context.hub,
+        NotificationEvent::ExportReady { export: &export },
+    ));
+    Ok(())
+}
+
+fn write_archive(
+    handle: &Handle,
+    storage: &Storage,
+    path: &Path,
+    user: &User,
+    posts: &[Post],
+    notifications: &[Notification],
+) -> Result<u64, String> {
+    let file =
+        File::create(path).map_err(|e| format!("Cannot create {}: {}", path.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+    let mut written: HashSet<String> = HashSet::new();
+    let mut exported = Vec::with_capacity(posts.len());
+    for post in posts.iter() {
+        let mut files = vec![];
+        for key in media_keys(storage, post) {
+            let name = archive_name(&key);
+            if written.insert(key.clone()) {
+                zip.start_file(name.as_str(), options)
+                    .map_err(|e| e.to_string())?;
+                match storage.local_path(&key) {
+                    Some(local) => {
+                        let mut source = File::open(&local)
+                            .map_err(|e| format!("Cannot read {}: {}", key, e))?;
+                        std::io::copy(&mut source, &mut zip)
+                            .map_err(|e| format!("Cannot archive {}: {}", key, e))?;
+                    }
+                    None => {
+                        let bytes = handle.block_on(storage.get(&key))?;
+                        zip.write_all(&bytes)
+                            .map_err(|e| format!("Cannot archive {}: {}", key, e))?;
+                    }
+                }
+            }
+            files.push(name);
+        }
+        exported.push(ExportedPost { post, files });
+    }
+    write_documents(&mut zip, user, &exported, notifications)?;
+    let file = zip.finish().map_err(|e| e.to_string())?;
+    let metadata = file.metadata().map_err(|e| e.to_string())?;
+    Ok(metadata.len())
+}
+
+pub async fn export_failed(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &ExportMessage,
+    error: &str,
+) {
+    let _ = tokio::fs::remove_file(
+        context
+            .storage
+            .staging_path(&format!("{}.zip", message.uuid)),
+    )
+    .await;
+    if let Ok(Some(export)) = Export::fail(connection, &message.uuid, error).await {
+        notify(
+            connection,
+            &context.hub,
+            NotificationEvent::ExportFailed { export: &export },
+        )
+        .await;
+    }
+}
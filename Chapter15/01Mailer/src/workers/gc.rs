@@ -0,0 +1,316 @@
+use crate::models::export::Export;
+use crate::models::job::Job;
+use crate::models::link_preview::LinkPreview;
+use crate::models::media_blob::MediaBlob;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::post::Post;
+use crate::models::resumable_upload::ResumableUpload;
+use crate::storage::blobs::{delete_blob, is_shared};
+use crate::storage::{Storage, PREVIEW_PREFIX, UPLOAD_PREFIX};
+use chrono::{offset::Utc, DateTime, Duration as ChronoDuration};
+use rocket::serde::Deserialize;
+use sqlx::{Acquire, PgConnection, PgPool};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+const GC_LOCK: i64 = 0x6d65_6469_615f_6763;
+const MANAGED_PREFIXES: &[&str] = &["blobs/", "hls/", PREVIEW_PREFIX, UPLOAD_PREFIX];
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GcConfig {
+    pub enabled: bool,
+    pub interval: u64,
+    pub grace_period: u64,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            enabled: true,
+            interval: 3600,
+            grace_period: 86400,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub objects: Vec<String>,
+    pub blobs: Vec<String>,
+    pub staged: Vec<String>,
+    pub bytes: u64,
+}
+
+impl GcReport {
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty() && self.blobs.is_empty() && self.staged.is_empty()
+    }
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} orphaned files, {} unreferenced blobs and {} stale uploads ({} bytes)",
+            if self.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            self.objects.len(),
+            self.blobs.len(),
+            self.staged.len(),
+            self.bytes
+        )
+    }
+}
+
+pub fn is_managed(key: &str) -> bool {
+    if MANAGED_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+    {
+        return true;
+    }
+    !key.contains('/') && key.get(..36).map_or(false, |s| Uuid::parse_str(s).is_ok())
+}
+
+pub fn spawn_media_gc(pool: PgPool, storage: Storage, config: GcConfig) {
+    if !config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.interval.max(60)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match run_exclusive(&pool, &storage, config.grace_period, false).await {
+                Ok(Some(report)) if !report.is_empty() => log::info!("{}", report),
+                Ok(_) => {}
+                Err(e) => log::error!("Media garbage collection failed: {}", e),
+            }
+        }
+    });
+}
+
+pub async fn run_exclusive(
+    pool: &PgPool,
+    storage: &Storage,
+    grace_period: u64,
+    dry_run: bool,
+) -> Result<Option<GcReport>, String> {
+    let mut connection = pool.acquire().await.map_err(|e| e.to_string())?;
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(GC_LOCK)
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !locked {
+        return Ok(None);
+    }
+    let report = collect_garbage(&mut connection, storage, grace_period, dry_run).await;
+    let _ = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(GC_LOCK)
+        .execute(&mut connection)
+        .await;
+    report.map(Some)
+}
+
+pub async fn collect_garbage(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    grace_period: u64,
+    dry_run: bool,
+) -> Result<GcReport, String> {
+    let cutoff = Utc::now() - ChronoDuration::seconds(grace_period as i64);
+    let mut report = GcReport {
+        dry_run,
+        ..GcReport::default()
+    };
+
+    // A dry run makes the same changes to the blob table and rolls them
+    // back, so it reports exactly what a real run would remove.
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    MediaBlob::recount(&mut transaction)
+        .await
+        .map_err(|e| e.message)?;
+    let orphaned_blobs = MediaBlob::destroy_orphaned(&mut transaction, &OurDateTime(cutoff))
+        .await
+        .map_err(|e| e.message)?;
+    if dry_run {
+        transaction.rollback().await
+    } else {
+        transaction.commit().await
+    }
+    .map_err(|e| e.to_string())?;
+    report.blobs = orphaned_blobs.into_iter().map(|blob| blob.key).collect();
+
+    let mut urls = Post::media_urls(connection)
+        .await
+        .map_err(|e| e.message)?;
+    urls.extend(
+        LinkPreview::image_urls(connection)
+            .await
+            .map_err(|e| e.message)?,
+    );
+    let mut referenced: HashSet<String> = urls
+        .iter()
+        .filter_map(|url| storage.key_from_url(url))
+        .collect();
+    referenced.extend(MediaBlob::keys(connection).await.map_err(|e| e.message)?);
+    referenced.extend(
+        Job::pending_upload_keys(connection)
+            .await
+            .map_err(|e| e.message)?,
+    );
+    for key in report.blobs.iter() {
+        referenced.remove(key);
+    }
+    let playlist_dirs: Vec<String> = referenced
+        .iter()
+        .filter(|key| key.ends_with(".m3u8"))
+        .filter_map(|key| key.rsplit_once('/').map(|(dir, _)| format!("{}/", dir)))
+        .collect();
+
+    for object in storage.list("").await? {
+        if !is_managed(&object.key)
+            || object.modified > cutoff
+            || referenced.contains(&object.key)
+            || playlist_dirs.iter().any(|dir| object.key.starts_with(dir))
+        {
+            continue;
+        }
+        if !dry_run {
+            // Blobs can be stored again after the listing above, so their
+            // files are only deleted under the blob lock.
+            let deleted = if is_shared(&object.key) {
+                delete_blob(connection, storage, &object.key).await
+            } else {
+                storage.delete(&object.key).await.map(|_| true)
+            };
+            match deleted {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    log::warn!("Cannot delete {}: {}", object.key, e);
+                    continue;
+                }
+            }
+        }
+        report.bytes += object.size;
+        report.objects.push(object.key);
+    }
+
+    clean_staging(&storage.staging_dir, &cutoff, &mut report).await?;
+    clean_expired_uploads(connection, storage, &mut report).await?;
+    clean_expired_exports(connection, storage, &mut report).await?;
+    Ok(report)
+}
+
+async fn clean_expired_exports(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    report: &mut GcReport,
+) -> Result<(), String> {
+    let expired = if report.dry_run {
+        Export::find_expired(connection).await
+    } else {
+        Export::destroy_expired(connection).await
+    }
+    .map_err(|e| e.message)?;
+    for export in expired {
+        let key = match export.storage_key {
+            Some(key) => key,
+            None => continue,
+        };
+        if !report.dry_run {
+            if let Err(e) = storage.delete(&key).await {
+                log::warn!("Cannot delete {}: {}", key, e);
+                continue;
+            }
+        }
+        report.bytes += export.size as u64;
+        report.objects.push(key);
+    }
+    Ok(())
+}
+
+async fn clean_expired_uploads(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    report: &mut GcReport,
+) -> Result<(), String> {
+    let expired = if report.dry_run {
+        ResumableUpload::find_expired(connection).await
+    } else {
+        ResumableUpload::destroy_expired(connection).await
+    }
+    .map_err(|e| e.message)?;
+    for uuid in expired {
+        let path = storage.resumable_path(&uuid.to_string());
+        if let Ok(metadata) = tokio::fs::metadata(&path).await {
+            report.bytes += metadata.len();
+        }
+        if !report.dry_run {
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+        report.staged.push(path.to_string_lossy().to_string());
+    }
+    Ok(())
+}
+
+async fn clean_staging(
+    staging_dir: &Path,
+    cutoff: &DateTime<Utc>,
+    report: &mut GcReport,
+) -> Result<(), String> {
+    if staging_dir == std::env::temp_dir() {
+        log::warn!("Not sweeping the shared temporary directory, set storage.staging_dir");
+        return Ok(());
+    }
+    let mut entries = match tokio::fs::read_dir(staging_dir).await {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", staging_dir.display(), e))?
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.get(..36).map_or(false, |s| Uuid::parse_str(s).is_ok()) {
+            continue;
+        }
+        let metadata = match entry.metadata().await {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let modified = metadata.modified().map(DateTime::<Utc>::from);
+        if !matches!(modified, Ok(modified) if modified < *cutoff) {
+            continue;
+        }
+        if !report.dry_run {
+            let removed = if metadata.is_dir() {
+                tokio::fs::remove_dir_all(entry.path()).await
+            } else {
+                tokio::fs::remove_file(entry.path()).await
+            };
+            if let Err(e) = removed {
+                log::warn!("Cannot delete {}: {}", entry.path().display(), e);
+                continue;
+            }
+        }
+        if metadata.is_file() {
+            report.bytes += metadata.len();
+        }
+        report
+            .staged
+            .push(entry.path().to_string_lossy().to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::media::link_preview::{fetch_image, fetch_preview};
+use crate::models::link_preview::LinkPreview;
+use crate::models::worker::LinkPreviewMessage;
+use crate::storage::{content_type_for, PREVIEW_PREFIX};
+use crate::workers::WorkerContext;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use tokio::runtime::Handle;
+
+pub fn fetch_link_preview(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &LinkPreviewMessage,
+) -> Result<(), String> {
+    let handle = Handle::current();
+    let config = &context.link_preview;
+    if !config.enabled {
+        return handle
+            .block_on(LinkPreview::fail(
+                connection,
+                &message.url,
+                "Link previews are disabled",
+                config.failure_ttl,
+            ))
+            .map_err(|e| e.message);
+    }
+    match handle.block_on(fetch_preview(config, &message.url)) {
+        Ok(mut metadata) => {
+            // Pages are shown with a copy of their image, so viewers never
+            // load anything from the linked site.
+            metadata.image_url = match metadata.image_url.take() {
+                Some(image_url) => match handle.block_on(store_image(context, &image_url)) {
+                    Ok(url) => Some(url),
+                    Err(e) => {
+                        log::info!("No preview image for {}: {}", message.url, e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            handle
+                .block_on(LinkPreview::complete(
+                    connection,
+                    &message.url,
+                    &metadata,
+                    config.ttl,
+                ))
+                .map_err(|e| e.message)?;
+            Ok(())
+        }
+        Err(e) if e.permanent => {
+            log::info!("No preview for {}: {}", message.url, e);
+            handle
+                .block_on(LinkPreview::fail(
+                    connection,
+                    &message.url,
+                    &e.message,
+                    config.failure_ttl,
+                ))
+                .map_err(|e| e.message)
+        }
+        Err(e) => Err(e.message),
+    }
+}
+
+async fn store_image(context: &WorkerContext, image_url: &str) -> Result<String, String> {
+    let storage = &context.storage;
+    let (bytes, extension) = fetch_image(&context.link_preview, image_url)
+        .await
+        .map_err(|e| e.message)?;
+    let hash: String = Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let key = format!("{}{}.{}", PREVIEW_PREFIX, hash, extension);
+    storage.put(&key, bytes, &content_type_for(&key)).await?;
+    Ok(storage.url(&key))
+}
+
+pub async fn link_preview_failed(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &LinkPreviewMessage,
+    error: &str,
+) {
+    let _ = LinkPreview::fail(
+        connection,
+        &message.url,
+        error,
+        context.link_preview.failure_ttl,
+    )
+    .await;
+}
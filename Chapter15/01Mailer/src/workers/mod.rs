@@ -1 +1,302 @@
+use crate::events::EventHub;
+use crate::media::link_preview::LinkPreviewConfig;
+use crate::models::job::{Job, STALE_ERROR};
+use crate::models::job_status::JobStatus;
+use crate::models::job_type::JobType;
+use crate::storage::Storage;
+use audio::AudioConfig;
+use export::ExportConfig;
+use rocket::serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::thread::sleep;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+use trash::TrashConfig;
+use video::VideoConfig;
+
+pub mod audio;
+pub mod export;
+pub mod gc;
+pub mod link_preview;
+pub mod publish;
+pub mod trash;
 pub mod video;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct WorkerConfig {
+    pub concurrency: usize,
+    pub poll_interval: u64,
+    pub max_attempts: i32,
+    pub backoff: u64,
+    pub lease: u64,
+}
+
+#[derive(Clone)]
+pub struct WorkerContext {
+    pub hub: EventHub,
+    pub video: VideoConfig,
+    pub audio: AudioConfig,
+    pub link_preview: LinkPreviewConfig,
+    pub trash: TrashConfig,
+    pub export: ExportConfig,
+    pub storage: Storage,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    Retry(String),
+    Permanent(String),
+}
+
+impl JobError {
+    pub fn message(&self) -> &str {
+        match self {
+            JobError::Retry(message) | JobError::Permanent(message) => message,
+        }
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, JobError::Permanent(_))
+    }
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl From<String> for JobError {
+    fn from(message: String) -> Self {
+        JobError::Retry(message)
+    }
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            concurrency: 2,
+            poll_interval: 1,
+            max_attempts: 5,
+            backoff: 30,
+            lease: 60,
+        }
+    }
+}
+
+impl WorkerConfig {
+    // Every worker holds a connection for the whole job and its heartbeat
+    // needs a second one. The stale job recovery and media GC loops take one
+    // each on top of that.
+    pub fn pool_size(&self) -> u32 {
+        (self.concurrency.max(1) * 2 + 2) as u32
+    }
+}
+
+pub async fn spawn_workers(pool: PgPool, context: WorkerContext, config: WorkerConfig) {
+    let recover_pool = pool.clone();
+    let recover_context = context.clone();
+    let recover_config = config.clone();
+    tokio::task::spawn_blocking(move || {
+        let handle = Handle::current();
+        let lease = Duration::from_secs(recover_config.lease.max(1));
+        loop {
+            handle.block_on(recover_stale(
+                &recover_pool,
+                &recover_context,
+                &recover_config,
+            ));
+            sleep(lease);
+        }
+    });
+
+    for index in 0..config.concurrency.max(1) {
+        let pool = pool.clone();
+        let context = context.clone();
+        let config = config.clone();
+        tokio::task::spawn_blocking(move || run_worker(index, pool, context, config));
+    }
+}
+
+async fn recover_stale(pool: &PgPool, context: &WorkerContext, config: &WorkerConfig) {
+    let mut connection = match pool.acquire().await {
+        Ok(connection) => connection,
+        Err(_) => return,
+    };
+    let recovered = Job::recover_stale(&mut connection, config.lease, config.max_attempts)
+        .await
+        .map_err(|e| e.message);
+    let jobs = match recovered {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log::error!("Cannot recover stale jobs: {}", e);
+            return;
+        }
+    };
+    if !jobs.is_empty() {
+        log::warn!("Recovered {} stale jobs", jobs.len());
+    }
+    for job in jobs.iter().filter(|job| job.status == JobStatus::Dead) {
+        on_dead(&mut connection, context, job, STALE_ERROR).await;
+    }
+}
+
+fn run_worker(index: usize, pool: PgPool, context: WorkerContext, config: WorkerConfig) {
+    let handle = Handle::current();
+    let worker_id = format!("{}-{}", std::process::id(), index);
+    let poll_interval = Duration::from_secs(config.poll_interval.max(1));
+    loop {
+        let claimed = handle.block_on(async {
+            let mut connection = pool.acquire().await.map_err(|e| e.to_string())?;
+            Job::claim(&mut connection, &worker_id)
+                .await
+                .map_err(|e| e.message)
+        });
+        let job = match claimed {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                sleep(poll_interval);
+                continue;
+            }
+            Err(e) => {
+                log::error!("Cannot claim job: {}", e);
+                sleep(poll_interval);
+                continue;
+            }
+        };
+
+        let heartbeat = start_heartbeat(&handle, pool.clone(), &job, &worker_id, config.lease);
+        let result = catch_unwind(AssertUnwindSafe(|| perform(&handle, &pool, &context, &job)))
+            .unwrap_or_else(|_| Err(JobError::from(String::from("Job panicked"))));
+        heartbeat.abort();
+
+        handle.block_on(async {
+            let mut connection = match pool.acquire().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::error!("Cannot finish job {}: {}", job.uuid, e);
+                    return;
+                }
+            };
+            match result {
+                Ok(()) => {
+                    if let Ok(false) = job.complete(&mut connection).await {
+                        log::warn!("Job {} finished after losing its lease", job.uuid);
+                    }
+                }
+                Err(error) => {
+                    log::warn!("Job {} failed: {}", job.uuid, error);
+                    let max_attempts = if error.is_permanent() {
+                        job.attempts
+                    } else {
+                        config.max_attempts
+                    };
+                    let failed = job
+                        .fail(
+                            &mut connection,
+                            error.message(),
+                            max_attempts,
+                            config.backoff,
+                        )
+                        .await;
+                    match failed {
+                        Ok(Some(JobStatus::Dead)) => {
+                            on_dead(&mut connection, &context, &job, error.message()).await;
+                        }
+                        Ok(None) => log::warn!("Job {} failed after losing its lease", job.uuid),
+                        _ => {}
+                    }
+                }
+            }
+        });
+    }
+}
+
+fn start_heartbeat(
+    handle: &Handle,
+    pool: PgPool,
+    job: &Job,
+    worker_id: &str,
+    lease: u64,
+) -> JoinHandle<()> {
+    let uuid = job.uuid;
+    let worker_id = String::from(worker_id);
+    handle.spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs((lease / 3).max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Ok(mut connection) = pool.acquire().await {
+                let _ = Job::heartbeat(&mut connection, &uuid, &worker_id).await;
+            }
+        }
+    })
+}
+
+fn perform(
+    handle: &Handle,
+    pool: &PgPool,
+    context: &WorkerContext,
+    job: &Job,
+) -> Result<(), JobError> {
+    let mut connection = handle.block_on(pool.acquire()).map_err(|e| e.to_string())?;
+    match job.job_type {
+        JobType::ProcessVideo => {
+            video::process_video(&mut connection, context, &job.parse_payload()?)
+        }
+        JobType::ProcessAudio => {
+            audio::process_audio(&mut connection, context, &job.parse_payload()?)
+        }
+        JobType::FetchLinkPreview => Ok(link_preview::fetch_link_preview(
+            &mut connection,
+            context,
+            &job.parse_payload()?,
+        )?),
+        JobType::PublishPost => Ok(publish::publish_post(
+            &mut connection,
+            context,
+            &job.parse_payload()?,
+        )?),
+        JobType::PurgePost => Ok(trash::purge_trashed_post(
+            &mut connection,
+            context,
+            &job.parse_payload()?,
+        )?),
+        JobType::PurgeUser => Ok(trash::purge_deleted_user(
+            &mut connection,
+            context,
+            &job.parse_payload()?,
+        )?),
+        JobType::ExportData => Ok(export::export_data(
+            &mut connection,
+            context,
+            &job.parse_payload()?,
+        )?),
+    }
+}
+
+async fn on_dead(connection: &mut PgConnection, context: &WorkerContext, job: &Job, error: &str) {
+    log::error!("Job {} exhausted its attempts", job.uuid);
+    match job.job_type {
+        JobType::ProcessVideo | JobType::ProcessAudio => {
+            if let Ok(wm) = job.parse_payload() {
+                video::processing_failed(connection, context, &wm, error).await;
+            }
+        }
+        JobType::FetchLinkPreview => {
+            if let Ok(message) = job.parse_payload() {
+                link_preview::link_preview_failed(connection, context, &message, error).await;
+            }
+        }
+        JobType::ExportData => {
+            if let Ok(message) = job.parse_payload() {
+                export::export_failed(connection, context, &message, error).await;
+            }
+        }
+        JobType::PublishPost | JobType::PurgePost | JobType::PurgeUser => {}
+    }
+}
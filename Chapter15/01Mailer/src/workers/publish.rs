@@ -0,0 +1,26 @@
+use crate::events::{notify_mentions, publish_new_post};
+use crate::models::post::Post;
+use crate::models::worker::PublishMessage;
+use crate::workers::WorkerContext;
+use sqlx::PgConnection;
+use tokio::runtime::Handle;
+
+pub fn publish_post(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &PublishMessage,
+) -> Result<(), String> {
+    let handle = Handle::current();
+    let published = handle
+        .block_on(Post::publish_due(connection, &message.uuid))
+        .map_err(|e| e.message)?;
+    match published {
+        Some(post) => {
+            log::info!("Published scheduled post {}", post.uuid);
+            publish_new_post(&context.hub, &post);
+            handle.block_on(notify_mentions(connection, &context.hub, &post));
+        }
+        None => log::info!("Post {} is no longer due for publishing", message.uuid),
+    }
+    Ok(())
+}
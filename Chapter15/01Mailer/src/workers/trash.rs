@@ -0,0 +1,208 @@
+use crate::models::export::Export;
+use crate::models::job::Job;
+use crate::models::job_type::JobType;
+use crate::models::media_blob::MediaBlob;
+use crate::models::notification::Notification;
+use crate::models::our_date_time::OurDateTime;
+use crate::models::post::Post;
+use crate::models::resumable_upload::ResumableUpload;
+use crate::models::user::User;
+use crate::models::worker::PurgeMessage;
+use crate::send_mail;
+use crate::storage::blobs::{delete_blobs, is_shared};
+use crate::storage::Storage;
+use crate::workers::WorkerContext;
+use chrono::{offset::Utc, Duration};
+use rocket::serde::Deserialize;
+use sqlx::{Acquire, PgConnection};
+use tokio::runtime::Handle;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TrashConfig {
+    pub retention: u64,
+    pub account_grace_period: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            retention: 30 * 86400,
+            account_grace_period: 14 * 86400,
+        }
+    }
+}
+
+impl TrashConfig {
+    pub fn purge_at(&self, deleted_at: &OurDateTime) -> OurDateTime {
+        OurDateTime(deleted_at.0 + Duration::seconds(self.retention as i64))
+    }
+
+    pub fn account_purge_at(&self, deleted_at: &OurDateTime) -> OurDateTime {
+        OurDateTime(deleted_at.0 + Duration::seconds(self.account_grace_period as i64))
+    }
+}
+
+pub async fn schedule_purge(
+    connection: &mut PgConnection,
+    job_type: JobType,
+    uuid: &str,
+    purge_at: &OurDateTime,
+) -> Result<(), String> {
+    Job::enqueue_at(
+        connection,
+        job_type,
+        &PurgeMessage {
+            uuid: String::from(uuid),
+        },
+        purge_at,
+    )
+    .await
+    .map_err(|e| e.message)?;
+    Ok(())
+}
+
+pub async fn purge_post(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    post: &Post,
+) -> Result<(), String> {
+    let uuid = post.uuid.to_string();
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let mut released = MediaBlob::release(&mut transaction, &post.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    released.extend(
+        Job::cancel_for_post(&mut transaction, &uuid)
+            .await
+            .map_err(|e| e.message)?,
+    );
+    Post::purge(&mut transaction, &uuid)
+        .await
+        .map_err(|e| e.message)?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+
+    delete_blobs(connection, storage, &released).await;
+    for path in post.media_paths() {
+        if storage
+            .key_from_url(&path)
+            .map_or(false, |key| is_shared(&key))
+        {
+            continue;
+        }
+        if let Err(e) = storage.delete_url(&path).await {
+            log::warn!("Cannot delete {}: {}", path, e);
+        }
+    }
+    Ok(())
+}
+
+pub fn purge_trashed_post(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &PurgeMessage,
+) -> Result<(), String> {
+    let handle = Handle::current();
+    let deleted = handle
+        .block_on(Post::find_deleted(connection, &message.uuid))
+        .map_err(|e| e.message)?;
+    let post = match deleted {
+        Some(post) => post,
+        None => return Ok(()),
+    };
+    if let Some(deleted_at) = post.deleted_at.as_ref() {
+        let purge_at = context.trash.purge_at(deleted_at);
+        if purge_at.0 > Utc::now() {
+            return handle
+                .block_on(Job::enqueue_at(
+                    connection,
+                    JobType::PurgePost,
+                    message,
+                    &purge_at,
+                ))
+                .map(|_| ())
+                .map_err(|e| e.message);
+        }
+    }
+    handle.block_on(purge_post(connection, &context.storage, &post))?;
+    log::info!("Purged post {}", post.uuid);
+    Ok(())
+}
+
+pub fn purge_deleted_user(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    message: &PurgeMessage,
+) -> Result<(), String> {
+    let handle = Handle::current();
+    let deleted = handle
+        .block_on(User::find_deleted(connection, &message.uuid))
+        .map_err(|e| e.message)?;
+    let user = match deleted {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    if let Some(deleted_at) = user.deleted_at.as_ref() {
+        let purge_at = context.trash.account_purge_at(deleted_at);
+        if purge_at.0 > Utc::now() {
+            return handle
+                .block_on(Job::enqueue_at(
+                    connection,
+                    JobType::PurgeUser,
+                    message,
+                    &purge_at,
+                ))
+                .map(|_| ())
+                .map_err(|e| e.message);
+        }
+    }
+    let posts = handle
+        .block_on(Post::find_all_for_purge(connection, &user.uuid))
+        .map_err(|e| e.message)?;
+    for post in posts.iter() {
+        handle.block_on(purge_post(connection, &context.storage, post))?;
+    }
+    handle.block_on(purge_account(connection, &context.storage, &user))?;
+    log::info!("Purged user {} and {} posts", user.uuid, posts.len());
+
+    let text = format!(
+        "Hello {},\n\nYour our_application account and all of its posts and media have been permanently deleted.",
+        user.username
+    );
+    if let Err(e) = send_mail(
+        &user.email,
+        &user.username,
+        "Your account has been deleted",
+        &text,
+    ) {
+        log::warn!("Cannot send deletion email to {}: {}", user.uuid, e);
+    }
+    Ok(())
+}
+
+async fn purge_account(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    user: &User,
+) -> Result<(), String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let uploads = ResumableUpload::destroy_for_user(&mut transaction, &user.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    Notification::destroy_for_user(&mut transaction, &user.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    let exports = Export::destroy_for_user(&mut transaction, &user.uuid)
+        .await
+        .map_err(|e| e.message)?;
+    User::purge(&mut transaction, &user.uuid.to_string())
+        .await
+        .map_err(|e| e.message)?;
+    transaction.commit().await.map_err(|e| e.to_string())?;
+
+    for uuid in uploads {
+        let _ = tokio::fs::remove_file(storage.resumable_path(&uuid.to_string())).await;
+    }
+    storage.delete_keys(&exports).await;
+    Ok(())
+}
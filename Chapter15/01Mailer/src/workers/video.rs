@@ -1,35 +1,661 @@
+use crate::errors::our_error::OurError;
+use crate::events::{notify, Event};
+use crate::models::media_blob::MediaBlob;
+use crate::models::media_variant::MediaVariant;
+use crate::models::notification::NotificationEvent;
 use crate::models::post::Post;
+use crate::models::post_media::PostMedia;
+use crate::models::processing_status::ProcessingStatus;
 use crate::models::worker::Message;
-use ffmpeg_cli::{FfmpegBuilder, File, Parameter};
-use sqlx::pool::PoolConnection;
-use sqlx::Postgres;
-use std::process::Stdio;
+use crate::storage::blobs::store_dir;
+use crate::storage::Storage;
+use crate::workers::{JobError, WorkerContext};
+use rocket::http::Status;
+use rocket::serde::json::{serde_json, Value};
+use rocket::serde::Deserialize;
+use sqlx::{Acquire, PgConnection};
+use std::fs::{create_dir_all, metadata, remove_dir_all, remove_file, write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use tokio::runtime::Handle;
 
-pub fn process_video(connection: &mut PoolConnection<Postgres>, wm: Message) -> Result<(), ()> {
-    let mut dest = String::from("static/");
-    dest.push_str(&wm.dest_filename);
-    let builder = FfmpegBuilder::new()
-        .stderr(Stdio::piped())
-        .option(Parameter::Single("nostdin"))
-        .option(Parameter::Single("y"))
-        .input(File::new(&wm.orig_filename))
-        .output(
-            File::new(&dest)
-                .option(Parameter::KeyValue("vcodec", "libx265"))
-                .option(Parameter::KeyValue("crf", "28")),
-        );
-    let make_permanent = async {
-        let ffmpeg = builder.run().await.unwrap();
-        let _ = ffmpeg.process.wait_with_output().unwrap();
-        let mut display_path = String::from("/assets/");
-        display_path.push_str(&wm.dest_filename);
-        Post::make_permanent(connection, &wm.uuid, &display_path).await
+const MAX_ERROR_LENGTH: usize = 4096;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct VideoConfig {
+    pub ffmpeg: String,
+    pub ffprobe: String,
+    pub max_duration: f64,
+    pub max_width: u32,
+    pub max_height: u32,
+    pub renditions: Vec<u32>,
+    pub crf: u32,
+    pub preset: String,
+    pub audio_bitrate: String,
+    pub webm: bool,
+    pub webm_crf: u32,
+    pub poster_time: f64,
+    pub hls: bool,
+    pub hls_segment_duration: u32,
+}
+
+impl Default for VideoConfig {
+    fn default() -> Self {
+        VideoConfig {
+            ffmpeg: String::from("ffmpeg"),
+            ffprobe: String::from("ffprobe"),
+            max_duration: 600.0,
+            max_width: 3840,
+            max_height: 2160,
+            renditions: vec![360, 720],
+            crf: 23,
+            preset: String::from("veryfast"),
+            audio_bitrate: String::from("128k"),
+            webm: false,
+            webm_crf: 33,
+            poster_time: 1.0,
+            hls: true,
+            hls_segment_duration: 6,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VideoProbe {
+    pub duration: f64,
+    pub width: u32,
+    pub height: u32,
+    pub video_codec: String,
+    pub audio_codec: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct TranscodedVideo {
+    pub variants: Vec<MediaVariant>,
+    pub poster: Option<String>,
+    pub duration: f64,
+}
+
+pub fn probe(config: &VideoConfig, input: &str) -> Result<VideoProbe, JobError> {
+    let json = ffprobe(&config.ffprobe, input)?;
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    let video = streams
+        .iter()
+        .find(|s| s["codec_type"] == "video")
+        .ok_or_else(|| {
+            JobError::Permanent(String::from("Upload does not contain a video stream"))
+        })?;
+    let audio = streams.iter().find(|s| s["codec_type"] == "audio");
+    let duration = json["format"]["duration"]
+        .as_str()
+        .and_then(|d| d.parse::<f64>().ok())
+        .or_else(|| json["format"]["duration"].as_f64())
+        .ok_or_else(|| JobError::Permanent(String::from("Cannot determine video duration")))?;
+    let probe = VideoProbe {
+        duration,
+        width: video["width"].as_u64().unwrap_or(0) as u32,
+        height: video["height"].as_u64().unwrap_or(0) as u32,
+        video_codec: video["codec_name"].as_str().unwrap_or("").to_string(),
+        audio_codec: audio.and_then(|a| a["codec_name"].as_str().map(String::from)),
+    };
+    if probe.video_codec.is_empty() {
+        return Err(JobError::Permanent(String::from("Unsupported video codec")));
+    }
+    if probe.width == 0 || probe.height == 0 {
+        return Err(JobError::Permanent(String::from(
+            "Cannot determine video resolution",
+        )));
+    }
+    if probe.duration > config.max_duration {
+        return Err(JobError::Permanent(format!(
+            "Video is {:.0} seconds long, the limit is {:.0} seconds",
+            probe.duration, config.max_duration
+        )));
+    }
+    if probe.width > config.max_width || probe.height > config.max_height {
+        return Err(JobError::Permanent(format!(
+            "Video resolution {}x{} exceeds the limit of {}x{}",
+            probe.width, probe.height, config.max_width, config.max_height
+        )));
+    }
+    Ok(probe)
+}
+
+pub fn rendition_heights(config: &VideoConfig, source_height: u32) -> Vec<u32> {
+    let mut heights: Vec<u32> = config
+        .renditions
+        .iter()
+        .map(|h| h - h % 2)
+        .filter(|h| *h > 0 && *h <= source_height)
+        .collect();
+    if heights.is_empty() {
+        heights.push((source_height - source_height % 2).max(2));
+    }
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    heights.dedup();
+    heights
+}
+
+pub fn transcode(
+    config: &VideoConfig,
+    input: &str,
+    output_dir: &Path,
+    base_name: &str,
+) -> Result<TranscodedVideo, JobError> {
+    let probe = probe(config, input)?;
+    let mut created: Vec<PathBuf> = vec![];
+    let result = transcode_probed(config, input, output_dir, base_name, &probe, &mut created);
+    if result.is_err() {
+        for path in created {
+            if path.is_dir() {
+                let _ = remove_dir_all(path);
+            } else {
+                let _ = remove_file(path);
+            }
+        }
+    }
+    Ok(result?)
+}
+
+fn transcode_probed(
+    config: &VideoConfig,
+    input: &str,
+    output_dir: &Path,
+    base_name: &str,
+    probe: &VideoProbe,
+    created: &mut Vec<PathBuf>,
+) -> Result<TranscodedVideo, String> {
+    let mut mp4_variants = vec![];
+    let mut webm_variants = vec![];
+    for height in rendition_heights(config, probe.height) {
+        let width = scaled_width(probe, height);
+        let label = format!("{}p", height);
+        let scale = format!("scale=-2:{}", height);
+
+        let filename = format!("{}_{}.mp4", base_name, label);
+        let dest = output_dir.join(&filename);
+        created.push(dest.clone());
+        let mut args = vec![
+            "-nostdin",
+            "-y",
+            "-i",
+            input,
+            "-map_metadata",
+            "-1",
+            "-map",
+            "0:v:0",
+        ];
+        if probe.audio_codec.is_some() {
+            args.extend(&["-map", "0:a:0"]);
+        }
+        let crf = config.crf.to_string();
+        args.extend(&[
+            "-vf",
+            &scale,
+            "-c:v",
+            "libx264",
+            "-preset",
+            &config.preset,
+            "-crf",
+            &crf,
+            "-pix_fmt",
+            "yuv420p",
+            "-movflags",
+            "+faststart",
+        ]);
+        audio_args(config, probe, "aac", &mut args);
+        let dest_str = dest.to_string_lossy();
+        args.push(&dest_str);
+        run(&config.ffmpeg, &args)?;
+        mp4_variants.push(MediaVariant {
+            path: filename,
+            mime: String::from("video/mp4"),
+            label: label.clone(),
+            width: Some(width),
+            height: Some(height),
+        });
+
+        if config.webm {
+            let filename = format!("{}_{}.webm", base_name, label);
+            let dest = output_dir.join(&filename);
+            created.push(dest.clone());
+            let mut args = vec![
+                "-nostdin",
+                "-y",
+                "-i",
+                input,
+                "-map_metadata",
+                "-1",
+                "-map",
+                "0:v:0",
+            ];
+            if probe.audio_codec.is_some() {
+                args.extend(&["-map", "0:a:0"]);
+            }
+            let crf = config.webm_crf.to_string();
+            args.extend(&[
+                "-vf",
+                &scale,
+                "-c:v",
+                "libvpx-vp9",
+                "-crf",
+                &crf,
+                "-b:v",
+                "0",
+                "-row-mt",
+                "1",
+            ]);
+            audio_args(config, probe, "libopus", &mut args);
+            let dest_str = dest.to_string_lossy();
+            args.push(&dest_str);
+            run(&config.ffmpeg, &args)?;
+            webm_variants.push(MediaVariant {
+                path: filename,
+                mime: String::from("video/webm"),
+                label,
+                width: Some(width),
+                height: Some(height),
+            });
+        }
+    }
+
+    let poster_filename = format!("{}.jpg", base_name);
+    let poster_dest = output_dir.join(&poster_filename);
+    created.push(poster_dest.clone());
+    let poster_time = format!(
+        "{:.3}",
+        config.poster_time.min(probe.duration / 2.0).max(0.0)
+    );
+    let poster_dest_str = poster_dest.to_string_lossy();
+    let poster_scale = format!(
+        "scale=-2:{}",
+        mp4_variants[0].height.unwrap_or(probe.height)
+    );
+    run(
+        &config.ffmpeg,
+        &[
+            "-nostdin",
+            "-y",
+            "-ss",
+            &poster_time,
+            "-i",
+            input,
+            "-map_metadata",
+            "-1",
+            "-frames:v",
+            "1",
+            "-vf",
+            &poster_scale,
+            "-q:v",
+            "3",
+            &poster_dest_str,
+        ],
+    )?;
+
+    let hls_variant = if config.hls {
+        Some(package_hls(
+            config,
+            output_dir,
+            base_name,
+            probe,
+            &mp4_variants,
+            created,
+        )?)
+    } else {
+        None
     };
 
+    mp4_variants.extend(webm_variants);
+    mp4_variants.extend(hls_variant);
+    Ok(TranscodedVideo {
+        variants: mp4_variants,
+        poster: Some(poster_filename),
+        duration: probe.duration,
+    })
+}
+
+fn package_hls(
+    config: &VideoConfig,
+    output_dir: &Path,
+    base_name: &str,
+    probe: &VideoProbe,
+    renditions: &[MediaVariant],
+    created: &mut Vec<PathBuf>,
+) -> Result<MediaVariant, String> {
+    let hls_dir = output_dir.join("hls").join(base_name);
+    create_dir_all(&hls_dir).map_err(|e| format!("Cannot create HLS directory: {}", e))?;
+    created.push(hls_dir.clone());
+
+    let segment_duration = config.hls_segment_duration.max(1).to_string();
+    let mut master = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+    for rendition in renditions.iter() {
+        let source = output_dir.join(&rendition.path);
+        let source_str = source.to_string_lossy();
+        let segments = hls_dir.join(format!("{}_%03d.ts", rendition.label));
+        let segments_str = segments.to_string_lossy();
+        let playlist_name = format!("{}.m3u8", rendition.label);
+        let playlist = hls_dir.join(&playlist_name);
+        let playlist_str = playlist.to_string_lossy();
+        run(
+            &config.ffmpeg,
+            &[
+                "-nostdin",
+                "-y",
+                "-i",
+                &source_str,
+                "-c",
+                "copy",
+                "-f",
+                "hls",
+                "-hls_time",
+                &segment_duration,
+                "-hls_playlist_type",
+                "vod",
+                "-hls_segment_filename",
+                &segments_str,
+                &playlist_str,
+            ],
+        )?;
+        let size = metadata(&source).map(|m| m.len()).unwrap_or(0);
+        let bandwidth = ((size * 8) as f64 / probe.duration.max(1.0)).ceil() as u64;
+        master.push_str(&format!(
+            "#EXT-X-STREAM-INF:BANDWIDTH={},RESOLUTION={}x{}\n{}\n",
+            bandwidth.max(1),
+            rendition.width.unwrap_or(0),
+            rendition.height.unwrap_or(0),
+            playlist_name
+        ));
+    }
+    write(hls_dir.join("master.m3u8"), master)
+        .map_err(|e| format!("Cannot write HLS playlist: {}", e))?;
+
+    Ok(MediaVariant {
+        path: format!("hls/{}/master.m3u8", base_name),
+        mime: String::from("application/vnd.apple.mpegurl"),
+        label: String::from("hls"),
+        width: renditions[0].width,
+        height: renditions[0].height,
+    })
+}
+
+fn audio_args<'a>(
+    config: &'a VideoConfig,
+    probe: &VideoProbe,
+    codec: &'a str,
+    args: &mut Vec<&'a str>,
+) {
+    if probe.audio_codec.is_some() {
+        args.extend(&["-c:a", codec, "-b:a", &config.audio_bitrate]);
+    } else {
+        args.push("-an");
+    }
+}
+
+fn scaled_width(probe: &VideoProbe, height: u32) -> u32 {
+    let width = (probe.width as u64 * height as u64 / probe.height as u64) as u32;
+    (width - width % 2).max(2)
+}
+
+pub(crate) fn ffprobe(program: &str, input: &str) -> Result<Value, JobError> {
+    let output = Command::new(program)
+        .args(&[
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+            input,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot start {}: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(JobError::Permanent(format!(
+            "Cannot read the uploaded media\n{}",
+            tail(&stderr)
+        )));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| JobError::Permanent(format!("Cannot read ffprobe output: {}", e)))
+}
+
+pub(crate) fn run(program: &str, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot start {}: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} exited with {}\n{}",
+            program,
+            output.status,
+            tail(&stderr)
+        ));
+    }
+    Ok(output.stdout)
+}
+
+pub fn process_video(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    wm: &Message,
+) -> Result<(), JobError> {
+    process_upload(connection, context, wm, |source, output_dir, base_name| {
+        transcode(&context.video, source, output_dir, base_name)
+    })
+}
+
+pub(crate) fn process_upload<F>(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    wm: &Message,
+    transcode: F,
+) -> Result<(), JobError>
+where
+    F: FnOnce(&str, &Path, &str) -> Result<TranscodedVideo, JobError>,
+{
     let handle = Handle::current();
-    Ok(handle
-        .block_on(make_permanent)
-        .map(|_| ())
-        .map_err(|_| ())?)
+    if wm.is_album_item() {
+        if let Err(e) = handle.block_on(PostMedia::find(connection, &wm.media_uuid)) {
+            if e.status != Status::NotFound {
+                return Err(JobError::from(e.message));
+            }
+            let _ = handle.block_on(context.storage.delete(&wm.orig_filename));
+            return Ok(());
+        }
+    }
+    handle
+        .block_on(update_status(
+            connection,
+            wm,
+            ProcessingStatus::Processing,
+            None,
+        ))
+        .map_err(|e| e.message)?;
+
+    let base_name = Path::new(&wm.dest_filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| wm.uuid.clone());
+    let storage = &context.storage;
+    let source_name = Path::new(&wm.orig_filename)
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("{}.source", base_name));
+    let source = storage.staging_path(&source_name);
+    let output_dir = storage.staging_path(&base_name);
+    let transcoded = handle
+        .block_on(storage.get_file(&wm.orig_filename, &source))
+        .and_then(|_| {
+            create_dir_all(&output_dir)
+                .map_err(|e| format!("Cannot create staging directory: {}", e))
+        })
+        .map_err(JobError::from)
+        .and_then(|_| transcode(&source.to_string_lossy(), &output_dir, &base_name));
+    let _ = remove_file(&source);
+    let transcoded = match transcoded {
+        Ok(transcoded) => transcoded,
+        Err(reason) => {
+            let _ = remove_dir_all(&output_dir);
+            let _ = handle.block_on(update_status(
+                connection,
+                wm,
+                ProcessingStatus::Queued,
+                Some(reason.message()),
+            ));
+            return Err(reason);
+        }
+    };
+
+    let mut uploaded = vec![];
+    let saved = handle.block_on(save_video(
+        connection,
+        storage,
+        wm,
+        transcoded,
+        &output_dir,
+        &mut uploaded,
+    ));
+    let _ = remove_dir_all(&output_dir);
+    let hub = &context.hub;
+    handle.block_on(async {
+        let post = match saved {
+            Ok(post) => post,
+            Err(e) => {
+                storage.delete_keys(&uploaded).await;
+                return Err(JobError::from(e));
+            }
+        };
+        let _ = storage.delete(&wm.orig_filename).await;
+        hub.publish(Event::VideoProcessed {
+            user_uuid: post.user_uuid,
+            post_uuid: post.uuid,
+            post_html: post.to_show_post().post_html,
+        });
+        if post.processing_status == ProcessingStatus::Ready {
+            notify(
+                connection,
+                hub,
+                NotificationEvent::VideoProcessed { post: &post },
+            )
+            .await;
+        }
+        Ok(())
+    })
+}
+
+async fn save_video(
+    connection: &mut PgConnection,
+    storage: &Storage,
+    wm: &Message,
+    transcoded: TranscodedVideo,
+    output_dir: &Path,
+    uploaded: &mut Vec<String>,
+) -> Result<Post, String> {
+    let mut transaction = connection.begin().await.map_err(|e| e.to_string())?;
+    let stored = store_dir(&mut transaction, storage, output_dir).await?;
+    uploaded.extend(stored.uploaded.iter().cloned());
+    let variants = stored.publish(storage, transcoded.variants);
+    let poster = transcoded.poster.map(|poster| stored.url(storage, &poster));
+    let post = if wm.is_album_item() {
+        let item = PostMedia::make_permanent(
+            &mut transaction,
+            &wm.media_uuid,
+            &variants[0].path,
+            &variants,
+            poster.as_deref(),
+            Some(transcoded.duration),
+            stored.size,
+            &stored.hashes,
+        )
+        .await
+        .map_err(|e| e.message)?;
+        MediaBlob::link(&mut transaction, &item.post_uuid, &stored.hashes)
+            .await
+            .map_err(|e| e.message)?;
+        Post::refresh_album(&mut transaction, &item.post_uuid)
+            .await
+            .map_err(|e| e.message)?
+    } else {
+        let post = Post::make_permanent(
+            &mut transaction,
+            &wm.uuid,
+            &variants[0].path,
+            &variants,
+            poster.as_deref(),
+            Some(transcoded.duration),
+            stored.size,
+        )
+        .await
+        .map_err(|e| e.message)?;
+        MediaBlob::link(&mut transaction, &post.uuid, &stored.hashes)
+            .await
+            .map_err(|e| e.message)?;
+        post
+    };
+    transaction.commit().await.map_err(|e| e.to_string())?;
+    Ok(post)
+}
+
+pub async fn processing_failed(
+    connection: &mut PgConnection,
+    context: &WorkerContext,
+    wm: &Message,
+    error: &str,
+) {
+    let updated = update_status(connection, wm, ProcessingStatus::Failed, Some(error)).await;
+    if let Ok(post) = updated {
+        context.hub.publish(Event::VideoFailed {
+            user_uuid: post.user_uuid,
+            post_uuid: post.uuid,
+            post_html: post.to_show_post().post_html,
+        });
+        notify(
+            connection,
+            &context.hub,
+            NotificationEvent::VideoFailed { post: &post },
+        )
+        .await;
+    }
+}
+
+async fn update_status(
+    connection: &mut PgConnection,
+    wm: &Message,
+    processing_status: ProcessingStatus,
+    processing_error: Option<&str>,
+) -> Result<Post, OurError> {
+    if !wm.is_album_item() {
+        return Post::update_processing_status(
+            connection,
+            &wm.uuid,
+            processing_status,
+            processing_error,
+        )
+        .await;
+    }
+    let item = PostMedia::update_processing_status(
+        connection,
+        &wm.media_uuid,
+        processing_status,
+        processing_error,
+    )
+    .await?;
+    Post::refresh_album(connection, &item.post_uuid).await
+}
+
+fn tail(stderr: &str) -> &str {
+    if stderr.len() <= MAX_ERROR_LENGTH {
+        return stderr;
+    }
+    let mut start = stderr.len() - MAX_ERROR_LENGTH;
+    while !stderr.is_char_boundary(start) {
+        start += 1;
+    }
+    &stderr[start..]
 }
@@ -0,0 +1,123 @@
+mod common;
+
+use our_application::models::media_variant::MediaVariant;
+use our_application::models::post::Post;
+use our_application::models::post_media::PostMedia;
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::worker::Message;
+use rocket::serde::json::serde_json;
+use sqlx::types::Json;
+use uuid::Uuid;
+
+fn album(media: Vec<PostMedia>) -> Post {
+    Post {
+        post_type: PostType::Album,
+        content: String::new(),
+        media: Json(media),
+        ..common::post()
+    }
+}
+
+fn item(
+    position: i32,
+    media_type: PostType,
+    content: &str,
+    processing_status: ProcessingStatus,
+) -> PostMedia {
+    PostMedia {
+        uuid: Uuid::new_v4(),
+        post_uuid: Uuid::new_v4(),
+        position,
+        media_type,
+        content: String::from(content),
+        variants: Json(vec![]),
+        poster: None,
+        duration: None,
+        processing_status,
+        processing_error: None,
+        media_size: 0,
+        blob_hashes: vec![],
+        created_at: common::created_at(),
+    }
+}
+
+#[test]
+fn album_renders_a_gallery_in_order() {
+    let post = album(vec![
+        item(
+            0,
+            PostType::Photo,
+            "/assets/blobs/aa/first.jpg",
+            ProcessingStatus::Ready,
+        ),
+        item(
+            1,
+            PostType::Video,
+            "/assets/second.mp4",
+            ProcessingStatus::Queued,
+        ),
+    ]);
+    let show = post.to_show_post();
+    assert_eq!(show.media.len(), 2);
+    assert!(show.media[0].contains("/assets/blobs/aa/first.jpg"));
+    assert!(show.media[1].contains("loading.gif"));
+    let first = show.post_html.find("first.jpg").unwrap();
+    let second = show.post_html.find("loading.gif").unwrap();
+    assert!(show.post_html.starts_with("<div class=\"row album\">"));
+    assert!(first < second);
+}
+
+#[test]
+fn single_posts_have_no_gallery() {
+    let mut post = album(vec![]);
+    post.post_type = PostType::Photo;
+    post.content = String::from("/assets/photo.jpg");
+    assert!(post.to_show_post().media.is_empty());
+}
+
+#[test]
+fn album_media_paths_include_every_item() {
+    let mut video = item(
+        1,
+        PostType::Video,
+        "/assets/hls/abc/master.m3u8",
+        ProcessingStatus::Ready,
+    );
+    video.poster = Some(String::from("/assets/blobs/bb/poster.jpg"));
+    video.variants = Json(vec![MediaVariant {
+        path: String::from("/assets/blobs/cc/video_720p.mp4"),
+        mime: String::from("video/mp4"),
+        label: String::from("720p"),
+        width: Some(1280),
+        height: Some(720),
+    }]);
+    let post = album(vec![
+        item(
+            0,
+            PostType::Photo,
+            "/assets/blobs/aa/photo.jpg",
+            ProcessingStatus::Ready,
+        ),
+        video,
+    ]);
+    assert_eq!(
+        post.media_paths(),
+        vec![
+            String::from("/assets/blobs/aa/photo.jpg"),
+            String::from("/assets/hls/abc/master.m3u8"),
+            String::from("/assets/blobs/cc/video_720p.mp4"),
+            String::from("/assets/blobs/bb/poster.jpg"),
+        ]
+    );
+}
+
+#[test]
+fn video_jobs_without_media_uuid_target_the_post() {
+    let payload = r#"{"uuid":"abc","orig_filename":"uploads/abc.mp4","dest_filename":"abc.mp4"}"#;
+    let wm: Message = serde_json::from_str(payload).unwrap();
+    assert!(!wm.is_album_item());
+    let mut wm = Message::new();
+    wm.media_uuid = Uuid::new_v4().to_string();
+    assert!(wm.is_album_item());
+}
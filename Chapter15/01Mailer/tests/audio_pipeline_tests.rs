@@ -0,0 +1,180 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{workdir, write_script};
+use our_application::models::audio_post::AudioPost;
+use our_application::models::media_variant::MediaVariant;
+use our_application::models::post::Post;
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::routes::post::{extension_for, post_type_for};
+use our_application::traits::DisplayPostContent;
+use our_application::workers::audio::{probe_audio, transcode_audio, AudioConfig};
+use our_application::workers::video::VideoConfig;
+use rocket::http::ContentType;
+use sqlx::types::Json;
+use std::fs;
+use std::path::Path;
+
+const PROBE_FLAC: &str = r#"{
+    "streams": [
+        {"codec_type": "audio", "codec_name": "flac", "channels": 2}
+    ],
+    "format": {"duration": "95.250000"}
+}"#;
+
+const PROBE_SILENT_VIDEO: &str = r#"{
+    "streams": [
+        {"codec_type": "video", "codec_name": "h264", "width": 854, "height": 480}
+    ],
+    "format": {"duration": "3.000000"}
+}"#;
+
+fn fake_tools(dir: &Path, probe_output: &str) -> VideoConfig {
+    let ffprobe = dir.join("ffprobe");
+    let ffmpeg = dir.join("ffmpeg");
+    fs::write(dir.join("probe.json"), probe_output).unwrap();
+    write_script(
+        &ffprobe,
+        &format!("cat '{}'\n", dir.join("probe.json").display()),
+    );
+    write_script(
+        &ffmpeg,
+        &format!(
+            "echo \"$@\" >> '{}'\nfor last; do :; done\ntouch \"$last\"\n",
+            dir.join("ffmpeg.log").display()
+        ),
+    );
+    VideoConfig {
+        ffmpeg: ffmpeg.to_string_lossy().to_string(),
+        ffprobe: ffprobe.to_string_lossy().to_string(),
+        ..VideoConfig::default()
+    }
+}
+
+fn ffmpeg_calls(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("ffmpeg.log"))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn audio_post(processing_status: ProcessingStatus) -> Post {
+    Post {
+        post_type: PostType::Audio,
+        content: String::from("/assets/blobs/aa/track.m4a"),
+        processing_status,
+        variants: Json(vec![MediaVariant {
+            path: String::from("/assets/blobs/aa/track.m4a"),
+            mime: String::from("audio/mp4"),
+            label: String::from("aac"),
+            width: None,
+            height: None,
+        }]),
+        poster: Some(String::from("/assets/blobs/bb/track_waveform.png")),
+        duration: Some(95.25),
+        ..common::post()
+    }
+}
+
+#[test]
+fn probe_reads_audio_stream() {
+    let dir = workdir();
+    let tools = fake_tools(&dir, PROBE_FLAC);
+    let probed = probe_audio(&tools, &AudioConfig::default(), "input.flac").unwrap();
+    assert_eq!(probed.duration, 95.25);
+    assert_eq!(probed.codec, "flac");
+    assert_eq!(probed.channels, 2);
+}
+
+#[test]
+fn probe_rejects_input_without_audio() {
+    let dir = workdir();
+    let tools = fake_tools(&dir, PROBE_SILENT_VIDEO);
+    let err = probe_audio(&tools, &AudioConfig::default(), "input.wav").unwrap_err();
+    assert!(err.message().contains("audio stream"));
+    assert!(err.is_permanent());
+}
+
+#[test]
+fn probe_enforces_duration_limit() {
+    let dir = workdir();
+    let tools = fake_tools(&dir, PROBE_FLAC);
+    let config = AudioConfig {
+        max_duration: 60.0,
+        ..AudioConfig::default()
+    };
+    let err = probe_audio(&tools, &config, "input.flac").unwrap_err();
+    assert!(err.message().contains("the limit is 60 seconds"));
+    assert!(err.is_permanent());
+}
+
+#[test]
+fn transcode_normalizes_loudness_and_draws_waveform() {
+    let dir = workdir();
+    let tools = fake_tools(&dir, PROBE_FLAC);
+    let output = dir.join("out");
+    fs::create_dir_all(&output).unwrap();
+    let transcoded = transcode_audio(
+        &tools,
+        &AudioConfig::default(),
+        "input.flac",
+        &output,
+        "track",
+    )
+    .unwrap();
+    assert_eq!(transcoded.duration, 95.25);
+    assert_eq!(transcoded.variants.len(), 1);
+    assert_eq!(transcoded.variants[0].path, "track.m4a");
+    assert_eq!(transcoded.variants[0].mime, "audio/mp4");
+    assert_eq!(transcoded.poster.as_deref(), Some("track_waveform.png"));
+    assert!(output.join("track.m4a").exists());
+    assert!(output.join("track_waveform.png").exists());
+
+    let calls = ffmpeg_calls(&dir);
+    assert_eq!(calls.len(), 2);
+    assert!(calls[0].contains("loudnorm=I=-16:TP=-1.5:LRA=11"));
+    assert!(calls[0].contains("-c:a aac"));
+    assert!(calls[0].contains("-vn"));
+    assert!(calls[1].contains("showwavespic=s=1200x200"));
+}
+
+#[test]
+fn audio_post_renders_player_with_waveform() {
+    let html = AudioPost::new(&audio_post(ProcessingStatus::Ready)).raw_html();
+    assert!(html.contains("<audio controls preload=\"metadata\">"));
+    assert!(html.contains("<source src=\"/assets/blobs/aa/track.m4a\" type=\"audio/mp4\">"));
+    assert!(html.contains("/assets/blobs/bb/track_waveform.png"));
+    assert!(html.contains("<figcaption>1:35</figcaption>"));
+
+    let html = AudioPost::new(&audio_post(ProcessingStatus::Queued)).raw_html();
+    assert!(html.contains("loading.gif"));
+    let html = AudioPost::new(&audio_post(ProcessingStatus::Failed)).raw_html();
+    assert!(html.contains("This audio could not be processed."));
+}
+
+#[test]
+fn audio_content_types_become_audio_posts() {
+    for (content_type, ext) in [
+        ("audio/mpeg", "mp3"),
+        ("audio/ogg", "ogg"),
+        ("audio/wav", "wav"),
+        ("audio/x-wav", "wav"),
+        ("audio/flac", "flac"),
+        ("audio/x-m4a", "m4a"),
+        ("audio/mp4", "m4a"),
+    ]
+    .iter()
+    {
+        let content_type = ContentType::parse_flexible(content_type).unwrap();
+        assert_eq!(post_type_for(&content_type), Some(PostType::Audio));
+        assert_eq!(extension_for(&content_type).as_deref(), Some(*ext));
+    }
+    let video = ContentType::parse_flexible("video/ogg").unwrap();
+    assert_eq!(post_type_for(&video), Some(PostType::Video));
+    let unknown = ContentType::parse_flexible("audio/x-unknown").unwrap();
+    assert_eq!(post_type_for(&unknown), None);
+    assert_eq!(AudioPost::format_duration(3725.0), "1:02:05");
+}
@@ -0,0 +1,121 @@
+#![allow(dead_code)]
+
+use chrono::{offset::Utc, TimeZone};
+use our_application::models::our_date_time::OurDateTime;
+use our_application::models::post::Post;
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::user::{NewUser, User};
+use our_application::models::user_status::UserStatus;
+use our_application::models::visibility::Visibility;
+use our_application::storage::local::LocalConfig;
+use our_application::storage::{Storage, StorageConfig};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub fn workdir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("our-application-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(unix)]
+pub fn write_script(path: &Path, body: &str) {
+    std::fs::write(path, format!("#!/bin/sh\n{}", body)).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+pub fn created_at() -> OurDateTime {
+    OurDateTime(Utc.timestamp_nanos(1431648000000000))
+}
+
+// A published public text post; tests override the fields they exercise with
+// `Post { .., ..post() }`.
+pub fn post() -> Post {
+    Post {
+        uuid: Uuid::new_v4(),
+        user_uuid: Uuid::new_v4(),
+        post_type: PostType::Text,
+        content: String::from("hello"),
+        created_at: created_at(),
+        processing_status: ProcessingStatus::Ready,
+        processing_error: None,
+        variants: Json(vec![]),
+        poster: None,
+        duration: None,
+        media_size: 0,
+        media: Json(vec![]),
+        link_url: None,
+        link_preview: Json(None),
+        visibility: Visibility::Public,
+        publish_status: PublishStatus::Published,
+        publish_at: None,
+        deleted_at: None,
+    }
+}
+
+pub fn user() -> User {
+    User {
+        uuid: Uuid::new_v4(),
+        username: String::from("testuser"),
+        email: String::from("testuser@example.com"),
+        password_hash: String::new(),
+        description: None,
+        status: UserStatus::Active,
+        created_at: created_at(),
+        updated_at: created_at(),
+        is_admin: false,
+        storage_quota: None,
+        daily_post_limit: None,
+        deleted_at: None,
+    }
+}
+
+// Route tests talk to the database configured for the application, like the
+// functional tests do.
+pub async fn database() -> PgPool {
+    let database_url: String = rocket::Config::figment()
+        .extract_inner("databases.main_connection.url")
+        .unwrap();
+    PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&database_url)
+        .await
+        .unwrap()
+}
+
+pub async fn create_user(pool: &PgPool) -> User {
+    let name = Uuid::new_v4().to_simple().to_string()[..12].to_string();
+    let email = format!("{}@example.com", name);
+    let mut connection = pool.acquire().await.unwrap();
+    User::create(
+        &mut connection,
+        &NewUser {
+            username: &name,
+            email: &email,
+            password: "Passw0rd!Passw0rd",
+            password_confirmation: "Passw0rd!Passw0rd",
+            description: None,
+            authenticity_token: "",
+        },
+    )
+    .await
+    .unwrap()
+}
+
+pub fn local_storage() -> Storage {
+    Storage::new(&StorageConfig {
+        staging_dir: workdir().to_string_lossy().to_string(),
+        local: LocalConfig {
+            root: workdir().to_string_lossy().to_string(),
+            public_url: String::from("/assets"),
+        },
+        ..StorageConfig::default()
+    })
+    .unwrap()
+}
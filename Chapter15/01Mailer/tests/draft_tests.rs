@@ -0,0 +1,87 @@
+mod common;
+
+use chrono::{offset::Utc, Duration, TimeZone};
+use our_application::models::our_date_time::OurDateTime;
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::visibility::Visibility;
+use uuid::Uuid;
+
+fn post(publish_status: PublishStatus) -> Post {
+    Post {
+        publish_status,
+        ..common::post()
+    }
+}
+
+#[test]
+fn parses_publish_status_values() {
+    assert_eq!(
+        PublishStatus::parse("published"),
+        Some(PublishStatus::Published)
+    );
+    assert_eq!(PublishStatus::parse(" Draft "), Some(PublishStatus::Draft));
+    assert_eq!(
+        PublishStatus::parse("SCHEDULED"),
+        Some(PublishStatus::Scheduled)
+    );
+    assert_eq!(PublishStatus::parse("later"), None);
+}
+
+#[test]
+fn parses_publish_times_as_utc() {
+    let expected = Utc.with_ymd_and_hms(2022, 5, 21, 9, 30, 0).unwrap();
+    assert_eq!(
+        OurDateTime::parse("2022-05-21T09:30").map(|d| d.0),
+        Some(expected)
+    );
+    assert_eq!(
+        OurDateTime::parse("2022-05-21T09:30:00").map(|d| d.0),
+        Some(expected)
+    );
+    assert_eq!(
+        OurDateTime::parse("2022-05-21T11:30:00+02:00").map(|d| d.0),
+        Some(expected)
+    );
+    assert!(OurDateTime::parse("tomorrow").is_none());
+}
+
+#[test]
+fn unpublished_posts_are_only_visible_to_owners() {
+    let stranger = Uuid::new_v4();
+    for publish_status in [PublishStatus::Draft, PublishStatus::Scheduled].iter() {
+        let post = post(*publish_status);
+        assert!(post.can_view(Some(&post.user_uuid)));
+        assert!(!post.can_view(Some(&stranger)));
+        assert!(!post.can_view(None));
+    }
+    let post = post(PublishStatus::Published);
+    assert!(post.can_view(Some(&stranger)));
+}
+
+#[test]
+fn scheduled_posts_need_a_future_time() {
+    let future = (Utc::now() + Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+    let settings = PostSettings::new(
+        Visibility::Unlisted,
+        PublishStatus::Scheduled,
+        Some(&future),
+    )
+    .unwrap();
+    assert!(!settings.is_published());
+    assert!(settings.publish_at.is_some());
+
+    let past = (Utc::now() - Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+    assert!(PostSettings::new(Visibility::Public, PublishStatus::Scheduled, Some(&past)).is_err());
+    assert!(PostSettings::new(Visibility::Public, PublishStatus::Scheduled, None).is_err());
+    assert!(PostSettings::new(Visibility::Public, PublishStatus::Scheduled, Some("")).is_err());
+
+    let settings =
+        PostSettings::new(Visibility::Public, PublishStatus::Draft, Some(&future)).unwrap();
+    assert!(settings.publish_at.is_none());
+    assert!(PostSettings::default().is_published());
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use our_application::events::{publish_new_post, Event, EventHub};
+use our_application::fairings::db::DBConnection;
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::models::post::Post;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::visibility::Visibility;
+use our_application::routes::event;
+use rocket::http::{Cookie, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::tokio::io::AsyncReadExt;
+use rocket::tokio::time::{timeout, Duration};
+use rocket_db_pools::Database;
+use uuid::Uuid;
+
+fn new_post(author: &Uuid, visibility: Visibility) -> Event {
+    Event::NewPost {
+        user_uuid: *author,
+        post_uuid: Uuid::new_v4(),
+        post_html: String::new(),
+        visibility,
+    }
+}
+
+// Reads the stream until `marker` shows up and returns everything read so far.
+async fn read_until(response: &mut LocalResponse<'_>, marker: &str) -> String {
+    let mut body = String::new();
+    let mut buffer = [0; 4096];
+    while !body.contains(marker) {
+        let read = timeout(Duration::from_secs(5), response.read(&mut buffer))
+            .await
+            .expect("event stream stalled")
+            .unwrap();
+        assert!(read > 0, "event stream ended early");
+        body.push_str(std::str::from_utf8(&buffer[..read]).unwrap());
+    }
+    body
+}
+
+#[test]
+fn new_posts_only_reach_watchers_when_listed() {
+    let (author, watcher) = (Uuid::new_v4(), Uuid::new_v4());
+    let watched = [author];
+    for visibility in [
+        Visibility::Unlisted,
+        Visibility::Followers,
+        Visibility::Private,
+    ] {
+        let event = new_post(&author, visibility);
+        assert!(event.is_for(&author, &[]), "{}", visibility);
+        assert!(!event.is_for(&watcher, &watched), "{}", visibility);
+    }
+    let event = new_post(&author, Visibility::Public);
+    assert!(event.is_for(&watcher, &watched));
+    assert!(!event.is_for(&watcher, &[]));
+}
+
+#[test]
+fn owner_events_only_reach_the_owner() {
+    let (owner, watcher) = (Uuid::new_v4(), Uuid::new_v4());
+    let events = [
+        Event::VideoProcessed {
+            user_uuid: owner,
+            post_uuid: Uuid::new_v4(),
+            post_html: String::new(),
+        },
+        Event::VideoFailed {
+            user_uuid: owner,
+            post_uuid: Uuid::new_v4(),
+            post_html: String::new(),
+        },
+        Event::Notification {
+            user_uuid: owner,
+            notification_uuid: Uuid::new_v4(),
+            message: String::new(),
+            link: None,
+            unread_count: 1,
+        },
+    ];
+    for event in events.iter() {
+        assert!(event.is_for(&owner, &[]), "{}", event.name());
+        assert!(!event.is_for(&watcher, &[owner]), "{}", event.name());
+    }
+}
+
+#[test]
+fn unpublished_posts_are_not_broadcast() {
+    let hub = EventHub::new();
+    let mut events = hub.subscribe();
+    for publish_status in [PublishStatus::Draft, PublishStatus::Scheduled] {
+        publish_new_post(
+            &hub,
+            &Post {
+                publish_status,
+                ..common::post()
+            },
+        );
+    }
+    assert!(events.try_recv().is_err());
+}
+
+#[rocket::async_test]
+async fn event_stream_hides_restricted_posts_from_watchers() {
+    let pool = common::database().await;
+    let author = common::create_user(&pool).await;
+    let watcher = common::create_user(&pool).await;
+    let hub = EventHub::new();
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(hub.clone())
+        .mount("/", rocket::routes![event::events]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let mut response = client
+        .get(format!("/events?watch={}", author.uuid))
+        .private_cookie(Cookie::new(LOGIN_COOKIE_NAME, watcher.uuid.to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let hidden: Vec<Post> = [
+        Visibility::Private,
+        Visibility::Followers,
+        Visibility::Unlisted,
+    ]
+    .iter()
+    .map(|visibility| Post {
+        user_uuid: author.uuid,
+        visibility: *visibility,
+        ..common::post()
+    })
+    .collect();
+    for post in hidden.iter() {
+        publish_new_post(&hub, post);
+    }
+    let public = Post {
+        user_uuid: author.uuid,
+        ..common::post()
+    };
+    publish_new_post(&hub, &public);
+
+    // Events are delivered in order, so everything published before the
+    // public post has already been filtered once it arrives.
+    let body = read_until(&mut response, &public.uuid.to_string()).await;
+    for post in hidden.iter() {
+        assert!(
+            !body.contains(&post.uuid.to_string()),
+            "{} post leaked",
+            post.visibility
+        );
+    }
+    assert_eq!(body.matches("\"post_uuid\"").count(), 1);
+    client.rocket().shutdown().notify();
+}
+
+#[rocket::async_test]
+async fn event_stream_requires_a_user() {
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(EventHub::new())
+        .mount("/", rocket::routes![event::events]);
+    let client = Client::tracked(rocket).await.unwrap();
+    let response = client.get("/events").dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use chrono::{offset::Utc, Duration, TimeZone};
+use our_application::models::export::Export;
+use our_application::models::export_status::ExportStatus;
+use our_application::models::our_date_time::OurDateTime;
+use our_application::models::post::Post;
+use our_application::models::post_type::PostType;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::user::User;
+use our_application::models::visibility::Visibility;
+use our_application::workers::export::{export_key, write_documents, ExportConfig, ExportedPost};
+use rocket::serde::json::{serde_json, Value};
+use std::io::{Cursor, Read};
+use uuid::Uuid;
+use zip::{ZipArchive, ZipWriter};
+
+fn created_at() -> OurDateTime {
+    OurDateTime(Utc.with_ymd_and_hms(2022, 6, 4, 3, 19, 26).unwrap())
+}
+
+fn export(status: ExportStatus, expires_in: Option<i64>) -> Export {
+    Export {
+        uuid: Uuid::new_v4(),
+        user_uuid: Uuid::new_v4(),
+        status,
+        storage_key: Some(String::from("exports/archive.zip")),
+        size: 1024,
+        error: None,
+        created_at: created_at(),
+        completed_at: None,
+        expires_at: expires_in.map(|seconds| OurDateTime(Utc::now() + Duration::seconds(seconds))),
+    }
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+    let mut content = String::new();
+    archive
+        .by_name(name)
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    content
+}
+
+#[test]
+fn only_ready_unexpired_exports_can_be_downloaded() {
+    assert!(export(ExportStatus::Ready, Some(3600)).is_downloadable());
+    assert!(!export(ExportStatus::Ready, Some(-1)).is_downloadable());
+    assert!(!export(ExportStatus::Pending, None).is_downloadable());
+    assert!(!export(ExportStatus::Failed, Some(3600)).is_downloadable());
+}
+
+#[test]
+fn exports_are_stored_per_user() {
+    let export = export(ExportStatus::Pending, None);
+    assert_eq!(
+        export_key(&export),
+        format!("exports/{}/{}.zip", export.user_uuid, export.uuid)
+    );
+    assert_eq!(
+        export.filename(),
+        "our_application-export-20220604031926.zip"
+    );
+    assert_eq!(ExportConfig::default().ttl, 7 * 86400);
+}
+
+#[test]
+fn archive_contains_profile_and_posts_without_secrets() {
+    let user = User {
+        username: String::from("exporter"),
+        email: String::from("exporter@example.com"),
+        password_hash: String::from("$argon2id$secret"),
+        description: Some(String::from("Hello")),
+        created_at: created_at(),
+        updated_at: created_at(),
+        ..common::user()
+    };
+    let post = Post {
+        user_uuid: user.uuid,
+        post_type: PostType::Photo,
+        content: String::from("/assets/blobs/ab/photo.jpg"),
+        created_at: created_at(),
+        media_size: 2048,
+        visibility: Visibility::Private,
+        publish_status: PublishStatus::Draft,
+        ..common::post()
+    };
+    let posts = vec![ExportedPost {
+        post: &post,
+        files: vec![String::from("media/blobs/ab/photo.jpg")],
+    }];
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    write_documents(&mut zip, &user, &posts, &[]).unwrap();
+    let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+    assert!(archive.by_name("README.txt").is_ok());
+
+    let profile: Value = serde_json::from_str(&read_entry(&mut archive, "profile.json")).unwrap();
+    assert_eq!(profile["username"], "exporter");
+    assert_eq!(profile["email"], "exporter@example.com");
+    assert!(profile.get("password_hash").is_none());
+
+    let posts: Value = serde_json::from_str(&read_entry(&mut archive, "posts.json")).unwrap();
+    assert_eq!(posts[0]["uuid"], post.uuid.to_string());
+    assert_eq!(posts[0]["visibility"], "Private");
+    assert_eq!(posts[0]["publish_status"], "Draft");
+    assert_eq!(posts[0]["files"][0], "media/blobs/ab/photo.jpg");
+
+    let notifications: Value =
+        serde_json::from_str(&read_entry(&mut archive, "notifications.json")).unwrap();
+    assert_eq!(notifications, Value::Array(vec![]));
+}
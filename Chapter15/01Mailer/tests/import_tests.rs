@@ -0,0 +1,236 @@
+mod common;
+
+use our_application::events::EventHub;
+use our_application::guards::upload::UploadPipeline;
+use our_application::import::{
+    import_archive, post_key, read_manifest, user_key, validate_post, validate_user, ImportOutcome,
+    ImportedPost, ImportedUser, PostBody,
+};
+use our_application::media::photo::PhotoConfig;
+use our_application::media::scan::ScanConfig;
+use our_application::models::quota::QuotaConfig;
+use our_application::models::visibility::Visibility;
+use rocket::serde::json::serde_json;
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+const PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=4096,t=3,p=1$c2FsdHNhbHRzYWx0$l2ZVZ2ICzVLN4Q0aMaVbRexr3Pw0PdBmuZSE7hDCyp0";
+
+fn user() -> ImportedUser {
+    ImportedUser {
+        id: String::from("42"),
+        username: String::from("imported"),
+        email: String::from("imported@example.com"),
+        password: None,
+        password_hash: Some(String::from(PASSWORD_HASH)),
+        description: Some(String::from("Hello")),
+        created_at: Some(String::from("2019-01-02T03:04:05Z")),
+        posts: vec![],
+    }
+}
+
+fn post(content: Option<&str>, files: &[&str]) -> ImportedPost {
+    ImportedPost {
+        id: String::from("7"),
+        content: content.map(String::from),
+        files: files.iter().map(|file| String::from(*file)).collect(),
+        visibility: None,
+        created_at: None,
+    }
+}
+
+fn archive(manifest: &str) -> ZipArchive<Cursor<Vec<u8>>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("manifest.json", FileOptions::default())
+        .unwrap();
+    zip.write_all(manifest.as_bytes()).unwrap();
+    ZipArchive::new(zip.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn users_follow_new_user_rules() {
+    let created_at = validate_user(&user()).unwrap().unwrap();
+    assert_eq!(created_at.0.to_rfc3339(), "2019-01-02T03:04:05+00:00");
+
+    let mut short = user();
+    short.username = String::from("abc");
+    assert_eq!(
+        validate_user(&short).unwrap_err(),
+        "username: name cannot be empty"
+    );
+
+    let mut invalid = user();
+    invalid.email = String::from("not an email");
+    assert_eq!(validate_user(&invalid).unwrap_err(), "email: invalid email");
+
+    let mut strong = user();
+    strong.password = Some(String::from("correct horse battery staple"));
+    strong.password_hash = None;
+    assert!(validate_user(&strong).is_ok());
+}
+
+#[test]
+fn users_need_exactly_one_credential() {
+    let mut both = user();
+    both.password = Some(String::from("correct horse battery staple"));
+    assert!(validate_user(&both).is_err());
+
+    let mut neither = user();
+    neither.password_hash = None;
+    assert!(validate_user(&neither).is_err());
+
+    let mut garbage = user();
+    garbage.password_hash = Some(String::from("md5:abcdef"));
+    assert!(validate_user(&garbage)
+        .unwrap_err()
+        .starts_with("Invalid password_hash"));
+
+    let mut future = user();
+    future.created_at = Some(String::from("2999-01-01T00:00:00Z"));
+    assert!(validate_user(&future).is_err());
+}
+
+#[test]
+fn posts_reference_files_in_the_archive() {
+    let entries: HashSet<String> = ["media/a.jpg", "media/b.mp4", "media/c.exe"]
+        .iter()
+        .map(|entry| String::from(*entry))
+        .collect();
+
+    let text = validate_post(&post(Some("Hello"), &[]), &entries, 10).unwrap();
+    assert!(matches!(text.body, PostBody::Text(ref content) if content == "Hello"));
+    assert_eq!(text.visibility, Visibility::Public);
+
+    let album = validate_post(&post(None, &["media/a.jpg", "media/b.mp4"]), &entries, 10).unwrap();
+    match album.body {
+        PostBody::Files(files) => {
+            assert_eq!(files.len(), 2);
+            assert_eq!(files[0].1.to_string(), "image/jpeg");
+            assert_eq!(files[1].1.to_string(), "video/mp4");
+        }
+        _ => panic!("expected files"),
+    }
+
+    assert!(validate_post(&post(None, &["media/missing.png"]), &entries, 10).is_err());
+    assert!(validate_post(&post(None, &["media/c.exe"]), &entries, 10).is_err());
+    assert!(validate_post(&post(None, &["media/a.jpg", "media/b.mp4"]), &entries, 1).is_err());
+    assert!(validate_post(&post(Some("Hello"), &["media/a.jpg"]), &entries, 10).is_err());
+    assert!(validate_post(&post(Some("  "), &[]), &entries, 10).is_err());
+
+    let mut private = post(Some("Hello"), &[]);
+    private.visibility = Some(String::from("private"));
+    assert_eq!(
+        validate_post(&private, &entries, 10).unwrap().visibility,
+        Visibility::Private
+    );
+    private.visibility = Some(String::from("friends"));
+    assert!(validate_post(&private, &entries, 10).is_err());
+}
+
+#[test]
+fn manifests_are_versioned() {
+    let manifest = read_manifest(&mut archive(
+        r#"{"version": 1, "source": "legacy", "users": [{"id": "1", "username": "imported", "email": "imported@example.com", "posts": [{"id": "2", "content": "Hi"}]}]}"#,
+    ))
+    .unwrap();
+    assert_eq!(manifest.source, "legacy");
+    assert_eq!(user_key(&manifest.users[0]), "users/1");
+    assert_eq!(
+        post_key(&manifest.users[0], &manifest.users[0].posts[0]),
+        "users/1/posts/2"
+    );
+
+    assert!(read_manifest(&mut archive(r#"{"version": 2, "source": "legacy"}"#)).is_err());
+    assert!(read_manifest(&mut archive(r#"{"version": 1, "source": " "}"#)).is_err());
+    assert!(read_manifest(&mut archive("not json")).is_err());
+}
+
+#[rocket::async_test]
+async fn resumed_imports_do_not_duplicate_records() {
+    let pool = common::database().await;
+    let storage = common::local_storage();
+    let hub = EventHub::new();
+    let (photo, quota, scan) = (
+        PhotoConfig::default(),
+        QuotaConfig::default(),
+        ScanConfig::default(),
+    );
+    let pipeline = UploadPipeline {
+        hub: &hub,
+        photo: &photo,
+        storage: &storage,
+        quota: &quota,
+        scan: &scan,
+    };
+    let name = Uuid::new_v4().to_simple().to_string()[..10].to_string();
+    let users: Vec<_> = ["a", "b"]
+        .iter()
+        .enumerate()
+        .map(|(id, prefix)| {
+            serde_json::json!({
+                "id": id.to_string(),
+                "username": format!("{}{}", prefix, name),
+                "email": format!("{}{}@example.com", prefix, name),
+                "password_hash": PASSWORD_HASH,
+                "posts": [{"id": "1", "content": "Hello", "created_at": "2019-01-03T00:00:00Z"}],
+            })
+        })
+        .collect();
+    let manifest = serde_json::json!({"version": 1, "source": name, "users": users});
+    let path = common::workdir().join("import.zip");
+    let mut zip = ZipWriter::new(std::fs::File::create(&path).unwrap());
+    zip.start_file("manifest.json", FileOptions::default())
+        .unwrap();
+    zip.write_all(manifest.to_string().as_bytes()).unwrap();
+    zip.finish().unwrap();
+
+    let mut connection = pool.acquire().await.unwrap();
+    let report = import_archive(&mut connection, &pipeline, &path, false, false)
+        .await
+        .unwrap();
+    let records: Vec<&str> = report
+        .results
+        .iter()
+        .map(|result| result.record.as_str())
+        .collect();
+    assert_eq!(
+        records,
+        ["users/0", "users/0/posts/1", "users/1", "users/1/posts/1"]
+    );
+    let mut user_uuids = vec![];
+    for result in report.results.iter() {
+        match result.outcome {
+            ImportOutcome::Imported(Some(uuid)) if !result.record.contains("/posts/") => {
+                user_uuids.push(uuid)
+            }
+            ImportOutcome::Imported(Some(_)) => {}
+            ref outcome => panic!("{} was not imported: {:?}", result.record, outcome),
+        }
+    }
+
+    assert!(
+        import_archive(&mut connection, &pipeline, &path, false, false)
+            .await
+            .is_err()
+    );
+    let resumed = import_archive(&mut connection, &pipeline, &path, true, false)
+        .await
+        .unwrap();
+    assert!(resumed
+        .results
+        .iter()
+        .all(|result| matches!(result.outcome, ImportOutcome::Skipped(_))));
+
+    let posts: Vec<(i32,)> = sqlx::query_as(
+        "SELECT EXTRACT(YEAR FROM created_at)::int FROM posts WHERE user_uuid = ANY($1)",
+    )
+    .bind(&user_uuids)
+    .fetch_all(&mut connection)
+    .await
+    .unwrap();
+    assert_eq!(posts, vec![(2019,), (2019,)]);
+}
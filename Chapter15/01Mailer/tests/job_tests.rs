@@ -0,0 +1,181 @@
+mod common;
+
+use our_application::models::job::Job;
+use our_application::models::job_status::JobStatus;
+use our_application::models::job_type::JobType;
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::worker::{Message, PublishMessage};
+use our_application::workers::WorkerConfig;
+use sqlx::postgres::PgPool;
+use uuid::Uuid;
+
+// Marks a fresh job as running under `worker_id`, as `Job::claim` would,
+// without claiming whatever else is queued in the test database.
+async fn running_job(pool: &PgPool, worker_id: &str) -> Job {
+    let mut connection = pool.acquire().await.unwrap();
+    let job = Job::enqueue(
+        &mut connection,
+        JobType::PublishPost,
+        &PublishMessage {
+            uuid: Uuid::new_v4().to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    drop(connection);
+    take_over(pool, &job, worker_id).await
+}
+
+async fn take_over(pool: &PgPool, job: &Job, worker_id: &str) -> Job {
+    sqlx::query_as::<_, Job>(
+        r#"UPDATE jobs
+SET status = $1, attempts = attempts + 1, locked_at = NOW(), locked_by = $2
+WHERE uuid = $3
+RETURNING *"#,
+    )
+    .bind(JobStatus::Running)
+    .bind(worker_id)
+    .bind(job.uuid)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+}
+
+async fn reload(pool: &PgPool, job: &Job) -> Job {
+    sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE uuid = $1")
+        .bind(job.uuid)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn pool_leaves_room_for_heartbeats() {
+    for concurrency in 0..8 {
+        let config = WorkerConfig {
+            concurrency,
+            ..WorkerConfig::default()
+        };
+        assert!(config.pool_size() as usize >= concurrency.max(1) * 2 + 2);
+    }
+}
+
+#[rocket::async_test]
+async fn jobs_are_only_finished_by_the_lease_holder() {
+    let pool = common::database().await;
+    let mut connection = pool.acquire().await.unwrap();
+    let stale = running_job(&pool, "worker-a").await;
+    let current = take_over(&pool, &stale, "worker-b").await;
+
+    Job::heartbeat(&mut connection, &stale.uuid, "worker-a")
+        .await
+        .unwrap();
+    assert_eq!(
+        reload(&pool, &stale).await.locked_at.map(|at| at.0),
+        current.locked_at.as_ref().map(|at| at.0)
+    );
+    assert!(!stale.complete(&mut connection).await.unwrap());
+    assert_eq!(
+        stale.fail(&mut connection, "late", 1, 0).await.unwrap(),
+        None
+    );
+    let job = reload(&pool, &stale).await;
+    assert_eq!(job.status, JobStatus::Running);
+    assert_eq!(job.locked_by.as_deref(), Some("worker-b"));
+    assert_eq!(job.last_error, None);
+
+    assert!(current.complete(&mut connection).await.unwrap());
+    assert_eq!(reload(&pool, &current).await.status, JobStatus::Completed);
+}
+
+#[rocket::async_test]
+async fn failing_a_held_job_requeues_it() {
+    let pool = common::database().await;
+    let mut connection = pool.acquire().await.unwrap();
+    let job = running_job(&pool, "worker-a").await;
+    assert_eq!(
+        job.fail(&mut connection, "try again", 5, 0).await.unwrap(),
+        Some(JobStatus::Queued)
+    );
+    let requeued = reload(&pool, &job).await;
+    assert_eq!(requeued.status, JobStatus::Queued);
+    assert_eq!(requeued.locked_by, None);
+    assert_eq!(requeued.last_error.as_deref(), Some("try again"));
+    sqlx::query("DELETE FROM jobs WHERE uuid = $1")
+        .bind(job.uuid)
+        .execute(&pool)
+        .await
+        .unwrap();
+}
+
+async fn video_jobs(pool: &PgPool, post: &Post) -> Vec<Job> {
+    sqlx::query_as::<_, Job>("SELECT * FROM jobs WHERE payload->>'uuid' = $1")
+        .bind(post.uuid.to_string())
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[rocket::async_test]
+async fn retrying_a_post_without_a_dead_job_enqueues_a_fresh_one() {
+    let pool = common::database().await;
+    let storage = common::local_storage();
+    let user = common::create_user(&pool).await;
+    let user_uuid = user.uuid.to_string();
+    let mut connection = pool.acquire().await.unwrap();
+    let post = Post::create(
+        &mut connection,
+        &user_uuid,
+        PostType::Video,
+        "/assets/legacy.mp4",
+        &[],
+        ProcessingStatus::Failed,
+        0,
+        &PostSettings::default(),
+    )
+    .await
+    .unwrap();
+    let post_uuid = post.uuid.to_string();
+
+    let retried = Post::retry_processing(&mut connection, &storage, &user_uuid, &post_uuid)
+        .await
+        .unwrap();
+    assert_eq!(retried.processing_status, ProcessingStatus::Queued);
+    let jobs = video_jobs(&pool, &post).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].job_type, JobType::ProcessVideo);
+    assert_eq!(jobs[0].status, JobStatus::Queued);
+    let wm: Message = jobs[0].parse_payload().unwrap();
+    assert_eq!(wm.orig_filename, "legacy.mp4");
+    assert_ne!(wm.dest_filename, "legacy.mp4");
+
+    // Once that job has died, retrying requeues it instead of adding another.
+    sqlx::query("UPDATE jobs SET status = $1, attempts = 5 WHERE uuid = $2")
+        .bind(JobStatus::Dead)
+        .bind(jobs[0].uuid)
+        .execute(&pool)
+        .await
+        .unwrap();
+    Post::update_processing_status(
+        &mut connection,
+        &post_uuid,
+        ProcessingStatus::Failed,
+        Some("failed"),
+    )
+    .await
+    .unwrap();
+    Post::retry_processing(&mut connection, &storage, &user_uuid, &post_uuid)
+        .await
+        .unwrap();
+    let jobs = video_jobs(&pool, &post).await;
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].status, JobStatus::Queued);
+    assert_eq!(jobs[0].attempts, 0);
+    sqlx::query("DELETE FROM jobs WHERE uuid = $1")
+        .bind(jobs[0].uuid)
+        .execute(&pool)
+        .await
+        .unwrap();
+}
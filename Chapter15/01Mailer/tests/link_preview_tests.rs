@@ -0,0 +1,326 @@
+mod common;
+
+use our_application::media::link_preview::{
+    fetch_image, fetch_preview, find_urls, is_public_ip, linkify, parse_metadata, parse_oembed,
+    LinkPreviewConfig,
+};
+use our_application::models::link_preview::LinkPreview;
+use our_application::models::link_preview_status::LinkPreviewStatus;
+use our_application::models::post::Post;
+use our_application::traits::DisplayPostContent;
+use reqwest::Url;
+use sqlx::types::Json;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::thread;
+
+const ARTICLE: &str = r#"<html><head>
+<title>Fallback title</title>
+<meta property="og:title" content="Rocket &amp; Rust">
+<meta property="og:description" content="Building web apps">
+<meta property="og:image" content="/images/cover.png">
+<meta property="og:site_name" content="Example Blog">
+<meta name="twitter:title" content="Twitter title">
+</head><body></body></html>"#;
+
+fn text_post(content: &str, link_preview: Option<LinkPreview>) -> Post {
+    Post {
+        content: String::from(content),
+        link_url: link_preview.as_ref().map(|preview| preview.url.clone()),
+        link_preview: Json(link_preview),
+        ..common::post()
+    }
+}
+
+fn preview(status: LinkPreviewStatus) -> LinkPreview {
+    LinkPreview {
+        url: String::from("https://example.com/post"),
+        status,
+        title: Some(String::from("<b>Title</b>")),
+        description: Some(String::from("A \"quoted\" description")),
+        image_url: Some(String::from("/assets/previews/cover.png")),
+        site_name: None,
+        source: Some(String::from("opengraph")),
+        error: None,
+        fetched_at: Some(common::created_at()),
+        expires_at: common::created_at(),
+        created_at: common::created_at(),
+    }
+}
+
+fn local_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let base = address.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let base = base.clone();
+            thread::spawn(move || serve(stream.unwrap(), &base));
+        }
+    });
+    address
+}
+
+fn serve(mut stream: TcpStream, base: &str) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+            break;
+        }
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    if path == "/cover.png" {
+        let image = cover_png();
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            image.len()
+        );
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&image).unwrap();
+        return;
+    }
+    let response = match path {
+        "/article" => format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            ARTICLE.len(),
+            ARTICLE
+        ),
+        "/moved" => format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {}/article\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            base
+        ),
+        "/loop" => String::from(
+            "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ),
+        "/image.png" => String::from(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 4\r\nConnection: close\r\n\r\nPNG!",
+        ),
+        _ => String::from(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ),
+    };
+    stream.write_all(response.as_bytes()).unwrap();
+}
+
+fn cover_png() -> Vec<u8> {
+    let mut bytes = vec![];
+    image::RgbImage::new(2, 2)
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+fn private_config() -> LinkPreviewConfig {
+    LinkPreviewConfig {
+        allow_private: true,
+        ..LinkPreviewConfig::default()
+    }
+}
+
+#[test]
+fn finds_urls_without_trailing_punctuation() {
+    let text = "See https://example.com/a?b=1. Also (https://en.wikipedia.org/wiki/Rust_(language)) and http://";
+    assert_eq!(
+        find_urls(text),
+        vec![
+            String::from("https://example.com/a?b=1"),
+            String::from("https://en.wikipedia.org/wiki/Rust_(language)"),
+        ]
+    );
+    assert!(find_urls("no links, ftp://example.com").is_empty());
+}
+
+#[test]
+fn linkify_escapes_text_and_wraps_urls() {
+    let html = linkify("<script>x</script> https://example.com/?a=1&b=2");
+    assert_eq!(
+        html,
+        "&lt;script&gt;x&lt;/script&gt; <a href=\"https://example.com/?a=1&amp;b=2\" rel=\"nofollow noopener ugc\" target=\"_blank\">https://example.com/?a=1&amp;b=2</a>"
+    );
+}
+
+#[test]
+fn private_addresses_are_not_public() {
+    for address in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "255.255.255.255",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "::ffff:10.0.0.1",
+    ]
+    .iter()
+    {
+        let ip: IpAddr = address.parse().unwrap();
+        assert!(!is_public_ip(ip), "{} should be blocked", address);
+    }
+    for address in ["93.184.216.34", "2606:2800:220:1::", "::ffff:93.184.216.34"].iter() {
+        let ip: IpAddr = address.parse().unwrap();
+        assert!(is_public_ip(ip), "{} should be allowed", address);
+    }
+}
+
+#[test]
+fn parses_opengraph_metadata() {
+    let base = Url::parse("https://blog.example.com/posts/1").unwrap();
+    let metadata = parse_metadata(ARTICLE, &base);
+    assert_eq!(metadata.title.as_deref(), Some("Rocket & Rust"));
+    assert_eq!(metadata.description.as_deref(), Some("Building web apps"));
+    assert_eq!(
+        metadata.image_url.as_deref(),
+        Some("https://blog.example.com/images/cover.png")
+    );
+    assert_eq!(metadata.site_name.as_deref(), Some("Example Blog"));
+    assert_eq!(metadata.source, "opengraph");
+}
+
+#[test]
+fn falls_back_to_twitter_cards_and_title() {
+    let base = Url::parse("https://example.com/").unwrap();
+    let twitter = r#"<meta name="twitter:title" content="Card"><meta name="twitter:image" content="javascript:alert(1)">"#;
+    let metadata = parse_metadata(twitter, &base);
+    assert_eq!(metadata.title.as_deref(), Some("Card"));
+    assert_eq!(metadata.image_url, None);
+    assert_eq!(metadata.source, "twitter");
+
+    let plain = r#"<html><head><title> Plain page </title><meta name="description" content="About"><link rel="alternate" type="application/json+oembed" href="/oembed?url=x"></head></html>"#;
+    let metadata = parse_metadata(plain, &base);
+    assert_eq!(metadata.title.as_deref(), Some("Plain page"));
+    assert_eq!(metadata.description.as_deref(), Some("About"));
+    assert_eq!(
+        metadata.oembed_url.as_deref(),
+        Some("https://example.com/oembed?url=x")
+    );
+    assert_eq!(metadata.source, "html");
+}
+
+#[test]
+fn parses_oembed_responses() {
+    let body = br#"{"type":"video","title":"A video","author_name":"Someone","provider_name":"VideoSite","thumbnail_url":"https://videos.example.com/t.jpg","html":"<iframe></iframe>"}"#;
+    let metadata = parse_oembed(body).unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("A video"));
+    assert_eq!(metadata.site_name.as_deref(), Some("VideoSite"));
+    assert_eq!(
+        metadata.image_url.as_deref(),
+        Some("https://videos.example.com/t.jpg")
+    );
+    assert_eq!(metadata.source, "oembed");
+    assert!(parse_oembed(b"not json").is_err());
+}
+
+#[test]
+fn text_posts_render_links_and_ready_previews() {
+    let post = text_post("Read https://example.com/post", None);
+    let html = post.to_text().raw_html();
+    assert!(html.contains("<a href=\"https://example.com/post\""));
+    assert!(!html.contains("link-preview"));
+
+    let post = text_post(
+        "Read https://example.com/post",
+        Some(preview(LinkPreviewStatus::Ready)),
+    );
+    let html = post.to_text().raw_html();
+    assert!(html.contains("class=\"card fluid link-preview\""));
+    assert!(html.contains("&lt;b&gt;Title&lt;/b&gt;<small>example.com</small>"));
+    assert!(html.contains("A &quot;quoted&quot; description"));
+    assert!(html.contains("src=\"/assets/previews/cover.png\""));
+
+    let post = text_post(
+        "Read https://example.com/post",
+        Some(preview(LinkPreviewStatus::Failed)),
+    );
+    assert!(!post.to_text().raw_html().contains("link-preview"));
+}
+
+#[rocket::async_test]
+async fn fetch_blocks_private_addresses() {
+    let server = local_server();
+    let err = fetch_preview(
+        &LinkPreviewConfig::default(),
+        &format!("{}/article", server),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.permanent);
+    assert!(err.message.contains("private address"));
+}
+
+#[rocket::async_test]
+async fn fetch_follows_redirects_and_reads_metadata() {
+    let server = local_server();
+    let metadata = fetch_preview(&private_config(), &format!("{}/moved", server))
+        .await
+        .unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("Rocket & Rust"));
+    assert_eq!(
+        metadata.image_url,
+        Some(format!("{}/images/cover.png", server))
+    );
+}
+
+#[rocket::async_test]
+async fn fetch_rejects_loops_non_html_and_missing_pages() {
+    let server = local_server();
+    let config = private_config();
+    let err = fetch_preview(&config, &format!("{}/loop", server))
+        .await
+        .unwrap_err();
+    assert!(err.permanent);
+    assert!(err.message.contains("Too many redirects"));
+
+    let err = fetch_preview(&config, &format!("{}/image.png", server))
+        .await
+        .unwrap_err();
+    assert!(err.permanent);
+    assert!(err.message.contains("not a supported page"));
+
+    let err = fetch_preview(&config, &format!("{}/missing", server))
+        .await
+        .unwrap_err();
+    assert!(err.permanent);
+    assert!(err.message.contains("404"));
+}
+
+#[rocket::async_test]
+async fn fetch_image_checks_address_size_and_format() {
+    let server = local_server();
+    let url = format!("{}/cover.png", server);
+    let err = fetch_image(&LinkPreviewConfig::default(), &url)
+        .await
+        .unwrap_err();
+    assert!(err.message.contains("private address"));
+
+    let (bytes, extension) = fetch_image(&private_config(), &url).await.unwrap();
+    assert_eq!(bytes, cover_png());
+    assert_eq!(extension, "png");
+
+    let config = LinkPreviewConfig {
+        max_image_size: 16,
+        ..private_config()
+    };
+    let err = fetch_image(&config, &url).await.unwrap_err();
+    assert!(err.permanent);
+    assert!(err.message.contains("larger than 16 bytes"));
+
+    let err = fetch_image(&private_config(), &format!("{}/image.png", server))
+        .await
+        .unwrap_err();
+    assert!(err.message.contains("not a supported image"));
+
+    let err = fetch_image(&private_config(), &format!("{}/article", server))
+        .await
+        .unwrap_err();
+    assert!(err.message.contains("not a supported"));
+}
@@ -0,0 +1,248 @@
+mod common;
+
+use our_application::events::{notify_mentions, Event, EventHub};
+use our_application::fairings::db::DBConnection;
+use our_application::guards::auth::{CurrentUser, LOGIN_COOKIE_NAME};
+use our_application::models::notification::find_mentions;
+use our_application::models::notification_type::NotificationType;
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::user::User;
+use our_application::models::visibility::Visibility;
+use our_application::routes::api;
+use our_application::states::JWToken;
+use rocket::http::{ContentType, Cookie, Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::serde::json::{serde_json, Value};
+use rocket_db_pools::Database;
+use sqlx::postgres::PgPool;
+use uuid::Uuid;
+
+async fn create_post(pool: &PgPool, user: &User, content: &str, visibility: Visibility) -> Post {
+    let mut connection = pool.acquire().await.unwrap();
+    Post::create(
+        &mut connection,
+        &user.uuid.to_string(),
+        PostType::Text,
+        content,
+        &[],
+        ProcessingStatus::Ready,
+        0,
+        &PostSettings::new(visibility, PublishStatus::Published, None).unwrap(),
+    )
+    .await
+    .unwrap()
+}
+
+async fn mention(pool: &PgPool, hub: &EventHub, author: &User, mentioned: &User) {
+    let content = format!("hello @{}", mentioned.username);
+    let post = create_post(pool, author, &content, Visibility::Public).await;
+    let mut connection = pool.acquire().await.unwrap();
+    notify_mentions(&mut connection, hub, &post).await;
+}
+
+// The unread badge in the page header renders this count.
+#[rocket::get("/unread")]
+fn unread(current_user: CurrentUser) -> String {
+    current_user.unread_notifications.to_string()
+}
+
+async fn notification_client() -> Client {
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(JWToken {
+            secret: String::from("notification-tests"),
+        })
+        .mount("/", rocket::routes![unread])
+        .mount(
+            "/api",
+            rocket::routes![
+                api::login,
+                api::notifications,
+                api::unread_notifications_count,
+                api::read_notification,
+                api::read_all_notifications,
+            ],
+        );
+    Client::tracked(rocket).await.unwrap()
+}
+
+async fn api_token(client: &Client, user: &User) -> String {
+    let response = client
+        .post("/api/login")
+        .header(ContentType::JSON)
+        .body(
+            serde_json::json!({
+                "username": user.username,
+                "password": "Passw0rd!Passw0rd",
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.unwrap();
+    String::from(body["token"].as_str().unwrap())
+}
+
+async fn api_get(client: &Client, token: &str, path: &str) -> Value {
+    let response = client
+        .get(path.to_string())
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", token.to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+async fn api_post(client: &Client, token: &str, path: &str) -> (Status, Option<Value>) {
+    let response = client
+        .post(path.to_string())
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", token.to_string()))
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await)
+}
+
+async fn unread_badge(client: &Client, user: &User) -> String {
+    let response = client
+        .get("/unread")
+        .private_cookie(Cookie::new(LOGIN_COOKIE_NAME, user.uuid.to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_string().await.unwrap()
+}
+
+#[test]
+fn mentions_are_found_once_per_username() {
+    assert_eq!(
+        find_mentions("@alice, meet @bob_2. Thanks @alice!"),
+        vec![String::from("alice"), String::from("bob_2")]
+    );
+    assert!(find_mentions("mail me at alice@example.com or @@bob").is_empty());
+    assert!(find_mentions("no mentions here @").is_empty());
+}
+
+#[rocket::async_test]
+async fn mentions_notify_users_who_can_see_the_post() {
+    let pool = common::database().await;
+    let hub = EventHub::new();
+    let mut events = hub.subscribe();
+    let author = common::create_user(&pool).await;
+    let mentioned = common::create_user(&pool).await;
+
+    let content = format!("hello @{} and @{}", mentioned.username, author.username);
+    let post = create_post(&pool, &author, &content, Visibility::Public).await;
+    let mut connection = pool.acquire().await.unwrap();
+    notify_mentions(&mut connection, &hub, &post).await;
+
+    let rows: Vec<(Uuid, Option<Uuid>, NotificationType, Option<String>)> = sqlx::query_as(
+        "SELECT user_uuid, actor_uuid, notification_type, link FROM notifications WHERE actor_uuid = $1",
+    )
+    .bind(author.uuid)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        rows,
+        vec![(
+            mentioned.uuid,
+            Some(author.uuid),
+            NotificationType::Mention,
+            Some(format!("/users/{}/posts/{}", author.uuid, post.uuid)),
+        )]
+    );
+    match events.try_recv().unwrap() {
+        Event::Notification {
+            user_uuid,
+            message,
+            unread_count,
+            ..
+        } => {
+            assert_eq!(user_uuid, mentioned.uuid);
+            assert_eq!(
+                message,
+                format!("{} mentioned you in a post", author.username)
+            );
+            assert_eq!(unread_count, 1);
+        }
+        event => panic!("unexpected event {}", event.name()),
+    }
+    assert!(events.try_recv().is_err());
+
+    for visibility in [Visibility::Private, Visibility::Followers] {
+        let content = format!("secret @{}", mentioned.username);
+        let post = create_post(&pool, &author, &content, visibility).await;
+        notify_mentions(&mut connection, &hub, &post).await;
+    }
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM notifications WHERE user_uuid = $1")
+            .bind(mentioned.uuid)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(count, 1);
+    assert!(events.try_recv().is_err());
+}
+
+#[rocket::async_test]
+async fn notifications_are_listed_and_marked_read() {
+    let pool = common::database().await;
+    let hub = EventHub::new();
+    let author = common::create_user(&pool).await;
+    let reader = common::create_user(&pool).await;
+    mention(&pool, &hub, &author, &reader).await;
+    mention(&pool, &hub, &author, &reader).await;
+    mention(&pool, &hub, &reader, &author).await;
+
+    let client = notification_client().await;
+    let token = api_token(&client, &reader).await;
+    assert_eq!(unread_badge(&client, &reader).await, "2");
+
+    let listed = api_get(&client, &token, "/api/notifications").await;
+    let notifications = listed["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 2);
+    assert_eq!(listed["unread_count"], 2);
+    assert!(notifications
+        .iter()
+        .all(|n| n["user_uuid"] == reader.uuid.to_string() && n["read_at"].is_null()));
+
+    let first = notifications[0]["uuid"].as_str().unwrap();
+    let (status, read) = api_post(
+        &client,
+        &token,
+        &format!("/api/notifications/{}/read", first),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert!(!read.unwrap()["read_at"].is_null());
+    let unread = api_get(&client, &token, "/api/notifications/unread_count").await;
+    assert_eq!(unread["unread_count"], 1);
+    assert_eq!(unread_badge(&client, &reader).await, "1");
+
+    // Another user's notification cannot be marked read.
+    let (others,): (Uuid,) = sqlx::query_as("SELECT uuid FROM notifications WHERE user_uuid = $1")
+        .bind(author.uuid)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let (_, error) = api_post(
+        &client,
+        &token,
+        &format!("/api/notifications/{}/read", others),
+    )
+    .await;
+    assert_eq!(error.unwrap()["status"], 404);
+
+    let (status, _) = api_post(&client, &token, "/api/notifications/read_all").await;
+    assert_eq!(status, Status::Ok);
+    let unread = api_get(&client, &token, "/api/notifications/unread_count").await;
+    assert_eq!(unread["unread_count"], 0);
+    assert_eq!(unread_badge(&client, &reader).await, "0");
+    assert_eq!(unread_badge(&client, &author).await, "1");
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use common::workdir;
+use exif::{In, Tag};
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageOutputFormat, Rgb, Rgba};
+use our_application::media::photo::{
+    apply_orientation, derivative_sizes, orientation, process_photo, read_exif, PhotoConfig,
+};
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgb([(x % 256) as u8, (y % 256) as u8, 128])
+    });
+    let mut bytes = vec![];
+    image::DynamicImage::ImageRgb8(image)
+        .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
+
+#[test]
+fn derivatives_never_upscale() {
+    let config = PhotoConfig::default();
+    let widths: Vec<u32> = derivative_sizes(&config, 2000)
+        .iter()
+        .map(|s| s.width)
+        .collect();
+    assert_eq!(widths, vec![320, 800, 1600]);
+    let sizes = derivative_sizes(&config, 500);
+    let labels: Vec<&str> = sizes.iter().map(|s| s.label.as_str()).collect();
+    assert_eq!(labels, vec!["thumbnail", "medium"]);
+    assert_eq!(sizes[1].width, 500);
+}
+
+#[test]
+fn process_photo_writes_every_size_and_format() {
+    let dir = workdir();
+    let config = PhotoConfig::default();
+    let processed = process_photo(&config, &png(1000, 500), &dir, "abc").unwrap();
+
+    assert_eq!(processed.content, "abc_large.jpg");
+    assert_eq!(processed.variants.len(), 9);
+    let thumbnail = &processed.variants[0];
+    assert_eq!(thumbnail.path, "abc_thumbnail.jpg");
+    assert_eq!((thumbnail.width, thumbnail.height), (Some(320), Some(160)));
+    let large = processed.variants.last().unwrap();
+    assert_eq!(large.mime, "image/avif");
+    assert_eq!((large.width, large.height), (Some(1000), Some(500)));
+    for variant in processed.variants.iter() {
+        assert!(dir.join(&variant.path).exists());
+    }
+}
+
+#[test]
+fn process_photo_keeps_transparency_where_supported() {
+    let dir = workdir();
+    let image = ImageBuffer::from_fn(64, 64, |x, _| {
+        if x < 32 {
+            Rgba([0, 0, 0, 0])
+        } else {
+            Rgba([200, 0, 0, 255])
+        }
+    });
+    let mut bytes = vec![];
+    DynamicImage::ImageRgba8(image)
+        .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+        .unwrap();
+    process_photo(&PhotoConfig::default(), &bytes, &dir, "alpha").unwrap();
+
+    let jpeg = image::open(dir.join("alpha_thumbnail.jpg")).unwrap();
+    let background = jpeg.get_pixel(4, 4);
+    assert!(background.0[..3].iter().all(|channel| *channel > 240));
+    let webp = image::open(dir.join("alpha_thumbnail.webp")).unwrap();
+    assert!(webp.color().has_alpha());
+    assert_eq!(webp.get_pixel(4, 4).0[3], 0);
+}
+
+#[test]
+fn process_photo_rejects_garbage() {
+    let dir = workdir();
+    let config = PhotoConfig::default();
+    assert!(process_photo(&config, b"not an image", &dir, "bad").is_err());
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name),
+    )
+    .unwrap()
+}
+
+#[test]
+fn fixture_orientation_is_applied() {
+    let bytes = fixture("rotated_gps.jpg");
+    let exif = read_exif(&bytes).unwrap();
+    assert_eq!(orientation(&exif), 6);
+
+    let dir = workdir();
+    let config = PhotoConfig::default();
+    let processed = process_photo(&config, &bytes, &dir, "rotated").unwrap();
+    let jpeg = &processed.variants[0];
+    assert_eq!((jpeg.width, jpeg.height), (Some(20), Some(40)));
+
+    let output = image::open(dir.join("rotated_thumbnail.jpg"))
+        .unwrap()
+        .to_rgb8();
+    assert_eq!((output.width(), output.height()), (20, 40));
+    let top = output.get_pixel(10, 5);
+    let bottom = output.get_pixel(10, 35);
+    assert!(
+        top[0] > 200 && top[2] < 60,
+        "top should be red, got {:?}",
+        top
+    );
+    assert!(
+        bottom[2] > 200 && bottom[0] < 60,
+        "bottom should be blue, got {:?}",
+        bottom
+    );
+}
+
+#[test]
+fn fixture_metadata_is_stripped() {
+    let dir = workdir();
+    let config = PhotoConfig::default();
+    process_photo(&config, &fixture("rotated_gps.jpg"), &dir, "stripped").unwrap();
+    for entry in fs::read_dir(&dir).unwrap() {
+        let bytes = fs::read(entry.unwrap().path()).unwrap();
+        assert!(read_exif(&bytes).is_none());
+    }
+}
+
+#[test]
+fn fixture_keeps_camera_info_when_requested() {
+    let dir = workdir();
+    let config = PhotoConfig {
+        keep_camera_info: true,
+        ..PhotoConfig::default()
+    };
+    process_photo(&config, &fixture("rotated_gps.jpg"), &dir, "camera").unwrap();
+    let exif = read_exif(&fs::read(dir.join("camera_thumbnail.jpg")).unwrap()).unwrap();
+    let model = exif.get_field(Tag::Model, In::PRIMARY).unwrap();
+    assert_eq!(model.display_value().to_string(), "\"Phone 3000\"");
+    assert!(exif.get_field(Tag::Make, In::PRIMARY).is_some());
+    assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+    assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_none());
+    assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_none());
+}
+
+#[test]
+fn orientation_transforms() {
+    let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(3, 2, |x, y| {
+        Rgb([x as u8, y as u8, 0])
+    }));
+    let expected = [
+        (1, 3, 2),
+        (2, 3, 2),
+        (3, 3, 2),
+        (4, 3, 2),
+        (5, 2, 3),
+        (6, 2, 3),
+        (7, 2, 3),
+        (8, 2, 3),
+    ];
+    for (value, width, height) in expected.iter() {
+        let oriented = apply_orientation(image.clone(), *value);
+        assert_eq!((oriented.width(), oriented.height()), (*width, *height));
+    }
+    let mirrored = apply_orientation(image.clone(), 2).to_rgb8();
+    assert_eq!(mirrored.get_pixel(0, 0)[0], 2);
+    let rotated = apply_orientation(image, 8).to_rgb8();
+    assert_eq!(rotated.get_pixel(0, 0), &Rgb([2, 0, 0]));
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::quota::{format_bytes, Quota, QuotaConfig, MEGABYTE};
+use our_application::models::user::User;
+use rocket::http::Status;
+use sqlx::Acquire;
+use std::time::Duration;
+
+fn user(storage_quota: Option<i64>, daily_post_limit: Option<i32>) -> User {
+    User {
+        storage_quota,
+        daily_post_limit,
+        ..common::user()
+    }
+}
+
+fn config() -> QuotaConfig {
+    QuotaConfig {
+        storage: 10 * MEGABYTE as u64,
+        posts_per_day: 5,
+        album_items: 10,
+    }
+}
+
+#[test]
+fn uploads_within_quota_are_accepted() {
+    let quota = Quota::new(&config(), &user(None, None), 4 * MEGABYTE, 4);
+    assert!(quota.check(6 * MEGABYTE as u64).is_ok());
+}
+
+#[test]
+fn uploads_over_storage_quota_are_too_large() {
+    let quota = Quota::new(&config(), &user(None, None), 9 * MEGABYTE, 0);
+    let error = quota.check(2 * MEGABYTE as u64).unwrap_err();
+    assert_eq!(error.status, Status::PayloadTooLarge);
+    assert_eq!(
+        error.message,
+        "Upload exceeds your storage quota (9.0 MiB of 10.0 MiB used)"
+    );
+}
+
+#[test]
+fn daily_post_limit_is_rate_limited() {
+    let quota = Quota::new(&config(), &user(None, None), 0, 5);
+    let error = quota.check(0).unwrap_err();
+    assert_eq!(error.status, Status::TooManyRequests);
+    assert!(error.message.contains("5 posts per day"));
+}
+
+#[test]
+fn admin_overrides_replace_configured_limits() {
+    let quota = Quota::new(
+        &config(),
+        &user(Some(100 * MEGABYTE), Some(50)),
+        50 * MEGABYTE,
+        20,
+    );
+    assert!(quota.check(20 * MEGABYTE as u64).is_ok());
+    let context = quota.to_context();
+    assert_eq!(context.storage_percent, 50);
+    assert_eq!(context.storage_quota_mb, Some(100));
+    assert_eq!(context.daily_post_limit, Some(50));
+
+    let unlimited = Quota::new(&config(), &user(Some(0), None), 0, 0);
+    assert_eq!(
+        unlimited.check(1).unwrap_err().status,
+        Status::PayloadTooLarge
+    );
+    assert_eq!(unlimited.to_context().storage_percent, 100);
+}
+
+#[test]
+fn bytes_are_formatted_for_display() {
+    assert_eq!(format_bytes(512), "512 B");
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+    assert_eq!(format_bytes(1024 * MEGABYTE), "1.0 GiB");
+}
+
+#[rocket::async_test]
+async fn concurrent_quota_checks_see_each_others_posts() {
+    let pool = common::database().await;
+    let user = common::create_user(&pool).await;
+    let config = config();
+    let mut first = pool.acquire().await.unwrap();
+    let mut second = pool.acquire().await.unwrap();
+
+    let mut transaction = first.begin().await.unwrap();
+    let quota = Quota::find_for_update(&mut transaction, &config, &user)
+        .await
+        .unwrap();
+    assert_eq!(quota.storage_used, 0);
+    let insert = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        Post::create(
+            &mut transaction,
+            &user.uuid.to_string(),
+            PostType::Photo,
+            "/assets/photo.jpg",
+            &[],
+            ProcessingStatus::Ready,
+            MEGABYTE,
+            &PostSettings::default(),
+        )
+        .await
+        .unwrap();
+        transaction.commit().await.unwrap();
+    };
+    let (_, quota) = tokio::join!(insert, Quota::find_for_update(&mut second, &config, &user));
+    let quota = quota.unwrap();
+    assert_eq!(quota.storage_used, MEGABYTE);
+    assert_eq!(quota.posts_today, 1);
+}
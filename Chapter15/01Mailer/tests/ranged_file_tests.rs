@@ -0,0 +1,145 @@
+use our_application::responders::ranged_file::{ByteRange, RangedFile};
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use std::path::PathBuf;
+
+const CONTENT: &[u8] = b"0123456789abcdefghij";
+
+#[rocket::get("/media")]
+async fn media() -> Option<RangedFile> {
+    RangedFile::open(media_path()).await.ok()
+}
+
+fn media_path() -> PathBuf {
+    std::env::temp_dir().join("ranged-file-tests.mp4")
+}
+
+async fn client() -> Client {
+    std::fs::write(media_path(), CONTENT).unwrap();
+    let rocket = rocket::build().mount("/", rocket::routes![media]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[test]
+fn parse_byte_ranges() {
+    assert_eq!(ByteRange::parse("bytes=0-4", 20), ByteRange::Partial(0, 4));
+    assert_eq!(
+        ByteRange::parse("bytes=15-", 20),
+        ByteRange::Partial(15, 19)
+    );
+    assert_eq!(ByteRange::parse("bytes=-5", 20), ByteRange::Partial(15, 19));
+    assert_eq!(ByteRange::parse("bytes=-50", 20), ByteRange::Partial(0, 19));
+    assert_eq!(
+        ByteRange::parse("bytes=10-99", 20),
+        ByteRange::Partial(10, 19)
+    );
+    assert_eq!(ByteRange::parse("bytes=20-", 20), ByteRange::Unsatisfiable);
+    assert_eq!(ByteRange::parse("bytes=-0", 20), ByteRange::Unsatisfiable);
+    assert_eq!(ByteRange::parse("bytes=0-", 0), ByteRange::Unsatisfiable);
+    assert_eq!(ByteRange::parse("bytes=0-1,5-6", 20), ByteRange::Full);
+}
+
+#[test]
+fn invalid_byte_ranges_are_ignored() {
+    for header in [
+        "bytes=abc",
+        "bytes=5",
+        "bytes=5-1",
+        "bytes=a-3",
+        "bytes=1-b",
+        "bytes=+1-3",
+        "bytes=-",
+        "items=0-1",
+        "0-1",
+    ] {
+        assert_eq!(ByteRange::parse(header, 20), ByteRange::Full, "{}", header);
+    }
+}
+
+#[rocket::async_test]
+async fn full_response_advertises_ranges() {
+    let client = client().await;
+    let response = client.get("/media").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Accept-Ranges"), Some("bytes"));
+    assert_eq!(
+        response.headers().get_one("Content-Type"),
+        Some("video/mp4")
+    );
+    assert!(response.headers().get_one("ETag").is_some());
+    assert_eq!(response.into_bytes().await.unwrap(), CONTENT);
+}
+
+#[rocket::async_test]
+async fn partial_content() {
+    let client = client().await;
+    let response = client
+        .get("/media")
+        .header(Header::new("Range", "bytes=10-14"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::PartialContent);
+    assert_eq!(
+        response.headers().get_one("Content-Range"),
+        Some("bytes 10-14/20")
+    );
+    assert_eq!(response.into_bytes().await.unwrap(), b"abcde");
+}
+
+#[rocket::async_test]
+async fn unsatisfiable_range() {
+    let client = client().await;
+    let response = client
+        .get("/media")
+        .header(Header::new("Range", "bytes=30-40"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::RangeNotSatisfiable);
+    assert_eq!(
+        response.headers().get_one("Content-Range"),
+        Some("bytes */20")
+    );
+}
+
+#[rocket::async_test]
+async fn invalid_range_returns_full_content() {
+    let client = client().await;
+    let response = client
+        .get("/media")
+        .header(Header::new("Range", "bytes=abc"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Range"), None);
+    assert_eq!(response.into_bytes().await.unwrap(), CONTENT);
+}
+
+#[rocket::async_test]
+async fn stale_if_range_returns_full_content() {
+    let client = client().await;
+    let etag = client
+        .get("/media")
+        .dispatch()
+        .await
+        .headers()
+        .get_one("ETag")
+        .map(String::from)
+        .unwrap();
+
+    let response = client
+        .get("/media")
+        .header(Header::new("Range", "bytes=0-1"))
+        .header(Header::new("If-Range", etag))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::PartialContent);
+
+    let response = client
+        .get("/media")
+        .header(Header::new("Range", "bytes=0-1"))
+        .header(Header::new("If-Range", "\"stale\""))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_bytes().await.unwrap(), CONTENT);
+}
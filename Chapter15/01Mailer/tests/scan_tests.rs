@@ -0,0 +1,163 @@
+mod common;
+
+use common::workdir;
+use our_application::media::scan::{
+    check_upload, parse_reply, quarantine, scan_file, ScanConfig, ScanResult,
+};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+const EICAR: &[u8] = b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+fn read_instream<S: Read>(stream: &mut S) -> Vec<u8> {
+    let mut command = [0; 10];
+    stream.read_exact(&mut command).unwrap();
+    assert_eq!(&command, b"zINSTREAM\0");
+    let mut received = vec![];
+    loop {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length).unwrap();
+        let length = u32::from_be_bytes(length) as usize;
+        if length == 0 {
+            return received;
+        }
+        let mut chunk = vec![0; length];
+        stream.read_exact(&mut chunk).unwrap();
+        received.extend(chunk);
+    }
+}
+
+fn verdict(received: &[u8]) -> &'static [u8] {
+    if received.windows(EICAR.len()).any(|window| window == EICAR) {
+        b"stream: Eicar-Test-Signature FOUND\0"
+    } else {
+        b"stream: OK\0"
+    }
+}
+
+fn fake_clamd() -> (String, Receiver<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let received = read_instream(&mut stream);
+            stream.write_all(verdict(&received)).unwrap();
+            let _ = sender.send(received);
+        }
+    });
+    (address, receiver)
+}
+
+fn config(address: &str) -> ScanConfig {
+    ScanConfig {
+        enabled: true,
+        address: String::from(address),
+        timeout: 5,
+        quarantine_dir: workdir().to_string_lossy().to_string(),
+        ..ScanConfig::default()
+    }
+}
+
+fn upload(bytes: &[u8]) -> PathBuf {
+    let path = workdir().join("upload.bin");
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn scanner_replies_are_parsed() {
+    assert_eq!(parse_reply("stream: OK\0"), Ok(ScanResult::Clean));
+    assert_eq!(
+        parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0"),
+        Ok(ScanResult::Infected(String::from("Win.Test.EICAR_HDB-1")))
+    );
+    assert!(parse_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+}
+
+#[rocket::async_test]
+async fn clean_files_are_streamed_in_chunks() {
+    let (address, received) = fake_clamd();
+    let bytes: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let path = upload(&bytes);
+
+    let result = scan_file(&config(&address), &path).await.unwrap();
+    assert_eq!(result, ScanResult::Clean);
+    assert_eq!(received.recv().unwrap(), bytes);
+}
+
+#[rocket::async_test]
+async fn infected_files_are_reported_and_quarantined() {
+    let (address, _) = fake_clamd();
+    let config = config(&format!("tcp://{}", address));
+    let path = upload(EICAR);
+
+    let result = check_upload(&config, &path).await.unwrap();
+    assert_eq!(
+        result,
+        ScanResult::Infected(String::from("Eicar-Test-Signature"))
+    );
+    let quarantined = quarantine(&config, &path, "infected.txt").await.unwrap();
+    assert!(!path.exists());
+    assert_eq!(std::fs::read(&quarantined).unwrap(), EICAR);
+    assert!(quarantined.starts_with(&config.quarantine_dir));
+}
+
+#[rocket::async_test]
+async fn unix_socket_scanners_are_supported() {
+    let socket = workdir().join("clamd.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let received = read_instream(&mut stream);
+        stream.write_all(verdict(&received)).unwrap();
+    });
+    let config = config(&format!("unix:{}", socket.display()));
+
+    let result = scan_file(&config, &upload(EICAR)).await.unwrap();
+    assert!(matches!(result, ScanResult::Infected(_)));
+}
+
+#[rocket::async_test]
+async fn unreachable_scanner_fails_closed_unless_configured_open() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let path = upload(b"hello");
+
+    let closed = config(&address);
+    assert!(check_upload(&closed, &path).await.is_err());
+    let open = ScanConfig {
+        fail_open: true,
+        ..config(&address)
+    };
+    assert_eq!(check_upload(&open, &path).await, Ok(ScanResult::Clean));
+    let disabled = ScanConfig {
+        enabled: false,
+        ..config(&address)
+    };
+    assert_eq!(check_upload(&disabled, &path).await, Ok(ScanResult::Clean));
+}
+
+#[rocket::async_test]
+async fn slow_scanner_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        thread::sleep(std::time::Duration::from_secs(3));
+        drop(stream);
+    });
+    let config = ScanConfig {
+        timeout: 1,
+        ..config(&address)
+    };
+
+    let error = scan_file(&config, &upload(b"hello")).await.unwrap_err();
+    assert!(error.contains("did not answer"));
+}
@@ -0,0 +1,89 @@
+use our_application::media::svg::{rasterize, sanitize};
+
+const HEADER: &str =
+    r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink">"#;
+
+fn wrap(body: &str) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="10" height="10">{}</svg>"#,
+        body
+    )
+}
+
+#[test]
+fn keeps_safe_drawing() {
+    let svg = wrap(
+        r##"<defs><linearGradient id="g"><stop offset="0" stop-color="red"/></linearGradient></defs><rect width="10" height="10" fill="url(#g)"/><text x="1" y="5">Hi &amp; bye</text>"##,
+    );
+    let sanitized = sanitize(&svg).unwrap();
+    assert!(sanitized.starts_with(HEADER.trim_end_matches('>')));
+    assert!(sanitized.contains(r##"<rect width="10" height="10" fill="url(#g)"></rect>"##));
+    assert!(sanitized.contains("<text x=\"1\" y=\"5\">Hi &amp; bye</text>"));
+}
+
+#[test]
+fn removes_scripts_and_event_handlers() {
+    let svg =
+        wrap(r#"<script>alert(1)</script><rect onload="alert(1)" onclick="alert(2)" width="1"/>"#);
+    let sanitized = sanitize(&svg).unwrap();
+    assert!(!sanitized.contains("script"));
+    assert!(!sanitized.contains("alert"));
+    assert!(sanitized.contains(r#"<rect width="1"></rect>"#));
+}
+
+#[test]
+fn removes_foreign_objects_and_other_namespaces() {
+    let svg = wrap(
+        r#"<foreignObject><div xmlns="http://www.w3.org/1999/xhtml"><iframe src="https://evil.example"/></div></foreignObject><html:p xmlns:html="http://www.w3.org/1999/xhtml">x</html:p>"#,
+    );
+    let sanitized = sanitize(&svg).unwrap();
+    assert!(!sanitized.contains("foreignObject"));
+    assert!(!sanitized.contains("iframe"));
+    assert!(!sanitized.contains("<p"));
+}
+
+#[test]
+fn removes_external_references_and_javascript_urls() {
+    let svg = wrap(concat!(
+        r#"<a href="javascript:alert(1)"><circle r="1"/></a>"#,
+        r#"<use xlink:href="https://evil.example/sprite.svg#icon"/>"#,
+        r##"<use href="#local"/>"##,
+        r#"<image href="http://evil.example/track.png"/>"#,
+        r#"<image xlink:href="data:image/png;base64,AAAA"/>"#,
+        r##"<rect fill="url(https://evil.example/x)" style="background:url(//evil.example)" filter="url(#f)"/>"##,
+        r#"<style>@import url(https://evil.example/x.css);</style>"#,
+    ));
+    let sanitized = sanitize(&svg).unwrap();
+    assert!(!sanitized.contains("javascript"));
+    assert!(!sanitized.contains("evil.example"));
+    assert!(!sanitized.contains("<style"));
+    assert!(sanitized.contains(r#"<circle r="1"></circle>"#));
+    assert!(sanitized.contains(r##"<use href="#local"></use>"##));
+    assert!(sanitized.contains(r#"<image xlink:href="data:image/png;base64,AAAA"></image>"#));
+    assert!(sanitized.contains(r##"<rect filter="url(#f)"></rect>"##));
+}
+
+#[test]
+fn removes_metadata_and_comments() {
+    let svg = wrap("<!-- exported by Editor --><metadata><rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">GPS</rdf:RDF></metadata><rect width=\"1\"/>");
+    let sanitized = sanitize(&svg).unwrap();
+    assert!(!sanitized.contains("metadata"));
+    assert!(!sanitized.contains("exported"));
+}
+
+#[test]
+fn rejects_non_svg_and_entities() {
+    assert!(sanitize("<html><body/></html>").is_err());
+    assert!(sanitize("not xml").is_err());
+    let entities = r#"<?xml version="1.0"?><!DOCTYPE svg [<!ENTITY a "aaaaaaaa">]><svg xmlns="http://www.w3.org/2000/svg">&a;</svg>"#;
+    assert!(sanitize(entities).is_err());
+}
+
+#[test]
+fn rasterizes_to_png() {
+    let sanitized = sanitize(&wrap(r#"<rect width="10" height="10" fill="red"/>"#)).unwrap();
+    let png = rasterize(&sanitized, 1600).unwrap();
+    let image = image::load_from_memory(&png).unwrap().to_rgba8();
+    assert_eq!((image.width(), image.height()), (10, 10));
+    assert_eq!(image.get_pixel(5, 5).0, [255, 0, 0, 255]);
+}
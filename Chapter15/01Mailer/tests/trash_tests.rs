@@ -0,0 +1,240 @@
+mod common;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use chrono::{offset::Utc, Duration, TimeZone};
+use our_application::fairings::db::DBConnection;
+use our_application::models::our_date_time::OurDateTime;
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::user::User;
+use our_application::models::visibility::Visibility;
+use our_application::routes::{api, asset};
+use our_application::workers::trash::TrashConfig;
+use rocket::http::{Accept, ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::serde::json::{serde_json, Value};
+use rocket_db_pools::Database;
+use sqlx::PgConnection;
+
+fn deleted_at() -> OurDateTime {
+    OurDateTime(Utc.with_ymd_and_hms(2022, 5, 28, 12, 0, 0).unwrap())
+}
+
+#[test]
+fn purges_after_the_retention_period() {
+    let config = TrashConfig::default();
+    assert_eq!(config.retention, 30 * 86400);
+    assert_eq!(
+        config.purge_at(&deleted_at()).0,
+        Utc.with_ymd_and_hms(2022, 6, 27, 12, 0, 0).unwrap()
+    );
+
+    let config = TrashConfig {
+        retention: 3600,
+        ..TrashConfig::default()
+    };
+    assert_eq!(
+        config.purge_at(&deleted_at()).0,
+        Utc.with_ymd_and_hms(2022, 5, 28, 13, 0, 0).unwrap()
+    );
+}
+
+#[test]
+fn accounts_use_their_own_grace_period() {
+    let config = TrashConfig::default();
+    assert_eq!(
+        config.account_purge_at(&deleted_at()).0,
+        Utc.with_ymd_and_hms(2022, 6, 11, 12, 0, 0).unwrap()
+    );
+    let config = TrashConfig {
+        account_grace_period: 60,
+        ..TrashConfig::default()
+    };
+    assert_eq!(
+        config.account_purge_at(&deleted_at()).0,
+        Utc.with_ymd_and_hms(2022, 5, 28, 12, 1, 0).unwrap()
+    );
+}
+
+#[test]
+fn account_deletion_requires_the_password() {
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(b"correct horse battery staple", &salt)
+        .unwrap()
+        .to_string();
+    let user = User {
+        password_hash,
+        ..common::user()
+    };
+    assert!(user.check_password("correct horse battery staple").is_ok());
+    let err = user.check_password("wrong").unwrap_err();
+    assert_eq!(err.message, "Password is incorrect");
+}
+
+#[test]
+fn deletion_time_is_not_exposed_in_json() {
+    let post = Post {
+        deleted_at: Some(deleted_at()),
+        ..common::post()
+    };
+    let json = serde_json::to_value(&post).unwrap();
+    assert!(json.get("deleted_at").is_none());
+    assert_eq!(json["content"], "hello");
+}
+
+async fn create_post(
+    connection: &mut PgConnection,
+    user: &User,
+    publish_status: PublishStatus,
+    days_ago: i64,
+) -> Post {
+    let post = Post::create(
+        connection,
+        &user.uuid.to_string(),
+        PostType::Text,
+        "hello",
+        &[],
+        ProcessingStatus::Ready,
+        0,
+        &PostSettings {
+            visibility: Visibility::Public,
+            publish_status,
+            publish_at: None,
+        },
+    )
+    .await
+    .unwrap();
+    if days_ago > 0 {
+        let created_at = OurDateTime(Utc::now() - Duration::days(days_ago));
+        Post::backdate(connection, &post.uuid, &created_at)
+            .await
+            .unwrap();
+    }
+    post
+}
+
+#[rocket::async_test]
+async fn pagination_only_counts_posts_in_the_listing() {
+    let pool = common::database().await;
+    let author = common::create_user(&pool).await;
+    let someone_else = common::create_user(&pool).await;
+    let mut connection = pool.acquire().await.unwrap();
+    for _ in 0..10 {
+        create_post(&mut connection, &author, PublishStatus::Published, 0).await;
+    }
+    create_post(&mut connection, &author, PublishStatus::Draft, 1).await;
+    let deleted = create_post(&mut connection, &author, PublishStatus::Published, 1).await;
+    Post::destroy(&mut connection, &deleted.uuid.to_string())
+        .await
+        .unwrap();
+    create_post(&mut connection, &someone_else, PublishStatus::Published, 1).await;
+
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .mount("/api", rocket::routes![api::posts]);
+    let client = Client::tracked(rocket).await.unwrap();
+    let url = format!("/api/users/{}/posts", author.uuid);
+
+    let response = client
+        .get(url.clone())
+        .header(Accept::JSON)
+        .dispatch()
+        .await;
+    let first_page: Value = response.into_json().await.unwrap();
+    assert_eq!(first_page["posts"].as_array().unwrap().len(), 10);
+    assert!(first_page.get("pagination").is_none());
+
+    let pagination = serde_json::json!({
+        "next": first_page["posts"][4]["created_at"],
+        "limit": 5,
+    });
+    let response = client
+        .get(url.clone())
+        .header(Accept::JSON)
+        .header(ContentType::JSON)
+        .body(pagination.to_string())
+        .dispatch()
+        .await;
+    let second_page: Value = response.into_json().await.unwrap();
+    assert_eq!(second_page["posts"].as_array().unwrap().len(), 5);
+    assert!(second_page.get("pagination").is_none());
+
+    create_post(&mut connection, &author, PublishStatus::Published, 2).await;
+    let response = client.get(url).header(Accept::JSON).dispatch().await;
+    let first_page: Value = response.into_json().await.unwrap();
+    assert!(first_page.get("pagination").is_some());
+}
+
+#[rocket::async_test]
+async fn deleted_accounts_hide_their_posts_and_media() {
+    let pool = common::database().await;
+    let storage = common::local_storage();
+    let author = common::create_user(&pool).await;
+    let mut connection = pool.acquire().await.unwrap();
+    let key = format!("blobs/aa/{}.jpg", uuid::Uuid::new_v4());
+    storage
+        .put(&key, b"jpeg".to_vec(), "image/jpeg")
+        .await
+        .unwrap();
+    let post = Post::create(
+        &mut connection,
+        &author.uuid.to_string(),
+        PostType::Photo,
+        &storage.url(&key),
+        &[],
+        ProcessingStatus::Ready,
+        4,
+        &PostSettings {
+            visibility: Visibility::Public,
+            publish_status: PublishStatus::Published,
+            publish_at: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(storage)
+        .mount("/api", rocket::routes![api::posts, api::post])
+        .mount("/assets", rocket::routes![asset::hls, asset::file]);
+    let client = Client::tracked(rocket).await.unwrap();
+    let posts_url = format!("/api/users/{}/posts", author.uuid);
+    let post_url = format!("{}/{}", posts_url, post.uuid);
+    let media_url = format!("/assets/{}", key);
+
+    let listing: Value = client
+        .get(posts_url.clone())
+        .header(Accept::JSON)
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(listing["posts"].as_array().unwrap().len(), 1);
+    let response = client.get(media_url.clone()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    User::destroy(&mut connection, &author.uuid.to_string())
+        .await
+        .unwrap();
+    for url in [posts_url, post_url].iter() {
+        let error: Value = client
+            .get(url.clone())
+            .header(Accept::JSON)
+            .dispatch()
+            .await
+            .into_json()
+            .await
+            .unwrap();
+        assert_eq!(error["status"], 404);
+    }
+    let response = client.get(media_url).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
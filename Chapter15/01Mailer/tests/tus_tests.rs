@@ -0,0 +1,304 @@
+mod common;
+
+use our_application::events::EventHub;
+use our_application::fairings::db::DBConnection;
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::media::photo::PhotoConfig;
+use our_application::media::scan::ScanConfig;
+use our_application::models::post::Post;
+use our_application::models::post_type::PostType;
+use our_application::models::quota::QuotaConfig;
+use our_application::models::resumable_upload::TusConfig;
+use our_application::models::user::User;
+use our_application::routes::tus::{self, content_type_from_metadata, parse_metadata};
+use our_application::storage::Storage;
+use rocket::http::{ContentType, Cookie, Header, Status};
+use rocket::local::asynchronous::{Client, LocalRequest};
+use rocket_db_pools::Database;
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+struct TusApp {
+    client: Client,
+    pool: PgPool,
+    storage: Storage,
+    user: User,
+}
+
+impl TusApp {
+    fn request<'c>(&self, request: LocalRequest<'c>) -> LocalRequest<'c> {
+        request
+            .header(Header::new("Tus-Resumable", "1.0.0"))
+            .private_cookie(Cookie::new(LOGIN_COOKIE_NAME, self.user.uuid.to_string()))
+    }
+
+    async fn create_upload(&self, length: usize) -> String {
+        let response = self
+            .request(self.client.post("/uploads"))
+            .header(Header::new("Upload-Length", length.to_string()))
+            .header(Header::new(
+                "Upload-Metadata",
+                format!("filetype {}", base64::encode("text/plain")),
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Created);
+        String::from(response.headers().get_one("Location").unwrap())
+    }
+
+    async fn patch(&self, location: &str, offset: usize, body: &str) -> (Status, Option<String>) {
+        let response = self
+            .request(self.client.patch(location.to_string()))
+            .header(Header::new("Upload-Offset", offset.to_string()))
+            .header(Header::new(
+                "Content-Type",
+                "application/offset+octet-stream",
+            ))
+            .body(body)
+            .dispatch()
+            .await;
+        let offset = response
+            .headers()
+            .get_one("Upload-Offset")
+            .map(String::from);
+        (response.status(), offset)
+    }
+
+    async fn offset(&self, location: &str) -> (Status, Option<String>) {
+        let response = self
+            .request(self.client.head(location.to_string()))
+            .dispatch()
+            .await;
+        let offset = response
+            .headers()
+            .get_one("Upload-Offset")
+            .map(String::from);
+        (response.status(), offset)
+    }
+}
+
+async fn tus_app() -> TusApp {
+    let pool = common::database().await;
+    let storage = common::local_storage();
+    let user = common::create_user(&pool).await;
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(EventHub::new())
+        .manage(PhotoConfig::default())
+        .manage(QuotaConfig::default())
+        .manage(ScanConfig::default())
+        .manage(TusConfig::default())
+        .manage(storage.clone())
+        .mount(
+            "/",
+            rocket::routes![tus::create, tus::head, tus::patch, tus::terminate],
+        );
+    TusApp {
+        client: Client::tracked(rocket).await.unwrap(),
+        pool,
+        storage,
+        user,
+    }
+}
+
+fn metadata(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(key, value)| (String::from(*key), String::from(*value)))
+        .collect()
+}
+
+#[test]
+fn upload_metadata_is_decoded() {
+    let parsed =
+        parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential").unwrap();
+    assert_eq!(parsed["filename"], "world_domination_plan.pdf");
+    assert_eq!(parsed["is_confidential"], "");
+    assert!(parse_metadata("").unwrap().is_empty());
+    assert!(parse_metadata("filename not-base64!").is_err());
+    assert!(parse_metadata("filename YQ==,filename Yg==").is_err());
+}
+
+#[test]
+fn content_type_comes_from_filetype_or_filename() {
+    assert_eq!(
+        content_type_from_metadata(&metadata(&[("filetype", "video/mp4")])),
+        Some(ContentType::MP4)
+    );
+    assert_eq!(
+        content_type_from_metadata(&metadata(&[("filename", "holiday.JPG")])),
+        Some(ContentType::JPEG)
+    );
+    assert_eq!(
+        content_type_from_metadata(&metadata(&[("filetype", "application/pdf")])),
+        None
+    );
+    assert_eq!(content_type_from_metadata(&metadata(&[])), None);
+}
+
+#[rocket::async_test]
+async fn options_advertise_protocol_capabilities() {
+    let rocket = rocket::build()
+        .manage(TusConfig {
+            max_size: 1024,
+            ..TusConfig::default()
+        })
+        .mount("/", rocket::routes![tus::options]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.options("/uploads").dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Tus-Resumable"), Some("1.0.0"));
+    assert_eq!(headers.get_one("Tus-Version"), Some("1.0.0"));
+    assert_eq!(
+        headers.get_one("Tus-Extension"),
+        Some("creation,termination,expiration")
+    );
+    assert_eq!(headers.get_one("Tus-Max-Size"), Some("1024"));
+}
+
+#[rocket::async_test]
+async fn uploads_are_created_with_an_empty_offset() {
+    let app = tus_app().await;
+    let location = app.create_upload(11).await;
+    assert_eq!(
+        app.offset(&location).await,
+        (Status::Ok, Some(String::from("0")))
+    );
+    let uuid = location.trim_start_matches("/uploads/");
+    assert!(app.storage.resumable_path(uuid).exists());
+
+    let unversioned = app
+        .client
+        .post("/uploads")
+        .header(Header::new("Upload-Length", "11"))
+        .private_cookie(Cookie::new(LOGIN_COOKIE_NAME, app.user.uuid.to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(unversioned.status(), Status::PreconditionFailed);
+    let unsupported = app
+        .request(app.client.post("/uploads"))
+        .header(Header::new("Upload-Length", "11"))
+        .header(Header::new(
+            "Upload-Metadata",
+            format!("filetype {}", base64::encode("application/pdf")),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(unsupported.status(), Status::UnsupportedMediaType);
+    let too_large = app
+        .request(app.client.post("/uploads"))
+        .header(Header::new(
+            "Upload-Length",
+            (TusConfig::default().max_size + 1).to_string(),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(too_large.status(), Status::PayloadTooLarge);
+}
+
+#[rocket::async_test]
+async fn patches_resume_at_the_stored_offset_and_create_a_post() {
+    let app = tus_app().await;
+    let location = app.create_upload(11).await;
+
+    assert_eq!(
+        app.patch(&location, 0, "hello ").await,
+        (Status::NoContent, Some(String::from("6")))
+    );
+    assert_eq!(
+        app.offset(&location).await,
+        (Status::Ok, Some(String::from("6")))
+    );
+    assert_eq!(
+        app.patch(&location, 0, "hello ").await,
+        (Status::Conflict, Some(String::from("6")))
+    );
+
+    let response = app
+        .request(app.client.patch(location.clone()))
+        .header(Header::new("Upload-Offset", "6"))
+        .header(Header::new(
+            "Content-Type",
+            "application/offset+octet-stream",
+        ))
+        .body("world")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(response.headers().get_one("Upload-Offset"), Some("11"));
+    let post_location = response.headers().get_one("Post-Location").unwrap();
+    let post_uuid = post_location.rsplit('/').next().unwrap();
+    assert_eq!(
+        post_location,
+        format!("/users/{}/posts/{}", app.user.uuid, post_uuid)
+    );
+
+    let mut connection = app.pool.acquire().await.unwrap();
+    let post = Post::find(&mut connection, post_uuid).await.unwrap();
+    assert_eq!(post.user_uuid, app.user.uuid);
+    assert_eq!(post.post_type, PostType::Text);
+    assert!(post.content.contains("hello world"));
+    let uuid = location.trim_start_matches("/uploads/");
+    assert!(!app.storage.resumable_path(uuid).exists());
+    assert_eq!(
+        app.patch(&location, 11, "!").await,
+        (Status::Conflict, Some(String::from("11")))
+    );
+}
+
+#[rocket::async_test]
+async fn concurrent_patches_are_locked_out() {
+    let app = tus_app().await;
+    let location = app.create_upload(11).await;
+    let uuid = Uuid::parse_str(location.trim_start_matches("/uploads/")).unwrap();
+
+    let mut transaction = app.pool.begin().await.unwrap();
+    sqlx::query("SELECT * FROM resumable_uploads WHERE uuid = $1 FOR UPDATE")
+        .bind(uuid)
+        .execute(&mut transaction)
+        .await
+        .unwrap();
+    assert_eq!(app.patch(&location, 0, "hello ").await.0, Status::Locked);
+    transaction.rollback().await.unwrap();
+
+    assert_eq!(
+        app.patch(&location, 0, "hello ").await,
+        (Status::NoContent, Some(String::from("6")))
+    );
+}
+
+#[rocket::async_test]
+async fn terminated_uploads_are_removed() {
+    let app = tus_app().await;
+    let location = app.create_upload(11).await;
+    assert_eq!(app.patch(&location, 0, "hello ").await.0, Status::NoContent);
+
+    let response = app
+        .request(app.client.delete(location.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(app.offset(&location).await.0, Status::NotFound);
+    let uuid = location.trim_start_matches("/uploads/");
+    assert!(!app.storage.resumable_path(uuid).exists());
+}
+
+#[rocket::async_test]
+async fn expired_uploads_are_not_found() {
+    let app = tus_app().await;
+    let location = app.create_upload(11).await;
+    let uuid = Uuid::parse_str(location.trim_start_matches("/uploads/")).unwrap();
+    sqlx::query(
+        "UPDATE resumable_uploads SET expires_at = NOW() - INTERVAL '1 second' WHERE uuid = $1",
+    )
+    .bind(uuid)
+    .execute(&app.pool)
+    .await
+    .unwrap();
+
+    assert_eq!(app.offset(&location).await.0, Status::NotFound);
+    assert_eq!(app.patch(&location, 0, "hello ").await.0, Status::NotFound);
+}
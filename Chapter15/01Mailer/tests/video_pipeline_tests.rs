@@ -0,0 +1,197 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{workdir, write_script};
+use our_application::workers::video::{probe, rendition_heights, transcode, VideoConfig};
+use std::fs;
+use std::path::Path;
+
+const PROBE_1080P: &str = r#"{
+    "streams": [
+        {"codec_type": "video", "codec_name": "hevc", "width": 1920, "height": 1080},
+        {"codec_type": "audio", "codec_name": "opus"}
+    ],
+    "format": {"duration": "12.500000"}
+}"#;
+
+const PROBE_SILENT_480P: &str = r#"{
+    "streams": [
+        {"codec_type": "video", "codec_name": "h264", "width": 854, "height": 480}
+    ],
+    "format": {"duration": "3.000000"}
+}"#;
+
+fn fake_config(dir: &Path, probe_output: &str) -> VideoConfig {
+    let ffprobe = dir.join("ffprobe");
+    let ffmpeg = dir.join("ffmpeg");
+    fs::write(dir.join("probe.json"), probe_output).unwrap();
+    write_script(
+        &ffprobe,
+        &format!("cat '{}'\n", dir.join("probe.json").display()),
+    );
+    write_script(
+        &ffmpeg,
+        &format!(
+            "echo \"$@\" >> '{}'\nfor last; do :; done\ntouch \"$last\"\n",
+            dir.join("ffmpeg.log").display()
+        ),
+    );
+    VideoConfig {
+        ffmpeg: ffmpeg.to_string_lossy().to_string(),
+        ffprobe: ffprobe.to_string_lossy().to_string(),
+        hls: false,
+        ..VideoConfig::default()
+    }
+}
+
+fn ffmpeg_calls(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("ffmpeg.log"))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn probe_reads_stream_information() {
+    let dir = workdir();
+    let config = fake_config(&dir, PROBE_1080P);
+    let probed = probe(&config, "input.mov").unwrap();
+    assert_eq!(probed.duration, 12.5);
+    assert_eq!((probed.width, probed.height), (1920, 1080));
+    assert_eq!(probed.video_codec, "hevc");
+    assert_eq!(probed.audio_codec.as_deref(), Some("opus"));
+}
+
+#[test]
+fn probe_enforces_limits() {
+    let dir = workdir();
+    let mut config = fake_config(&dir, PROBE_1080P);
+    config.max_duration = 10.0;
+    let error = probe(&config, "input.mov").unwrap_err();
+    assert!(error.message().contains("limit"));
+    assert!(error.is_permanent());
+
+    config.max_duration = 600.0;
+    config.max_height = 720;
+    let error = probe(&config, "input.mov").unwrap_err();
+    assert!(error.message().contains("1920x1080"));
+    assert!(error.is_permanent());
+}
+
+#[test]
+fn probe_rejects_input_without_video() {
+    let dir = workdir();
+    let config = fake_config(
+        &dir,
+        r#"{"streams": [{"codec_type": "audio"}], "format": {"duration": "1.0"}}"#,
+    );
+    assert!(probe(&config, "input.mp3").unwrap_err().is_permanent());
+}
+
+#[test]
+fn probe_failures_are_permanent_but_missing_tools_are_retried() {
+    let dir = workdir();
+    let mut config = fake_config(&dir, PROBE_1080P);
+    write_script(
+        Path::new(&config.ffprobe),
+        "echo 'Invalid data' >&2\nexit 1\n",
+    );
+    let error = probe(&config, "input.mov").unwrap_err();
+    assert!(error.message().contains("Invalid data"));
+    assert!(error.is_permanent());
+
+    config.ffprobe = dir.join("missing").to_string_lossy().to_string();
+    assert!(!probe(&config, "input.mov").unwrap_err().is_permanent());
+}
+
+#[test]
+fn renditions_never_upscale() {
+    let config = VideoConfig::default();
+    assert_eq!(rendition_heights(&config, 1080), vec![720, 360]);
+    assert_eq!(rendition_heights(&config, 480), vec![360]);
+    assert_eq!(rendition_heights(&config, 241), vec![240]);
+}
+
+#[test]
+fn transcode_produces_h264_renditions_and_poster() {
+    let dir = workdir();
+    let config = fake_config(&dir, PROBE_1080P);
+    let output = transcode(&config, "input.mov", &dir, "abc").unwrap();
+
+    let paths: Vec<&str> = output.variants.iter().map(|v| v.path.as_str()).collect();
+    assert_eq!(paths, vec!["abc_720p.mp4", "abc_360p.mp4"]);
+    assert_eq!(output.variants[0].width, Some(1280));
+    assert_eq!(output.variants[1].width, Some(640));
+    assert!(output.variants.iter().all(|v| v.mime == "video/mp4"));
+    assert_eq!(output.poster.as_deref(), Some("abc.jpg"));
+    assert_eq!(output.duration, 12.5);
+    assert!(dir.join("abc_720p.mp4").exists());
+    assert!(dir.join("abc.jpg").exists());
+
+    let calls = ffmpeg_calls(&dir);
+    assert_eq!(calls.len(), 3);
+    assert!(calls[0].contains("-c:v libx264"));
+    assert!(calls[0].contains("-c:a aac"));
+    assert!(calls[0].contains("-movflags +faststart"));
+    assert!(calls[0].contains("scale=-2:720"));
+    assert!(calls[2].contains("-frames:v 1"));
+}
+
+#[test]
+fn transcode_packages_hls_master_playlist() {
+    let dir = workdir();
+    let mut config = fake_config(&dir, PROBE_1080P);
+    config.hls = true;
+    let output = transcode(&config, "input.mov", &dir, "abc").unwrap();
+
+    let hls = output.variants.last().unwrap();
+    assert_eq!(hls.path, "hls/abc/master.m3u8");
+    assert_eq!(hls.mime, "application/vnd.apple.mpegurl");
+    assert_eq!(output.variants[0].mime, "video/mp4");
+
+    let calls = ffmpeg_calls(&dir);
+    assert_eq!(calls.len(), 5);
+    assert!(calls[3].contains("-c copy -f hls -hls_time 6 -hls_playlist_type vod"));
+    assert!(calls[3].contains("abc_720p.mp4"));
+    assert!(calls[4].ends_with("hls/abc/360p.m3u8"));
+
+    let master = fs::read_to_string(dir.join("hls/abc/master.m3u8")).unwrap();
+    assert!(master.starts_with("#EXTM3U"));
+    assert!(master.contains("RESOLUTION=1280x720\n720p.m3u8"));
+    assert!(master.contains("RESOLUTION=640x360\n360p.m3u8"));
+}
+
+#[test]
+fn transcode_adds_webm_without_audio_when_silent() {
+    let dir = workdir();
+    let mut config = fake_config(&dir, PROBE_SILENT_480P);
+    config.webm = true;
+    let output = transcode(&config, "input.mp4", &dir, "silent").unwrap();
+
+    let mimes: Vec<&str> = output.variants.iter().map(|v| v.mime.as_str()).collect();
+    assert_eq!(mimes, vec!["video/mp4", "video/webm"]);
+    let calls = ffmpeg_calls(&dir);
+    assert!(calls[1].contains("-c:v libvpx-vp9"));
+    assert!(calls.iter().take(2).all(|c| c.contains("-an")));
+    assert!(calls[2].contains("-ss 1.000"));
+}
+
+#[test]
+fn transcode_cleans_up_when_ffmpeg_fails() {
+    let dir = workdir();
+    let mut config = fake_config(&dir, PROBE_1080P);
+    config.hls = true;
+    write_script(
+        Path::new(&config.ffmpeg),
+        "for last; do :; done\ntouch \"$last\"\ncase \"$last\" in *360p.m3u8) echo broken >&2; exit 1;; esac\n",
+    );
+    let error = transcode(&config, "input.mov", &dir, "bad").unwrap_err();
+    assert!(error.message().contains("broken"));
+    assert!(!error.is_permanent());
+    assert!(!dir.join("hls/bad").exists());
+    assert!(!dir.join("bad_720p.mp4").exists());
+    assert!(!dir.join("bad_360p.mp4").exists());
+    assert!(!dir.join("bad.jpg").exists());
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use our_application::events::Event;
+use our_application::fairings::db::DBConnection;
+use our_application::guards::auth::LOGIN_COOKIE_NAME;
+use our_application::models::post::{Post, PostSettings};
+use our_application::models::post_type::PostType;
+use our_application::models::processing_status::ProcessingStatus;
+use our_application::models::publish_status::PublishStatus;
+use our_application::models::visibility::Visibility;
+use our_application::routes::asset;
+use rocket::http::{Cookie, Status};
+use rocket::local::asynchronous::Client;
+use rocket::serde::json::serde_json;
+use rocket_db_pools::Database;
+use uuid::Uuid;
+
+fn post(visibility: Visibility) -> Post {
+    Post {
+        visibility,
+        ..common::post()
+    }
+}
+
+fn new_post_event(author: Uuid, visibility: Visibility) -> Event {
+    Event::NewPost {
+        user_uuid: author,
+        post_uuid: Uuid::new_v4(),
+        post_html: String::from("<p>hello</p>"),
+        visibility,
+    }
+}
+
+#[test]
+fn owners_always_see_their_posts() {
+    let stranger = Uuid::new_v4();
+    for visibility in [
+        Visibility::Public,
+        Visibility::Unlisted,
+        Visibility::Followers,
+        Visibility::Private,
+    ]
+    .iter()
+    {
+        let post = post(*visibility);
+        assert!(post.can_view(Some(&post.user_uuid)));
+        let visible = *visibility == Visibility::Public || *visibility == Visibility::Unlisted;
+        assert_eq!(post.can_view(Some(&stranger)), visible);
+        assert_eq!(post.can_view(None), visible);
+    }
+}
+
+#[test]
+fn only_public_posts_are_listed() {
+    assert!(Visibility::Public.is_listed());
+    assert!(!Visibility::Unlisted.is_listed());
+    assert!(!Visibility::Unlisted.is_restricted());
+    assert!(Visibility::Followers.is_restricted());
+    assert!(Visibility::Private.is_restricted());
+}
+
+#[test]
+fn parses_upload_metadata_values() {
+    assert_eq!(Visibility::parse("public"), Some(Visibility::Public));
+    assert_eq!(Visibility::parse(" Unlisted "), Some(Visibility::Unlisted));
+    assert_eq!(Visibility::parse("followers"), Some(Visibility::Followers));
+    assert_eq!(Visibility::parse("PRIVATE"), Some(Visibility::Private));
+    assert_eq!(Visibility::parse("friends"), None);
+}
+
+#[test]
+fn post_json_includes_visibility() {
+    let post = post(Visibility::Unlisted);
+    let json = serde_json::to_value(&post).unwrap();
+    assert_eq!(json["visibility"], "Unlisted");
+    assert_eq!(post.to_show_post().visibility, Visibility::Unlisted);
+}
+
+#[test]
+fn feeds_only_carry_public_posts() {
+    let author = Uuid::new_v4();
+    let watcher = Uuid::new_v4();
+    let watched = vec![author];
+    assert!(new_post_event(author, Visibility::Public).is_for(&watcher, &watched));
+    for visibility in [
+        Visibility::Unlisted,
+        Visibility::Followers,
+        Visibility::Private,
+    ]
+    .iter()
+    {
+        let event = new_post_event(author, *visibility);
+        assert!(!event.is_for(&watcher, &watched));
+        assert!(event.is_for(&author, &[]));
+    }
+}
+
+#[rocket::async_test]
+async fn managed_media_is_only_served_for_visible_posts() {
+    let pool = common::database().await;
+    let storage = common::local_storage();
+    let owner = common::create_user(&pool).await;
+    let mut connection = pool.acquire().await.unwrap();
+    let mut keys = vec![];
+    for visibility in [Visibility::Public, Visibility::Private].iter() {
+        let key = format!("blobs/aa/{}.jpg", Uuid::new_v4());
+        storage
+            .put(&key, b"jpeg".to_vec(), "image/jpeg")
+            .await
+            .unwrap();
+        Post::create(
+            &mut connection,
+            &owner.uuid.to_string(),
+            PostType::Photo,
+            &storage.url(&key),
+            &[],
+            ProcessingStatus::Ready,
+            4,
+            &PostSettings {
+                visibility: *visibility,
+                publish_status: PublishStatus::Published,
+                publish_at: None,
+            },
+        )
+        .await
+        .unwrap();
+        keys.push(key);
+    }
+    let orphan = format!("blobs/aa/{}.jpg", Uuid::new_v4());
+    storage
+        .put(&orphan, b"jpeg".to_vec(), "image/jpeg")
+        .await
+        .unwrap();
+
+    let rocket = rocket::custom(rocket::Config::figment())
+        .attach(DBConnection::init())
+        .manage(storage)
+        .mount("/assets", rocket::routes![asset::hls, asset::file]);
+    let client = Client::tracked(rocket).await.unwrap();
+    let get = |key: &str| client.get(format!("/assets/{}", key));
+    let as_owner = Cookie::new(LOGIN_COOKIE_NAME, owner.uuid.to_string());
+
+    assert_eq!(get(&keys[0]).dispatch().await.status(), Status::Ok);
+    assert_eq!(get(&keys[1]).dispatch().await.status(), Status::NotFound);
+    let response = get(&keys[1])
+        .private_cookie(as_owner.clone())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("private, no-cache")
+    );
+    let response = get(&orphan).private_cookie(as_owner).dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}